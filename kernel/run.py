@@ -82,6 +82,8 @@ parser.add_argument('-m', '--mods', nargs='+', default=['init'],
                     help='User-space modules to be included in build & deployment', required=False)
 parser.add_argument("--cmd", type=str,
                     help="Command line arguments passed to the kernel.")
+parser.add_argument("--initrd", type=str,
+                    help="cpio archive (newc format) that is unpacked into the file-system at boot.", required=False, default=None)
 parser.add_argument("--machine",
                     help='Which machine to run on (defaults to qemu)', required=False, default='qemu')
 
@@ -250,12 +252,17 @@ def deploy(args):
 
     # Write kernel cmd-line file in ESP dir
     with open(esp_path / 'cmdline.in', 'w') as cmdfile:
-        if args.cmd:
-            cmdfile.write('./kernel {}'.format(args.cmd))
-        else:
-            cmdfile.write('./kernel')
+        cmd = './kernel {}'.format(args.cmd) if args.cmd else './kernel'
+        if args.initrd:
+            cmd += ' initrd={}'.format(os.path.basename(args.initrd))
+        cmdfile.write(cmd)
 
     deployed = []
+    # Deploy the initial ramdisk
+    if args.initrd:
+        shutil.copy2(args.initrd, esp_path)
+        deployed.append(os.path.basename(args.initrd))
+
     # Deploy user-modules
     for module in args.mods:
         if not (user_build_path / module).is_file():
@@ -31,7 +31,7 @@ static KERNEL_ARGS: KernelArgs = KernelArgs::new();
 static mut KCB: Kcb<ArchKcb> = {
     Kcb::new(
         &[],
-        BootloaderArguments::new("info", "init", "init", "init"),
+        BootloaderArguments::new("info", "init", "init", "init", ""),
         TCacheSp::new(0),
         ArchKcb::new(&KERNEL_ARGS),
         0,
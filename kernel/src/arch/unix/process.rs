@@ -21,10 +21,10 @@ use crate::kcb::{self, ArchSpecificKcb};
 use crate::memory::detmem::DA;
 use crate::memory::vspace::AddressSpace;
 use crate::memory::vspace::MapAction;
-use crate::memory::{Frame, VAddr, LARGE_PAGE_SIZE};
+use crate::memory::{Frame, VAddr};
 use crate::nrproc::NrProcess;
 use crate::process::{
-    Eid, Executor, Pid, Process, ResumeHandle, MAX_FRAMES_PER_PROCESS, MAX_PROCESSES,
+    Eid, Executor, Pid, Process, ProcessArgs, ResumeHandle, MAX_FRAMES_PER_PROCESS, MAX_PROCESSES,
 };
 
 use super::debug;
@@ -43,9 +43,10 @@ lazy_static! {
             numa_cache.push(process_replicas)
         }
 
+        let log_size = kcb::get_kcb().cmdline.process_log_size;
         for pid in 0..MAX_PROCESSES {
                 let log = Arc::try_new(Log::<<NrProcess<UnixProcess> as Dispatch>::WriteOperation>::new(
-                    LARGE_PAGE_SIZE,
+                    log_size,
                 )).expect("Can't initialize processes, out of memory.");
 
             let da = DA::new().expect("Can't initialize process deterministic memory allocator");
@@ -233,6 +234,7 @@ impl Process for UnixProcess {
         _pid: Pid,
         _module: &Module,
         _writable_sections: Vec<Frame>,
+        _args: ProcessArgs,
     ) -> Result<(), KError> {
         self.vspace.map_frame(
             VAddr::from(0x2000_0000),
@@ -281,6 +283,14 @@ impl Process for UnixProcess {
         &self.pinfo
     }
 
+    fn subscribe_event(&mut self, _vector: u64) -> Result<(), KError> {
+        Err(KError::NotSupported)
+    }
+
+    fn is_subscribed(&self, _vector: u64) -> bool {
+        false
+    }
+
     fn add_frame(&mut self, _frame: Frame) -> Result<FrameId, KError> {
         Err(KError::InvalidFrameId)
     }
@@ -292,10 +302,14 @@ impl Process for UnixProcess {
     fn deallocate_frame(&mut self, _fid: FrameId) -> Result<Frame, KError> {
         Err(KError::InvalidFrameId)
     }
+
+    fn destroy(&mut self) -> Result<Vec<Frame>, KError> {
+        Ok(Vec::new())
+    }
 }
 
 pub fn spawn(binary: &'static str) -> Result<Pid, KError> {
-    let pid = crate::process::make_process::<UnixProcess>(binary)?;
+    let pid = crate::process::make_process::<UnixProcess>(binary, ProcessArgs::default(), None)?;
     crate::process::allocate_dispatchers::<UnixProcess>(pid)?;
     Ok(0)
 }
@@ -37,6 +37,7 @@ use core::fmt;
 
 use alloc::boxed::Box;
 
+use x86::bits64::paging::PTFlags;
 use x86::bits64::segmentation::Descriptor64;
 use x86::irq::*;
 use x86::segmentation::{
@@ -46,7 +47,7 @@ use x86::{dtables, Ring};
 
 use apic::ApicDriver;
 use klogger::{sprint, sprintln};
-use log::{info, trace, warn};
+use log::{error, info, trace, warn};
 
 use crate::kcb::ArchSpecificKcb;
 use crate::memory::vspace::MapAction;
@@ -298,10 +299,66 @@ unsafe fn unhandled_irq(a: &ExceptionArguments) {
     debug::shutdown(ExitReason::UnhandledInterrupt);
 }
 
+/// Forwards an exception in user-space to the upcall handler of the process,
+/// if the process subscribed to it (`fault_address` is reported for
+/// page-faults).
+///
+/// Doesn't return in that case; returns if the process didn't subscribe or
+/// is in a critical section (it can't take an upcall then).
+unsafe fn try_upcall_exception(a: &ExceptionArguments, fault_address: VAddr) {
+    let kcb = get_kcb();
+    let pid = match kcb.current_pid() {
+        Ok(pid) => pid,
+        Err(_) => return,
+    };
+    if nrproc::NrProcess::<Ring3Process>::is_subscribed(pid, a.vector) != Ok(true) {
+        return;
+    }
+
+    let resumer = {
+        let mut plock = kcb.arch.current_executor();
+        let p = plock.as_mut().unwrap();
+
+        if p.vcpu().upcalls_disabled(VAddr::from(a.rip)) {
+            warn!(
+                "Exception {} while upcalls are disabled in process {}",
+                a.vector, pid
+            );
+            return;
+        }
+        p.vcpu().disable_upcalls();
+
+        // Copy CURRENT_SAVE_AREA to process enabled save area
+        // then resume in the upcall handler
+        kcb.arch.save_area.as_ref().map(|sa| {
+            p.vcpu().enabled_state = **sa;
+        });
+        p.vcpu().fault_address = fault_address;
+
+        p.upcall(a.vector, a.exception)
+    };
+
+    resumer.resume()
+}
+
+/// Terminates the process that caused an exception in user-space.
+///
+/// The process exits with `reason` as exit code (and if it was the last
+/// process, the kernel shuts down with it). The core goes back to the
+/// scheduler afterwards.
+unsafe fn kill_current_process(reason: ExitReason) -> ! {
+    let kcb = get_kcb();
+    let pid = kcb
+        .current_pid()
+        .expect("An exception from user-space must have a process");
+    super::process::exit_current(pid, reason as u64, reason)
+}
+
 /// Handler for unexpected page-faults.
 ///
-/// TODO: Right now we terminate kernel.
-/// Should abort process and resume.
+/// Page-faults in user-space that don't resolve to a mapping (or to one
+/// without the rights for the access) terminate the process, page-faults in
+/// the kernel terminate the kernel.
 unsafe fn pf_handler(a: &ExceptionArguments) {
     use crate::arch::kcb;
 
@@ -317,8 +374,8 @@ unsafe fn pf_handler(a: &ExceptionArguments) {
             .current_pid()
             .expect("A pid must be set in this if branch (US bit set in page-fault error)");
 
-        match nrproc::NrProcess::<Ring3Process>::resolve(pid, faulting_address_va) {
-            Ok((paddr, rights)) => {
+        match nrproc::NrProcess::<Ring3Process>::resolve_rights(pid, faulting_address_va) {
+            Ok((paddr, rights)) if access_allowed(err, rights) => {
                 // TODO(harden): We probably want to warn/abort if we get many
                 // "spurious" pfaults for the same addr in quick succession: one
                 // bug I encountered is when I accidentially made executor
@@ -327,14 +384,25 @@ unsafe fn pf_handler(a: &ExceptionArguments) {
                 // here until the other replica (by chance) advances and this
                 // code doesn't really do anything...
                 trace!(
-                    "Spurious page-fault, after resolve page-table is up to date {} {} -> {:#x} {:#b} on {}",
+                    "Spurious page-fault, after resolve page-table is up to date {} {} -> {:#x} {:?} on {}",
                     pid, faulting_address_va, paddr, rights, kcb.arch.hwthread_id()
                 );
                 let r = kcb_iret_handle(kcb);
                 r.resume()
             }
-            Err(_) => {
-                // unresolved page-fault, proceed with abort below
+            // Not mapped, or mapped without the rights for the access (e.g.,
+            // a write to a read-only mapping)
+            _ => {
+                try_upcall_exception(a, faulting_address_va);
+                error!(
+                    "Page fault in process {} on core {}: {} at {:#x} (instruction pointer {:#x}), killing it",
+                    pid,
+                    kcb.arch.id(),
+                    err,
+                    faulting_address,
+                    a.rip
+                );
+                kill_current_process(ExitReason::PageFault)
             }
         }
     }
@@ -393,16 +461,27 @@ unsafe fn pf_handler(a: &ExceptionArguments) {
     debug::shutdown(ExitReason::PageFault);
 }
 
+/// Check if a user-space access that caused the page-fault `err` is allowed
+/// by the `rights` of the mapping.
+fn access_allowed(err: PageFaultError, rights: MapAction) -> bool {
+    let rights = rights.to_pt_rights();
+    rights.contains(PTFlags::US)
+        && (!err.contains(PageFaultError::WR) || rights.contains(PTFlags::RW))
+        && (!err.contains(PageFaultError::ID) || !rights.contains(PTFlags::XD))
+}
+
 /// Handler for a debug exception.
 ///
 /// The default behavior right now is just to print a warning and resume
-/// execution in user-space.
+/// execution in user-space (breakpoints from a process with an upcall
+/// handler are upcalled before in `handle_generic_exception`).
 unsafe fn dbg_handler(a: &ExceptionArguments) {
     let desc = &EXCEPTIONS[a.vector as usize];
     warn!("Got debug interrupt {}", desc.source);
 
     let kcb = get_kcb();
     assert!(kcb.arch.has_executor(), "Not from user-space?");
+
     let r = Ring3Resumer::new_restore(kcb.arch.get_save_area_ptr());
     r.resume()
 }
@@ -460,10 +539,24 @@ unsafe fn timer_handler(a: &ExceptionArguments) {
 
 /// Handler for a general protection exception.
 ///
-/// TODO: Right now we terminate kernel.
-/// Should abort process and resume.
+/// A general protection fault in user-space terminates the process, in the
+/// kernel it terminates the kernel.
 unsafe fn gp_handler(a: &ExceptionArguments) {
     let desc = &EXCEPTIONS[a.vector as usize];
+
+    if (a.cs & 0b11) == Ring::Ring3 as u64 {
+        try_upcall_exception(a, VAddr::zero());
+        let kcb = get_kcb();
+        error!(
+            "General protection fault in process {:?} on core {}: error {:#x} (instruction pointer {:#x}), killing it",
+            kcb.current_pid(),
+            kcb.arch.id(),
+            a.exception,
+            a.rip
+        );
+        kill_current_process(ExitReason::GeneralProtectionFault)
+    }
+
     sprint!("\n[IRQ] GENERAL PROTECTION FAULT: ");
     sprintln!("From {}", desc.source);
 
@@ -629,6 +722,21 @@ pub extern "C" fn handle_generic_exception(a: ExceptionArguments) -> ! {
             pf_handler(&a);
         } else if a.vector == 0x3 {
             dbg_handler(&a);
+        } else if a.vector == DIVIDE_ERROR_VECTOR.into() || a.vector == DEBUG_VECTOR.into() {
+            // Only forwarded to processes that subscribed to them, otherwise
+            // the process is terminated
+            if (a.cs & 0b11) == Ring::Ring3 as u64 {
+                try_upcall_exception(&a, VAddr::zero());
+                let kcb = get_kcb();
+                error!(
+                    "Exception {} in process {:?} on core {} (instruction pointer {:#x}), killing it",
+                    a.vector,
+                    kcb.current_pid(),
+                    kcb.arch.id(),
+                    a.rip
+                );
+                kill_current_process(ExitReason::UnhandledInterrupt)
+            }
         } else if a.vector == TLB_WORK_PENDING.into() {
             let kcb = get_kcb();
             trace!("got an interrupt {:?}", kcb.arch.id());
@@ -246,6 +246,11 @@ impl Arch86Kcb {
         self.current_executor.replace(new_executor)
     }
 
+    /// Removes the current process from the core. Returns the old process.
+    pub fn take_current_executor(&mut self) -> Option<Box<Ring3Executor>> {
+        self.current_executor.take()
+    }
+
     pub fn has_executor(&self) -> bool {
         self.current_executor.is_some()
     }
@@ -27,7 +27,8 @@ use alloc::vec::Vec;
 use core::mem::transmute;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use crate::cnrfs::{MlnrKernelNode, Modify};
+use crate::cnrfs::{self, MlnrKernelNode, Modify};
+use crate::error::KError;
 use crate::kcb::{BootloaderArguments, Kcb};
 use crate::memory::{mcache, Frame, GlobalMemory, BASE_PAGE_SIZE};
 use crate::nr::{KernelNode, Op};
@@ -41,7 +42,7 @@ use cnr::{Log as MlnrLog, Replica as MlnrReplica};
 use driverkit::DriverControl;
 use fallible_collections::{FallibleVecGlobal, TryClone};
 use klogger::sprint;
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use node_replication::{Log, Replica};
 use x86::bits64::paging::{PAddr, VAddr, PML4};
 use x86::{controlregs, cpuid};
@@ -342,6 +343,8 @@ fn boot_app_cores(
         kcb.set_allocation_affinity(0).expect("Can't set affinity");
     }
 
+    cnrfs::set_replication(fs_logs.len(), fs_replicas.len());
+
     let global_memory = kcb
         .physical_memory
         .gmanager
@@ -732,11 +735,25 @@ fn _start(argc: isize, _argv: *const *const u8) -> isize {
         .map(|node| node.threads().count())
         .unwrap_or(1);
 
+    // Every log is advanced by a core of the node with the same index (see
+    // `func`), so there can't be more logs than cores on a node.
+    let num_fs_logs = match cmdline.fs_logs {
+        Some(nlogs) if nlogs == 0 || nlogs > cores_per_node => {
+            warn!(
+                "Can't use {} file-system logs with {} cores per node, using {}",
+                nlogs, cores_per_node, cores_per_node
+            );
+            cores_per_node
+        }
+        Some(nlogs) => nlogs,
+        None => cores_per_node,
+    };
+
     let mut fs_logs: Vec<Arc<MlnrLog<Modify>>> =
-        Vec::try_with_capacity(cores_per_node).expect("Not enough memory to initialize system");
-    for i in 0..cores_per_node {
-        // Log idx in range [1, cores_per_node+1]
-        let mut log = Arc::try_new(MlnrLog::<Modify>::new(LARGE_PAGE_SIZE, i + 1))
+        Vec::try_with_capacity(num_fs_logs).expect("Not enough memory to initialize system");
+    for i in 0..num_fs_logs {
+        // Log idx in range [1, num_fs_logs+1]
+        let mut log = Arc::try_new(MlnrLog::<Modify>::new(cmdline.fs_log_size, i + 1))
             .expect("Not enough memory to initialize system");
 
         // TODO(api): `func` should be passed as part of constructor:
@@ -753,6 +770,7 @@ fn _start(argc: isize, _argv: *const *const u8) -> isize {
             .expect("Not enough memory to initialize system"),
     );
     let local_ridx = fs_replica.register().unwrap();
+    cnrfs::set_replication(fs_logs.len(), 1);
     {
         let kcb = kcb::get_kcb();
         kcb.arch.setup_cnr(fs_replica.clone(), local_ridx);
@@ -777,6 +795,14 @@ fn _start(argc: isize, _argv: *const *const u8) -> isize {
         fs_replica,
     );
 
+    // Populate the file-system before init starts
+    if !cmdline.initrd.is_empty() {
+        match unpack_initrd(cmdline.initrd) {
+            Ok(entries) => info!("Unpacked {} entries from {}", entries, cmdline.initrd),
+            Err(e) => error!("Unable to unpack initrd {}: {}", cmdline.initrd, e),
+        }
+    }
+
     // Done with initialization, now we go in
     // the arch-independent part:
     let _r = xmain();
@@ -785,6 +811,22 @@ fn _start(argc: isize, _argv: *const *const u8) -> isize {
     debug::shutdown(ExitReason::ReturnFromMain);
 }
 
+/// Unpack the cpio archive in module `name` into the replicated file-system.
+fn unpack_initrd(name: &'static str) -> Result<usize, KError> {
+    let kcb = kcb::get_kcb();
+    let module = kcb
+        .arch
+        .kernel_args()
+        .modules
+        .iter()
+        .find(|module| module.name() == name)
+        .ok_or(KError::BinaryNotFound { binary: name })?;
+
+    // Safe: Modules stay mapped in the kernel address space.
+    let archive = unsafe { module.as_slice() };
+    MlnrKernelNode::unpack_initrd(archive)
+}
+
 /// For cores that advances the replica eagerly. This avoids additional IPI costs.
 pub fn advance_fs_replica() {
     tlb::eager_advance_fs_replica();
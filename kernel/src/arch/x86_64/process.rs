@@ -6,21 +6,23 @@ use alloc::collections::TryReserveError;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cmp::PartialEq;
+use core::convert::TryFrom;
 use core::ops::{Deref, DerefMut};
 use core::{fmt, ptr};
 
 use arrayvec::ArrayVec;
 use fallible_collections::try_vec;
-use fallible_collections::FallibleVec;
+use fallible_collections::{FallibleVec, FallibleVecGlobal};
 use kpi::process::{FrameId, ELF_OFFSET, EXECUTOR_OFFSET};
 use lazy_static::lazy_static;
-use log::{debug, info, trace, warn};
+use log::{debug, error, info, trace, warn};
 use node_replication::{Dispatch, Log, Replica};
 use x86::bits64::paging::*;
 use x86::bits64::rflags;
 use x86::controlregs;
 
 use crate::error::KError;
+use crate::fallible_string::TryString;
 use crate::fs::{Fd, MAX_FILES_PER_PROCESS};
 use crate::kcb::ArchSpecificKcb;
 use crate::kcb::{self, Kcb};
@@ -29,7 +31,7 @@ use crate::memory::vspace::{AddressSpace, MapAction};
 use crate::memory::{paddr_to_kernel_vaddr, Frame, KernelAllocator, PAddr, VAddr};
 use crate::nrproc::NrProcess;
 use crate::process::{
-    Eid, Executor, Pid, Process, ResumeHandle, MAX_FRAMES_PER_PROCESS, MAX_PROCESSES,
+    Eid, Executor, Pid, Process, ProcessArgs, ResumeHandle, MAX_FRAMES_PER_PROCESS, MAX_PROCESSES,
     MAX_WRITEABLE_SECTIONS_PER_PROCESS,
 };
 use crate::round_up;
@@ -53,9 +55,10 @@ lazy_static! {
             numa_cache.push(process_replicas)
         }
 
+        let log_size = kcb::get_kcb().cmdline.process_log_size;
         for pid in 0..MAX_PROCESSES {
                 let log = Arc::try_new(Log::<<NrProcess<Ring3Process> as Dispatch>::WriteOperation>::new(
-                    LARGE_PAGE_SIZE,
+                    log_size,
                 )).expect("Can't initialize processes, out of memory.");
 
             let da = DA::new().expect("Can't initialize process deterministic memory allocator");
@@ -727,6 +730,11 @@ pub struct Ring3Process {
     pub offset: VAddr,
     /// Process info struct (can be retrieved by user-space)
     pub pinfo: kpi::process::ProcessInfo,
+    /// Arguments the process was started with (`pinfo` points to them).
+    pub args: ProcessArgs,
+    /// Exception vectors that are forwarded to the process as upcalls
+    /// (bit `n` is set for vector `n`).
+    pub subscribed_events: u64,
     /// The entry point of the ELF file (set during elfloading).
     pub entry_point: VAddr,
     /// Executor cache (holds a per-region cache of executors)
@@ -739,6 +747,10 @@ pub struct Ring3Process {
     pub frames: ArrayVec<Option<Frame>, MAX_FRAMES_PER_PROCESS>,
     /// Frames of the writeable ELF data section (shared across all replicated Process structs)
     pub writeable_sections: ArrayVec<Frame, MAX_WRITEABLE_SECTIONS_PER_PROCESS>,
+    /// Frames of the read-only ELF sections (every replica has its own copy)
+    pub read_only_frames: Vec<Frame>,
+    /// Frames that hold the executors (shared across all replicated Process structs)
+    pub executor_frames: Vec<Frame>,
     /// Section in ELF where last read-only header is
     ///
     /// (TODO(robustness): assumes that all read-only segments come before
@@ -769,8 +781,12 @@ impl Ring3Process {
             executor_offset: VAddr::from(EXECUTOR_OFFSET),
             fds,
             pinfo: Default::default(),
+            args: Default::default(),
+            subscribed_events: 0,
             frames,
             writeable_sections: ArrayVec::new(),
+            read_only_frames: Vec::new(),
+            executor_frames: Vec::new(),
             read_only_offset: VAddr::zero(),
         })
     }
@@ -866,10 +882,14 @@ impl elfloader::ElfLoader for Ring3Process {
                         map_action == MapAction::ReadUser
                             || map_action == MapAction::ReadExecuteUser
                     );
-                    let mut pmanager = kcb.mem_manager();
-                    pmanager
+                    let frame = kcb
+                        .mem_manager()
                         .allocate_large_page()
-                        .expect("We refilled so allocation should work.")
+                        .expect("We refilled so allocation should work.");
+                    self.read_only_frames
+                        .try_push(frame)
+                        .map_err(|_e| "Can't keep track of read-only ELF frame")?;
+                    frame
                 };
 
                 trace!(
@@ -1038,8 +1058,16 @@ impl Process for Ring3Process {
         pid: Pid,
         module: &Module,
         writeable_sections: Vec<Frame>,
+        args: ProcessArgs,
     ) -> Result<(), KError> {
         self.pid = pid;
+        self.args = args;
+        // Safe: The strings live as long as the process, `destroy` resets
+        // `pinfo` before it frees them.
+        unsafe {
+            self.pinfo.cmdline = &*(self.args.cmdline.as_str() as *const str);
+            self.pinfo.app_cmdline = &*(self.args.app_cmdline.as_str() as *const str);
+        }
         // TODO(error-handling): properly unwind on error
         self.writeable_sections.clear();
         for sec in writeable_sections {
@@ -1111,6 +1139,7 @@ impl Process for Ring3Process {
         let executors_to_create = memory.size() / executor_space_requirement;
 
         KernelAllocator::try_refill_tcache(20, 0).expect("Refill didn't work");
+        self.executor_frames.try_push(memory)?;
         {
             self.vspace
                 .map_frame(self.executor_offset, memory, MapAction::ReadWriteUser)
@@ -1198,6 +1227,22 @@ impl Process for Ring3Process {
         &self.pinfo
     }
 
+    fn subscribe_event(&mut self, vector: u64) -> Result<(), KError> {
+        use kpi::upcall::*;
+
+        match vector {
+            DIVIDE_ERROR | DEBUG | GENERAL_PROTECTION_FAULT | PAGE_FAULT => {
+                self.subscribed_events |= 1 << vector;
+                Ok(())
+            }
+            _ => Err(KError::InvalidSyscallArgument1 { a: vector }),
+        }
+    }
+
+    fn is_subscribed(&self, vector: u64) -> bool {
+        vector < 64 && self.subscribed_events & (1 << vector) != 0
+    }
+
     fn add_frame(&mut self, frame: Frame) -> Result<FrameId, KError> {
         if let Some(fid) = self.frames.iter().position(|fid| fid.is_none()) {
             self.frames[fid] = Some(frame);
@@ -1225,6 +1270,44 @@ impl Process for Ring3Process {
             _ => Err(KError::InvalidFileDescriptor),
         }
     }
+
+    fn destroy(&mut self) -> Result<Vec<Frame>, KError> {
+        let mut shared_frames = Vec::try_with_capacity(
+            self.writeable_sections.len() + self.executor_frames.len() + self.frames.len(),
+        )?;
+        let da = self
+            .vspace
+            .page_table
+            .da
+            .clone()
+            .expect("Process page-tables always have a DA");
+        // Drops the old page-tables, but not the frames mapped in them
+        self.vspace = VSpace::new(da)?;
+
+        shared_frames.extend(self.writeable_sections.drain(..));
+        shared_frames.extend(self.executor_frames.drain(..));
+        shared_frames.extend(self.frames.iter_mut().filter_map(|frame| frame.take()));
+
+        for executors in self.executor_cache.iter_mut() {
+            *executors = None;
+        }
+        for fd in self.fds.iter_mut() {
+            *fd = None;
+        }
+        self.current_eid = 0;
+        self.offset = VAddr::from(ELF_OFFSET);
+        self.entry_point = VAddr::from(0usize);
+        self.executor_offset = VAddr::from(EXECUTOR_OFFSET);
+        self.pinfo = Default::default();
+        self.args = Default::default();
+        self.subscribed_events = 0;
+        self.read_only_offset = VAddr::zero();
+
+        let read_only_frames = core::mem::take(&mut self.read_only_frames);
+        KernelAllocator::release_frames(&read_only_frames)?;
+
+        Ok(shared_frames)
+    }
 }
 
 /// Spawns a new process
@@ -1236,23 +1319,114 @@ impl Process for Ring3Process {
 /// - Then we allocate a bunch of memory on all NUMA nodes to create enough dispatchers
 ///   so we can run on all cores
 /// - Finally we allocate a dispatcher to the current core (0) and start running the process
+///
+/// The process gets the init arguments from the kernel command line.
 #[cfg(target_os = "none")]
 pub fn spawn(binary: &'static str) -> Result<Pid, KError> {
+    let kcb = kcb::get_kcb();
+    let args = ProcessArgs {
+        cmdline: TryString::try_from(kcb.cmdline.init_args)?.into(),
+        app_cmdline: TryString::try_from(kcb.cmdline.app_args)?.into(),
+    };
+
+    // Set current thread to run executor from our process (on the current core)
+    spawn_on(binary, args, None, kcb.arch.node_id, kcb.arch.id)
+}
+
+/// Create a new process from the module `binary` and run it on core `gtid`
+/// (which is in NUMA node `affinity`).
+///
+/// `parent` is the process that asked for the new process, it can wait for
+/// the new process to exit.
+///
+/// If the core can't be allocated to the new process, the process is torn
+/// down again.
+#[cfg(target_os = "none")]
+pub fn spawn_on(
+    binary: &'static str,
+    args: ProcessArgs,
+    parent: Option<Pid>,
+    affinity: atopology::NodeId,
+    gtid: atopology::GlobalThreadId,
+) -> Result<Pid, KError> {
     use crate::nr;
     use crate::process::{allocate_dispatchers, make_process};
 
-    let pid = make_process::<Ring3Process>(binary)?;
+    let pid = make_process::<Ring3Process>(binary, args, parent)?;
     allocate_dispatchers::<Ring3Process>(pid)?;
 
-    // Set current thread to run executor from our process (on the current core)
-    let kcb = kcb::get_kcb();
-
-    let _gtid = nr::KernelNode::allocate_core_to_process(
+    let allocated = nr::KernelNode::allocate_core_to_process(
         pid,
         INVALID_EXECUTOR_START, // This VAddr is irrelevant as it is overriden later
-        Some(kcb.arch.node_id),
-        Some(kcb.arch.id),
-    )?;
+        Some(affinity),
+        Some(gtid),
+    );
+    if let Err(e) = allocated {
+        exit(pid, 0)?;
+        // The process never ran, nobody should wait for it
+        if parent.is_some() {
+            nr::KernelNode::free_pid(pid)?;
+        }
+        return Err(e);
+    }
 
     Ok(pid)
 }
+
+/// Tears down a process
+///
+/// - First we take all cores away from the process and stop running it on them
+///   (including the current core)
+/// - Then we close the files of the process and release its address-space,
+///   executors and memory on all replicas
+/// - Finally we record the `exit_code`, the pid can be reused once the parent
+///   waited for the process (or right away if it has no parent)
+#[cfg(target_os = "none")]
+pub fn exit(pid: Pid, exit_code: u64) -> Result<(), KError> {
+    let cores = crate::nr::KernelNode::release_cores(pid)?;
+    teardown(pid, &cores, exit_code)
+}
+
+/// Tears down a process after its `cores` were released (see `exit`).
+#[cfg(target_os = "none")]
+fn teardown(pid: Pid, cores: &[atopology::GlobalThreadId], exit_code: u64) -> Result<(), KError> {
+    use crate::{cnrfs, nr};
+
+    super::tlb::stop_process(pid, cores)?;
+
+    cnrfs::MlnrKernelNode::remove_process(pid)?;
+    let frames = NrProcess::<Ring3Process>::destroy(pid)?;
+    // Device memory and files mapped by the process are not part of these
+    KernelAllocator::release_frames(&frames)?;
+
+    nr::KernelNode::exit_pid(pid, exit_code)
+}
+
+/// Tears down the process `pid` that ran on the current core (it exits with
+/// `exit_code`) and lets the core run something else.
+///
+/// TODO: For now the machine is done once the last process exited, in that
+/// case we shut down with `reason`.
+#[cfg(target_os = "none")]
+pub fn exit_current(pid: Pid, exit_code: u64, reason: crate::ExitReason) -> ! {
+    let my_gtid = super::kcb::get_kcb().arch.id();
+    match crate::nr::KernelNode::release_cores(pid) {
+        // Only the core that got the cores of the process tears it down
+        Ok(cores) if cores.contains(&my_gtid) => {
+            if let Err(e) = teardown(pid, &cores, exit_code) {
+                error!("Couldn't tear down process {}: {:?}", pid, e);
+            }
+
+            if crate::nr::KernelNode::process_count() == Ok(0) {
+                super::debug::shutdown(reason);
+            }
+        }
+        // Another core of the process exits at the same time, it stops the
+        // process everywhere and tears it down
+        Ok(_cores) => super::tlb::StopProcess::new(pid).process(),
+        Err(e) => error!("Couldn't tear down process {}: {:?}", pid, e),
+    }
+
+    // The core no longer runs the process, find something else to do
+    crate::scheduler::schedule()
+}
@@ -3,6 +3,7 @@
 
 #![allow(warnings)]
 
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
@@ -15,7 +16,9 @@ use x86::bits64::paging::{PAddr, VAddr, BASE_PAGE_SIZE, LARGE_PAGE_SIZE};
 use x86::bits64::rflags;
 use x86::msr::{rdmsr, wrmsr, IA32_EFER, IA32_FMASK, IA32_LSTAR, IA32_STAR};
 
+use kpi::io::{FileInfo, FileModes, FsStats, IoVec, IOV_MAX};
 use kpi::process::FrameId;
+use kpi::system::SystemStats;
 use kpi::{
     FileOperation, ProcessOperation, SystemCall, SystemCallError, SystemOperation, VSpaceOperation,
 };
@@ -25,17 +28,20 @@ use crate::fs::FileSystem;
 use crate::kcb::ArchSpecificKcb;
 use crate::memory::vspace::MapAction;
 use crate::memory::{Frame, PhysicalPageProvider, KERNEL_BASE};
-use crate::process::{Pid, ResumeHandle};
+use crate::process::{userptr_to_str, Pid, ProcessArgs, ResumeHandle};
 use crate::{cnrfs, nr, nrproc};
 
 use super::gdt::GdtTable;
-use super::process::{Ring3Process, UserValue};
+use super::process::{Ring3Process, UserPtr, UserValue};
 
 extern "C" {
     #[no_mangle]
     fn syscall_enter();
 }
 
+/// Length of the `syscall` instruction, to restart a system call.
+const SYSCALL_INSTRUCTION_LEN: u64 = 2;
+
 fn handle_system(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError> {
     let op = SystemOperation::from(arg1);
 
@@ -70,6 +76,21 @@ fn handle_system(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError>
         SystemOperation::Stats => {
             let kcb = super::kcb::get_kcb();
             info!("IRQ handler time: {} cycles", kcb.tlb_time);
+
+            let stats_ptr = arg2;
+            let pid = kcb.current_pid()?;
+            let _r =
+                user_virt_addr_valid(pid, stats_ptr, core::mem::size_of::<SystemStats>() as u64)?;
+            let stats = SystemStats {
+                irq_handler_time: kcb.tlb_time,
+                fs_logs: cnrfs::num_logs() as u64,
+                fs_log_size: kcb.cmdline.fs_log_size as u64,
+                process_log_size: kcb.cmdline.process_log_size as u64,
+            };
+            let user_ptr = UserPtr::new(&mut VAddr::from(stats_ptr));
+            unsafe {
+                *user_ptr.as_mut_ptr::<SystemStats>() = stats;
+            }
             Ok((0, 0))
         }
         SystemOperation::GetCoreID => {
@@ -121,18 +142,86 @@ fn process_print(buf: UserValue<&str>) -> Result<(u64, u64), KError> {
 
 /// System call handler for process exit
 fn process_exit(code: u64) -> Result<(u64, u64), KError> {
-    debug!("Process got exit, we are done for now...");
-    // TODO: For now just a dummy version that exits Qemu
-    if code != 0 {
-        // When testing we want to indicate to our integration
-        // test that our user-space test failed with a non-zero exit
-        super::debug::shutdown(crate::ExitReason::UserSpaceError);
+    let kcb = super::kcb::get_kcb();
+    let pid = kcb.current_pid()?;
+    debug!("Process {} exited with {}", pid, code);
+
+    // When testing we want to indicate to our integration test that our
+    // user-space test failed with a non-zero exit
+    let reason = if code != 0 {
+        crate::ExitReason::UserSpaceError
     } else {
-        super::debug::shutdown(crate::ExitReason::Ok);
-    }
+        crate::ExitReason::Ok
+    };
+    super::process::exit_current(pid, code, reason)
+}
+
+/// System call handler for spawning a new process
+///
+/// `binary` is the name of a boot module, `cmdline` and `app_cmdline` are
+/// (optional) user-space strings that the new process gets in its
+/// `ProcessInfo`. The process starts running on core `gtid`.
+fn process_spawn(
+    binary: u64,
+    cmdline: u64,
+    app_cmdline: u64,
+    gtid: u64,
+) -> Result<(u64, u64), KError> {
+    let kcb = super::kcb::get_kcb();
+    let gtid: usize = gtid
+        .try_into()
+        .map_err(|_e| KError::InvalidGlobalThreadId)?;
+    let affinity = thread_affinity(gtid)?;
+    let parent = kcb.current_pid()?;
+
+    // Processes are created from the boot modules
+    let _r = user_virt_addr_valid(parent, binary, 0)?;
+    let binary = userptr_to_str(binary)?;
+    let binary = kcb
+        .arch
+        .kernel_args()
+        .modules
+        .iter()
+        .map(|module| module.name())
+        .find(|name| *name == binary)
+        .ok_or(KError::InvalidFile)?;
+
+    // The new process owns the arguments, they are freed when it exits
+    let user_arg = |arg: u64| -> Result<String, KError> {
+        if arg == 0 {
+            Ok(String::new())
+        } else {
+            let _r = user_virt_addr_valid(parent, arg, 0)?;
+            userptr_to_str(arg)
+        }
+    };
+    let args = ProcessArgs {
+        cmdline: user_arg(cmdline)?,
+        app_cmdline: user_arg(app_cmdline)?,
+    };
+
+    let pid = super::process::spawn_on(binary, args, Some(parent), affinity, gtid)?;
+    debug!("Spawned process {} ({}) on core {}", pid, binary, gtid);
+
+    Ok((pid as u64, 0))
+}
+
+/// The NUMA node of the hardware thread `gtid`.
+fn thread_affinity(gtid: atopology::GlobalThreadId) -> Result<atopology::NodeId, KError> {
+    atopology::MACHINE_TOPOLOGY
+        .threads()
+        .find(|thread| thread.id == gtid)
+        .map(|thread| thread.node_id.unwrap_or(0))
+        .ok_or(KError::InvalidGlobalThreadId)
 }
 
-fn handle_process(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError> {
+fn handle_process(
+    arg1: u64,
+    arg2: u64,
+    arg3: u64,
+    arg4: u64,
+    arg5: u64,
+) -> Result<(u64, u64), KError> {
     let op = ProcessOperation::from(arg1);
 
     match op {
@@ -171,9 +260,7 @@ fn handle_process(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError>
             let kcb = super::kcb::get_kcb();
 
             let pid = kcb.current_pid()?;
-            let mut pinfo = nrproc::NrProcess::<Ring3Process>::pinfo(pid)?;
-            pinfo.cmdline = kcb.cmdline.init_args;
-            pinfo.app_cmdline = kcb.cmdline.app_args;
+            let pinfo = nrproc::NrProcess::<Ring3Process>::pinfo(pid)?;
 
             let serialized = serde_cbor::to_vec(&pinfo).unwrap();
             if serialized.len() <= vaddr_buf_len as usize {
@@ -188,13 +275,7 @@ fn handle_process(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError>
             let entry_point = arg3;
             let kcb = super::kcb::get_kcb();
 
-            let mut affinity = None;
-            for thread in atopology::MACHINE_TOPOLOGY.threads() {
-                if thread.id == gtid {
-                    affinity = Some(thread.node_id.unwrap_or(0));
-                }
-            }
-            let affinity = affinity.ok_or(KError::InvalidGlobalThreadId)?;
+            let affinity = thread_affinity(gtid)?;
             let pid = kcb.current_pid()?;
 
             let gtid = nr::KernelNode::allocate_core_to_process(
@@ -242,13 +323,38 @@ fn handle_process(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError>
 
             Ok((fid as u64, frame.base.as_u64()))
         }
-        ProcessOperation::SubscribeEvent => Err(KError::InvalidProcessOperation { a: arg1 }),
+        ProcessOperation::Spawn => process_spawn(arg2, arg3, arg4, arg5),
+        ProcessOperation::Wait => {
+            let child: Pid = arg2.try_into().map_err(|_e| KError::NoProcessFoundForPid)?;
+            let nonblock = arg3 != 0;
+            let kcb = super::kcb::get_kcb();
+
+            let pid = kcb.current_pid()?;
+            let exit_code = nr::KernelNode::wait_pid(pid, child, nonblock)?;
+
+            Ok((exit_code, 0))
+        }
+        ProcessOperation::SubscribeEvent => {
+            let vector = arg2;
+            let kcb = super::kcb::get_kcb();
+
+            let pid = kcb.current_pid()?;
+            nrproc::NrProcess::<Ring3Process>::subscribe_event(pid, vector)?;
+
+            Ok((0, 0))
+        }
         ProcessOperation::Unknown => Err(KError::InvalidProcessOperation { a: arg1 }),
     }
 }
 
 /// System call handler for vspace operations
-fn handle_vspace(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError> {
+fn handle_vspace(
+    arg1: u64,
+    arg2: u64,
+    arg3: u64,
+    arg4: u64,
+    arg5: u64,
+) -> Result<(u64, u64), KError> {
     let op = VSpaceOperation::from(arg1);
     let base = VAddr::from(arg2);
     let region_size = arg3;
@@ -335,6 +441,46 @@ fn handle_vspace(arg1: u64, arg2: u64, arg3: u64) -> Result<(u64, u64), KError>
             )?;
             Ok((paddr.as_u64(), size as u64))
         },
+        VSpaceOperation::MapFile => {
+            // The fd is in the lower, the access rights in the upper half of arg3.
+            let fd = arg3 & 0xffff_ffff;
+            let rights = FileModes::from(arg3 >> 32);
+            let offset = arg4;
+            let len = arg5;
+
+            if !base.is_base_page_aligned() {
+                return Err(KError::InvalidBase);
+            }
+            // Every replica has its own copy of the file, stores through a
+            // mapping would only change the local one.
+            if rights.is_writable() {
+                return Err(KError::NotSupported);
+            }
+            let action = match (rights.is_readable(), rights.is_executable()) {
+                (true, false) => MapAction::ReadUser,
+                (true, true) => MapAction::ReadExecuteUser,
+                _ => return Err(KError::InvalidFlags),
+            };
+
+            let regions = cnrfs::MlnrKernelNode::file_map(p.pid, fd, offset, len)?;
+
+            // Large regions are split up if the virtual address doesn't allow
+            // mapping them as a large page.
+            let mut frames = Vec::try_with_capacity(regions.len())?;
+            let mut vaddr = base;
+            for (paddr, size) in regions {
+                if vaddr % size == 0 {
+                    frames.try_push(Frame::new(paddr, size, kcb.node))?;
+                } else {
+                    for offset in (0..size).step_by(BASE_PAGE_SIZE) {
+                        frames.try_push(Frame::new(paddr + offset, BASE_PAGE_SIZE, kcb.node))?;
+                    }
+                }
+                vaddr = vaddr + size;
+            }
+
+            nrproc::NrProcess::<Ring3Process>::map_file_frames(p.pid, base, frames, action)
+        }
         VSpaceOperation::Unmap => {
             let handle = nrproc::NrProcess::<Ring3Process>::unmap(p.pid, base)?;
             let va: u64 = handle.vaddr.as_u64();
@@ -395,10 +541,24 @@ fn handle_fileio(
             let _r = user_virt_addr_valid(pid, buffer, len)?;
             cnrfs::MlnrKernelNode::file_io(op, pid, fd, buffer, len, offset)
         }
+        FileOperation::ReadV | FileOperation::WriteV => {
+            let fd = arg2;
+            let iov = arg3;
+            let iovcnt = arg4;
+            let offset = arg5 as i64;
+
+            let iovecs = user_iovecs(pid, iov, iovcnt)?;
+            cnrfs::MlnrKernelNode::file_iov(op, pid, fd, &iovecs, offset)
+        }
         FileOperation::Close => {
             let fd = arg2;
             cnrfs::MlnrKernelNode::unmap_fd(pid, fd)
         }
+        FileOperation::StatFs => {
+            let stats_ptr = arg2;
+            let _r = user_virt_addr_valid(pid, stats_ptr, core::mem::size_of::<FsStats>() as u64)?;
+            cnrfs::MlnrKernelNode::statfs(stats_ptr)
+        }
         FileOperation::GetInfo => {
             let name = arg2;
             let info_ptr = arg3;
@@ -443,6 +603,116 @@ fn handle_fileio(
 
             cnrfs::MlnrKernelNode::mkdir(pid, pathname, modes)
         }
+        FileOperation::ReadDir => {
+            let pathname = arg2;
+            let buffer = arg3;
+            let len = arg4;
+            let _r = user_virt_addr_valid(pid, pathname, 0)?;
+            let _r = user_virt_addr_valid(pid, buffer, len)?;
+
+            cnrfs::MlnrKernelNode::readdir(pid, pathname, buffer, len)
+        }
+        FileOperation::Seek => {
+            let fd = arg2;
+            let offset = arg3 as i64;
+            let whence = arg4;
+
+            cnrfs::MlnrKernelNode::file_seek(pid, fd, offset, whence)
+        }
+        FileOperation::FStat => {
+            let fd = arg2;
+            let info_ptr = arg3;
+
+            let _r = user_virt_addr_valid(pid, info_ptr, core::mem::size_of::<FileInfo>() as u64)?;
+            cnrfs::MlnrKernelNode::file_stat(pid, fd, info_ptr)
+        }
+        FileOperation::Truncate => {
+            let fd = arg2;
+            let len = arg3;
+
+            cnrfs::MlnrKernelNode::file_truncate(pid, fd, len)
+        }
+        FileOperation::Dup => {
+            let fd = arg2;
+            cnrfs::MlnrKernelNode::file_dup(pid, fd)
+        }
+        FileOperation::Dup2 => {
+            let oldfd = arg2;
+            let newfd = arg3;
+            cnrfs::MlnrKernelNode::file_dup2(pid, oldfd, newfd)
+        }
+        FileOperation::Chdir => {
+            let pathname = arg2;
+            let _r = user_virt_addr_valid(pid, pathname, 0)?;
+
+            cnrfs::MlnrKernelNode::chdir(pid, pathname)
+        }
+        FileOperation::Getcwd => {
+            let buffer = arg2;
+            let len = arg3;
+            let _r = user_virt_addr_valid(pid, buffer, len)?;
+
+            cnrfs::MlnrKernelNode::getcwd(pid, buffer, len)
+        }
+        FileOperation::Link => {
+            let oldname = arg2;
+            let newname = arg3;
+            let _r = user_virt_addr_valid(pid, oldname, 0)?;
+            let _r = user_virt_addr_valid(pid, newname, 0)?;
+
+            cnrfs::MlnrKernelNode::file_link(pid, oldname, newname)
+        }
+        FileOperation::Symlink => {
+            let target = arg2;
+            let linkname = arg3;
+            let _r = user_virt_addr_valid(pid, target, 0)?;
+            let _r = user_virt_addr_valid(pid, linkname, 0)?;
+
+            cnrfs::MlnrKernelNode::file_symlink(pid, target, linkname)
+        }
+        FileOperation::ReadLink => {
+            let pathname = arg2;
+            let buffer = arg3;
+            let len = arg4;
+            let _r = user_virt_addr_valid(pid, pathname, 0)?;
+            let _r = user_virt_addr_valid(pid, buffer, len)?;
+
+            cnrfs::MlnrKernelNode::file_readlink(pid, pathname, buffer, len)
+        }
+        FileOperation::Pipe => {
+            let flags = arg2;
+            cnrfs::MlnrKernelNode::pipe(pid, flags)
+        }
+        FileOperation::Lock => {
+            let fd = arg2;
+            let flags = arg3;
+            let offset = arg4;
+            let len = arg5;
+            cnrfs::MlnrKernelNode::file_lock(pid, fd, flags, offset, len)
+        }
+        FileOperation::Unlock => {
+            let fd = arg2;
+            let offset = arg3;
+            let len = arg4;
+            cnrfs::MlnrKernelNode::file_unlock(pid, fd, offset, len)
+        }
+        FileOperation::WatchInit => {
+            let flags = arg2;
+            cnrfs::MlnrKernelNode::watch_init(pid, flags)
+        }
+        FileOperation::WatchAdd => {
+            let fd = arg2;
+            let pathname = arg3;
+            let events = arg4;
+            let _r = user_virt_addr_valid(pid, pathname, 0)?;
+
+            cnrfs::MlnrKernelNode::watch_add(pid, fd, pathname, events)
+        }
+        FileOperation::WatchRemove => {
+            let fd = arg2;
+            let wd = arg3;
+            cnrfs::MlnrKernelNode::watch_remove(pid, fd, wd)
+        }
         FileOperation::Unknown => {
             unreachable!("FileOperation not allowed");
             Err(KError::NotSupported)
@@ -450,6 +720,28 @@ fn handle_fileio(
     }
 }
 
+/// Copy an array of `iovcnt` iovecs from user-space and check that all the
+/// buffers they describe are valid.
+fn user_iovecs(pid: Pid, iov: u64, iovcnt: u64) -> Result<Vec<IoVec>, KError> {
+    if iovcnt == 0 || iovcnt as usize > IOV_MAX {
+        return Err(KError::InvalidLength);
+    }
+    let size = iovcnt * core::mem::size_of::<IoVec>() as u64;
+    let _r = user_virt_addr_valid(pid, iov, size)?;
+
+    let mut iovecs = Vec::try_with_capacity(iovcnt as usize)?;
+    let user_ptr = UserPtr::new(&mut VAddr::from(iov));
+    let user_iovecs =
+        unsafe { core::slice::from_raw_parts(user_ptr.as_ptr::<IoVec>(), iovcnt as usize) };
+    iovecs.extend_from_slice(user_iovecs);
+    drop(user_ptr);
+
+    for iovec in iovecs.iter() {
+        let _r = user_virt_addr_valid(pid, iovec.base, iovec.len)?;
+    }
+    Ok(iovecs)
+}
+
 /// TODO: This method makes file-operations slow, improve it to use large page
 /// sizes. Or maintain a list of (low, high) memory limits per process and check
 /// if (base, size) are within the process memory limits.
@@ -537,8 +829,8 @@ pub extern "C" fn syscall_handle(
 ) -> ! {
     let status: Result<(u64, u64), KError> = match SystemCall::new(function) {
         SystemCall::System => handle_system(arg1, arg2, arg3),
-        SystemCall::Process => handle_process(arg1, arg2, arg3),
-        SystemCall::VSpace => handle_vspace(arg1, arg2, arg3),
+        SystemCall::Process => handle_process(arg1, arg2, arg3, arg4, arg5),
+        SystemCall::VSpace => handle_vspace(arg1, arg2, arg3, arg4, arg5),
         SystemCall::FileIO => handle_fileio(arg1, arg2, arg3, arg4, arg5),
         _ => Err(KError::InvalidSyscallArgument1 { a: function }),
     };
@@ -554,6 +846,14 @@ pub extern "C" fn syscall_handle(
                     sa.set_syscall_error_code(SystemCallError::Ok);
                 });
             }
+            // The system call has to wait: run it again once the process is
+            // back in user-space, in between interrupts and other threads of
+            // the process can run. The arguments are still in the registers.
+            Err(KError::Restart) => {
+                kcb.arch.save_area.as_mut().map(|sa| {
+                    sa.rip -= SYSCALL_INSTRUCTION_LEN;
+                });
+            }
             Err(status) => {
                 error!("System call returned with error: {:?}", status);
                 kcb.arch.save_area.as_mut().map(|sa| {
@@ -18,8 +18,10 @@ use x86::apic::{
 };
 
 use super::memory::BASE_PAGE_SIZE;
+use crate::error::KError;
 use crate::kcb;
 use crate::memory::vspace::TlbFlushHandle;
+use crate::process::Pid;
 use crate::{cnrfs, is_page_aligned, nr};
 
 // In the xAPIC mode, the Destination Format Register (DFR) through the MMIO
@@ -58,6 +60,7 @@ lazy_static! {
 pub enum WorkItem {
     Shootdown(Arc<Shootdown>),
     AdvanceReplica(usize),
+    StopProcess(Arc<StopProcess>),
 }
 
 #[derive(Debug)]
@@ -107,6 +110,50 @@ impl Shootdown {
     }
 }
 
+#[derive(Debug)]
+pub struct StopProcess {
+    pid: Pid,
+    ack: AtomicBool,
+}
+
+impl StopProcess {
+    /// Create a new request to stop running process `pid`.
+    pub fn new(pid: Pid) -> Self {
+        StopProcess {
+            pid,
+            ack: AtomicBool::new(false),
+        }
+    }
+
+    /// Acknowledge to the sender/requestor core that we stopped.
+    fn acknowledge(&self) {
+        self.ack.store(true, Ordering::Release);
+    }
+
+    /// Check if receiver has stopped running the process.
+    pub fn is_acknowledged(&self) -> bool {
+        self.ack.load(Ordering::Acquire)
+    }
+
+    /// Drop the executor if the core runs the process, the core goes back to
+    /// the scheduler afterwards.
+    pub fn process(&self) {
+        let kcb = kcb::get_kcb();
+        if kcb.current_pid() == Ok(self.pid) {
+            let _executor = kcb.arch.take_current_executor();
+            // Don't keep using the page-tables of the process, they are
+            // released once all cores stopped.
+            unsafe {
+                let kernel_pml4 = kcb.arch.init_vspace().pml4_address();
+                x86::controlregs::cr3_write(kernel_pml4.into());
+            }
+        }
+
+        // Only safe to acknowledge once we're done with the process:
+        self.acknowledge();
+    }
+}
+
 pub fn enqueue(gtid: atopology::GlobalThreadId, s: WorkItem) {
     trace!("TLB enqueue shootdown msg {:?}", s);
     let _ignore = IPI_WORKQUEUE[gtid as usize].push(s);
@@ -120,20 +167,15 @@ pub fn dequeue(gtid: atopology::GlobalThreadId) {
                 s.process();
             }
             WorkItem::AdvanceReplica(log_id) => advance_log(log_id),
+            WorkItem::StopProcess(s) => s.process(),
         },
         None => { /*IPI request was handled by eager_advance_fs_replica()*/ }
     }
 }
 
 fn advance_log(log_id: usize) {
-    // All metadata operations are done using log 1. So, make sure that the
-    // replica has applied all those operation before any other log sync.
-    if log_id != 1 {
-        match cnrfs::MlnrKernelNode::synchronize_log(1) {
-            Ok(_) => { /* Simply return */ }
-            Err(e) => unreachable!("Error {:?} while advancing the log 1", e),
-        }
-    }
+    // Metadata operations are sharded over the logs too, an operation that
+    // is in several logs is ordered by CNR when one of them is applied.
     match cnrfs::MlnrKernelNode::synchronize_log(log_id) {
         Ok(_) => { /* Simply return */ }
         Err(e) => unreachable!("Error {:?} while advancing the log {}", e, log_id),
@@ -152,6 +194,7 @@ pub fn eager_advance_fs_replica() {
                     enqueue(core_id, msg)
                 }
                 WorkItem::AdvanceReplica(log_id) => advance_log(*log_id),
+                WorkItem::StopProcess(s) => s.process(),
             }
         }
         None => {
@@ -207,6 +250,56 @@ fn send_ipi_multicast(ldr: u32) {
     unsafe { apic.send_ipi(icr) }
 }
 
+/// IPI destinations of all clusters without any cores in them.
+///
+/// We support up to 16 IPI clusters, this will address `16*16 = 256` cores
+/// Cluster ID (LDR[31:16]) is the address of the destination cluster
+/// We pre-configure the upper half (cluster ID) here
+const NO_CLUSTER_DESTINATIONS: [u32; 16] = [
+    0 << 16,
+    1 << 16,
+    2 << 16,
+    3 << 16,
+    4 << 16,
+    5 << 16,
+    6 << 16,
+    7 << 16,
+    8 << 16,
+    9 << 16,
+    10 << 16,
+    11 << 16,
+    12 << 16,
+    13 << 16,
+    14 << 16,
+    15 << 16,
+];
+
+/// Adds `gtid` to the IPI destination of its cluster.
+fn add_cluster_destination(cluster_destination: &mut [u32; 16], gtid: atopology::GlobalThreadId) {
+    let apic_id = atopology::MACHINE_TOPOLOGY.threads[gtid].apic_id();
+    let cluster_addr = apic_id.x2apic_logical_cluster_address();
+    let cluster = apic_id.x2apic_logical_cluster_id();
+
+    trace!(
+        "Send IPI to gtid:{} in cluster:{} cluster_addr:{}",
+        gtid,
+        cluster,
+        cluster_addr
+    );
+    cluster_destination[cluster as usize].set_bit(cluster_addr as usize, true);
+}
+
+/// Notify the cores in all clusters of new work in the queue
+fn send_ipi_to_clusters(cluster_destination: [u32; 16]) {
+    for cluster_ldr in cluster_destination {
+        // Do we need to send to anyone inside this cluster?
+        if cluster_ldr.get_bits(0..=3) != 0 {
+            trace!("send ipi multicast to {}", cluster_ldr);
+            send_ipi_multicast(cluster_ldr);
+        }
+    }
+}
+
 /// Runs the TLB shootdown protocol.
 ///
 /// Takes the `TlbFlushHandle` and figures out what cores it needs to send an IPI to.
@@ -214,29 +307,7 @@ fn send_ipi_multicast(ldr: u32) {
 /// Finally, waits until all cores have acknowledged the IPI before it returns.
 pub fn shootdown(handle: TlbFlushHandle) {
     let my_gtid = super::kcb::get_kcb().arch.id();
-
-    // We support up to 16 IPI clusters, this will address `16*16 = 256` cores
-    // Cluster ID (LDR[31:16]) is the address of the destination cluster
-    // We pre-configure the upper half (cluster ID) of LDR here
-    // by initializing the elements
-    let mut cluster_destination: [u32; 16] = [
-        0 << 16,
-        1 << 16,
-        2 << 16,
-        3 << 16,
-        4 << 16,
-        5 << 16,
-        6 << 16,
-        7 << 16,
-        8 << 16,
-        9 << 16,
-        10 << 16,
-        11 << 16,
-        12 << 16,
-        13 << 16,
-        14 << 16,
-        15 << 16,
-    ];
+    let mut cluster_destination = NO_CLUSTER_DESTINATIONS;
 
     let num_cores = atopology::MACHINE_TOPOLOGY.num_threads();
     let mut shootdowns: Vec<Arc<Shootdown>> = Vec::try_with_capacity(num_cores)
@@ -245,17 +316,7 @@ pub fn shootdown(handle: TlbFlushHandle) {
 
     for gtid in handle.cores() {
         if gtid != my_gtid {
-            let apic_id = atopology::MACHINE_TOPOLOGY.threads[gtid].apic_id();
-            let cluster_addr = apic_id.x2apic_logical_cluster_address();
-            let cluster = apic_id.x2apic_logical_cluster_id();
-
-            trace!(
-                "Send shootdown to gtid:{} in cluster:{} cluster_addr:{}",
-                gtid,
-                cluster,
-                cluster_addr
-            );
-            cluster_destination[cluster as usize].set_bit(cluster_addr as usize, true);
+            add_cluster_destination(&mut cluster_destination, gtid);
 
             let shootdown = Arc::try_new(Shootdown::new(range.clone()))
                 .expect("TODO(error-handling): ideally: no possible failure during shootdown");
@@ -266,14 +327,7 @@ pub fn shootdown(handle: TlbFlushHandle) {
         }
     }
 
-    // Notify the cores in all clusters of new work in the queue
-    for cluster_ldr in cluster_destination {
-        // Do we need to send to anyone inside this cluster?
-        if cluster_ldr.get_bits(0..=3) != 0 {
-            trace!("send ipi multicast to {}", cluster_ldr);
-            send_ipi_multicast(cluster_ldr);
-        }
-    }
+    send_ipi_to_clusters(cluster_destination);
 
     // Finally, we also need to shootdown our own TLB
     let shootdown = Shootdown::new(range);
@@ -288,6 +342,43 @@ pub fn shootdown(handle: TlbFlushHandle) {
     trace!("done with all shootdowns");
 }
 
+/// Stops a process on all `cores` that were assigned to it and on the current core.
+///
+/// Uses the same IPI protocol as the TLB shootdown and waits until all cores
+/// have acknowledged they no longer run the process. Afterwards, it's safe to
+/// release the address-space and executors of the process.
+pub fn stop_process(pid: Pid, cores: &[atopology::GlobalThreadId]) -> Result<(), KError> {
+    let my_gtid = super::kcb::get_kcb().arch.id();
+    let mut cluster_destination = NO_CLUSTER_DESTINATIONS;
+
+    let mut stops: Vec<Arc<StopProcess>> = Vec::try_with_capacity(cores.len())?;
+    for gtid in cores.iter().copied() {
+        if gtid != my_gtid {
+            add_cluster_destination(&mut cluster_destination, gtid);
+
+            let stop = Arc::try_new(StopProcess::new(pid))?;
+            enqueue(gtid, WorkItem::StopProcess(stop.clone()));
+
+            debug_assert!(stops.len() < stops.capacity(), "Avoid realloc");
+            stops.push(stop);
+        }
+    }
+
+    send_ipi_to_clusters(cluster_destination);
+
+    // The current core is the one that tears down the process
+    StopProcess::new(pid).process();
+
+    // Wait synchronously on cores to complete
+    while !stops.is_empty() {
+        stops.drain_filter(|s| s.is_acknowledged());
+        core::hint::spin_loop();
+    }
+
+    trace!("process {} stopped on all cores", pid);
+    Ok(())
+}
+
 pub fn advance_replica(gtid: atopology::GlobalThreadId, log_id: usize) {
     trace!("Send AdvanceReplica IPI for {} to {}", log_id, gtid);
     let apic_id = atopology::MACHINE_TOPOLOGY.threads[gtid as usize].apic_id();
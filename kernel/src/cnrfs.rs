@@ -3,26 +3,60 @@
 
 use crate::arch::process::{UserPtr, UserSlice};
 use crate::error::KError;
+use crate::fallible_string::{FallibleString, TryString};
+use crate::fs::cpio::CpioArchive;
 use crate::fs::fd::FileDesc;
+use crate::fs::lock::LockKind;
 use crate::fs::{
-    Buffer, FileDescriptor, FileSystem, Filename, Flags, Len, MlnrFS, Mnode, Modes, NrLock, Offset,
-    FD, MNODE_OFFSET,
+    normalize_path, Buffer, Fd, FileDescriptor, FileSystem, Filename, Flags, Len, MlnrFS, Mnode,
+    Modes, NrLock, Offset, TimeUpdate, FD, MNODE_OFFSET,
 };
-use crate::memory::VAddr;
+use crate::memory::{PAddr, VAddr};
 use crate::prelude::*;
 use crate::process::{userptr_to_str, KernSlice, Pid};
 
 use alloc::sync::Arc;
 use cnr::{Dispatch, LogMapper};
+use core::convert::TryFrom;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use fallible_collections::vec::FallibleVec;
 use hashbrown::HashMap;
 use kpi::io::*;
 use kpi::FileOperation;
 
+/// Number of logs the file-system operations are spread over.
+static NUM_LOGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of file-system replicas in the system.
+static NUM_REPLICAS: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of the next mnode that is created.
+///
+/// Like timestamps, mnode numbers are picked before an operation is put in
+/// the log: replicas can apply operations of different logs in a different
+/// order, but they have to create the same mnodes.
+static NEXT_MNODE: AtomicU64 = AtomicU64::new(MNODE_OFFSET as u64);
+
+fn next_mnode() -> Mnode {
+    NEXT_MNODE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Record how the file-system is replicated, once during initialization.
+pub fn set_replication(nlogs: usize, nreplicas: usize) {
+    NUM_LOGS.store(nlogs, Ordering::Relaxed);
+    NUM_REPLICAS.store(nreplicas, Ordering::Relaxed);
+}
+
+/// Number of logs the file-system operations are spread over.
+pub fn num_logs() -> usize {
+    NUM_LOGS.load(Ordering::Relaxed)
+}
+
 pub struct MlnrKernelNode {
     /// TODO: RwLock should be okay for read-write operations as those ops
     /// perform read() on lock. Make an array of hashmaps to distribute the
     /// load evenly for file-open benchmarks.
-    process_map: NrLock<HashMap<Pid, FileDesc>>,
+    process_map: NrLock<HashMap<Pid, FsContext>>,
     /// MLNR kernel node primarily replicates the in-memory filesystem.
     fs: MlnrFS,
 }
@@ -30,22 +64,109 @@ pub struct MlnrKernelNode {
 impl Default for MlnrKernelNode {
     fn default() -> Self {
         MlnrKernelNode {
-            process_map: NrLock::<HashMap<Pid, FileDesc>>::default(),
+            process_map: NrLock::<HashMap<Pid, FsContext>>::default(),
             fs: MlnrFS::default(),
         }
     }
 }
 
+/// The file-system state of a process.
+pub struct FsContext {
+    /// The open file descriptors.
+    fds: FileDesc,
+    /// The working directory, an absolute and normalized path.
+    cwd: String,
+    /// The file ranges (mnode, offset, length) mapped into the process, the
+    /// mappings go away with the process.
+    mappings: Vec<(Mnode, usize, usize)>,
+}
+
+impl FsContext {
+    fn new() -> Result<FsContext, KError> {
+        Ok(FsContext {
+            fds: FileDesc::default(),
+            cwd: TryString::try_from("/")?.into(),
+            mappings: Vec::new(),
+        })
+    }
+
+    /// Get the open file behind `fd` for an operation in the log of `mnode`.
+    ///
+    /// Fails if `fd` was closed (and maybe reused for another file) after
+    /// the caller looked up its mnode, the log doesn't order the operation
+    /// with the close.
+    fn get_fd(&self, fd: FD, mnode: Mnode) -> Result<&Fd, KError> {
+        self.fds
+            .get_fd(fd as usize)
+            .filter(|fd| fd.get_mnode() == mnode)
+            .ok_or(KError::PermissionError)
+    }
+}
+
+/// The kind of lock `flags` ask for on `fd`; a shared lock requires read
+/// and an exclusive lock write access.
+fn lock_kind(fd: &Fd, flags: Flags, offset: Offset) -> Result<LockKind, KError> {
+    let flags = LockFlags::from(flags);
+    let kind = match (
+        flags.contains(LockFlags::LOCK_SH),
+        flags.contains(LockFlags::LOCK_EX),
+    ) {
+        (true, false) if fd.get_flags().is_read() => LockKind::Shared,
+        (false, true) if fd.get_flags().is_write() => LockKind::Exclusive,
+        (true, false) | (false, true) => return Err(KError::PermissionError),
+        _ => return Err(KError::InvalidFlags),
+    };
+    if offset < 0 {
+        return Err(KError::InvalidOffset);
+    }
+    Ok(kind)
+}
+
+/// Current wall-clock time in nanoseconds since the unix epoch.
+///
+/// Operations that update file timestamps read the time before they are
+/// put in the log, so all replicas end up with the same timestamps.
+fn now() -> u64 {
+    let since_boot = rawtime::duration_since_boot();
+    rawtime::WALL_TIME_ANCHOR.as_unix_time() * 1_000_000_000 + since_boot.as_nanos() as u64
+}
+
 #[derive(Hash, Clone, Debug, PartialEq)]
 pub enum Modify {
     ProcessAdd(Pid),
     ProcessRemove(Pid),
-    FileOpen(Pid, String, Flags, Modes),
-    FileWrite(Pid, FD, Mnode, Arc<[u8]>, Len, Offset),
-    FileClose(Pid, FD),
-    FileDelete(Pid, String),
+    FileOpen(
+        Pid,
+        String,
+        Flags,
+        Modes,
+        u64,
+        Option<Mnode>,
+        Option<Mnode>,
+        bool,
+    ),
+    FileWrite(Pid, FD, Mnode, Arc<[u8]>, Len, Offset, u64, bool),
+    FileClose(Pid, FD, Option<Mnode>),
+    FileDelete(Pid, String, bool),
     FileRename(Pid, String, String),
-    MkDir(Pid, String, Modes),
+    MkDir(Pid, String, Modes, u64, Mnode, bool),
+    FileSeek(Pid, FD, Mnode, Offset, u64),
+    FileTruncate(Pid, FD, Mnode, Len, u64, bool),
+    FileDup(Pid, FD),
+    FileDup2(Pid, FD, FD),
+    FileMap(Pid, FD, Mnode, Offset, Len),
+    Unpack(String, FileType, Modes, &'static [u8], u64, Mnode),
+    Chdir(Pid, String),
+    FileLink(Pid, String, String),
+    FileSymlink(Pid, String, String, u64, Mnode),
+    Pipe(Pid, Flags, Mnode),
+    PipeRead(Pid, FD, Mnode, Len),
+    FileLock(Pid, FD, Mnode, Flags, Offset, Len),
+    FileUnlock(Pid, FD, Mnode, Offset, Len),
+    WatchInit(Pid, Flags, Mnode),
+    WatchAdd(Pid, FD, String, u64),
+    WatchRemove(Pid, FD, u64),
+    WatchRead(Pid, FD, Mnode, Len),
 }
 
 // TODO: Stateless op to log mapping. Maintain some state for correct redirection.
@@ -56,14 +177,78 @@ impl LogMapper for Modify {
         match self {
             Modify::ProcessAdd(_pid) => push_to_all(nlogs, logs),
             Modify::ProcessRemove(_pid) => push_to_all(nlogs, logs),
-            Modify::FileOpen(_pid, _filename, _flags, _modes) => push_to_all(nlogs, logs),
-            Modify::FileWrite(_pid, _fd, mnode, _kernslice, _len, _offset) => {
-                logs.push((*mnode as usize - MNODE_OFFSET) % nlogs)
+            // Metadata operations on an absolute path are ordered in the log of
+            // its directory, with the operations on the fds of the process. The
+            // new fd is also used in the log of the file.
+            Modify::FileOpen(pid, filename, _flags, _modes, _time, new_mnode, mnode, true) => {
+                logs.push(parent_log(filename, nlogs));
+                push_unique(pid_log(*pid, nlogs), logs);
+                if let Some(mnode) = mnode.or(*new_mnode) {
+                    push_unique(mnode_log(mnode, nlogs), logs);
+                }
+            }
+            Modify::FileOpen(_pid, _filename, _flags, _modes, _time, _new_mnode, _mnode, false) => {
+                push_to_all(nlogs, logs)
+            }
+            // Changes to a watched file are ordered with all other events.
+            Modify::FileWrite(_pid, _fd, mnode, _kernslice, _len, _offset, _time, true) => {
+                logs.push(mnode_log(*mnode, nlogs))
             }
-            Modify::FileClose(_pid, _fd) => push_to_all(nlogs, logs),
-            Modify::FileDelete(_pid, _filename) => push_to_all(nlogs, logs),
+            Modify::FileWrite(_pid, _fd, _mnode, _kernslice, _len, _offset, _time, false) => {
+                push_to_all(nlogs, logs)
+            }
+            Modify::FileClose(pid, _fd, Some(mnode)) => {
+                logs.push(pid_log(*pid, nlogs));
+                push_unique(mnode_log(*mnode, nlogs), logs);
+            }
+            Modify::FileClose(_pid, _fd, None) => push_to_all(nlogs, logs),
+            // Removing or creating a directory is also ordered with the
+            // operations on its entries.
+            Modify::FileDelete(_pid, filename, true) => {
+                logs.push(parent_log(filename, nlogs));
+                push_unique(dir_log(filename, nlogs), logs);
+            }
+            Modify::FileDelete(_pid, _filename, false) => push_to_all(nlogs, logs),
             Modify::FileRename(_pid, _oldname, _newname) => push_to_all(nlogs, logs),
-            Modify::MkDir(_pid, _name, _modes) => push_to_all(nlogs, logs),
+            Modify::MkDir(_pid, filename, _modes, _time, _mnode, true) => {
+                logs.push(parent_log(filename, nlogs));
+                push_unique(dir_log(filename, nlogs), logs);
+            }
+            Modify::MkDir(_pid, _filename, _modes, _time, _mnode, false) => {
+                push_to_all(nlogs, logs)
+            }
+            // Goes to the same log as the writes, so SEEK_END sees the size
+            // after all preceding writes.
+            Modify::FileSeek(_pid, _fd, mnode, _offset, _whence) => {
+                logs.push(mnode_log(*mnode, nlogs))
+            }
+            Modify::FileTruncate(_pid, _fd, mnode, _len, _time, true) => {
+                logs.push(mnode_log(*mnode, nlogs))
+            }
+            Modify::FileTruncate(_pid, _fd, _mnode, _len, _time, false) => push_to_all(nlogs, logs),
+            Modify::FileDup(_pid, _fd) => push_to_all(nlogs, logs),
+            Modify::FileDup2(_pid, _oldfd, _newfd) => push_to_all(nlogs, logs),
+            Modify::FileMap(_pid, _fd, mnode, _offset, _len) => logs.push(mnode_log(*mnode, nlogs)),
+            Modify::Unpack(_name, _ftype, _modes, _data, _time, _mnode) => push_to_all(nlogs, logs),
+            Modify::Chdir(_pid, _pathname) => push_to_all(nlogs, logs),
+            Modify::FileLink(_pid, _oldname, _newname) => push_to_all(nlogs, logs),
+            Modify::FileSymlink(_pid, _target, _linkname, _time, _mnode) => {
+                push_to_all(nlogs, logs)
+            }
+            Modify::Pipe(_pid, _flags, _mnode) => push_to_all(nlogs, logs),
+            // Goes to the same log as the writes to the pipe.
+            Modify::PipeRead(_pid, _fd, mnode, _len) => logs.push(mnode_log(*mnode, nlogs)),
+            Modify::FileLock(_pid, _fd, mnode, _flags, _offset, _len) => {
+                logs.push(mnode_log(*mnode, nlogs))
+            }
+            Modify::FileUnlock(_pid, _fd, mnode, _offset, _len) => {
+                logs.push(mnode_log(*mnode, nlogs))
+            }
+            Modify::WatchInit(_pid, _flags, _mnode) => push_to_all(nlogs, logs),
+            Modify::WatchAdd(_pid, _fd, _filename, _events) => push_to_all(nlogs, logs),
+            Modify::WatchRemove(_pid, _fd, _wd) => push_to_all(nlogs, logs),
+            // Operations that report events go to all logs.
+            Modify::WatchRead(_pid, _fd, mnode, _len) => logs.push(mnode_log(*mnode, nlogs)),
         }
 
         fn push_to_all(nlogs: usize, logs: &mut Vec<usize>) {
@@ -71,6 +256,58 @@ impl LogMapper for Modify {
                 logs.push(i);
             }
         }
+
+        fn push_unique(log: usize, logs: &mut Vec<usize>) {
+            if !logs.contains(&log) {
+                logs.push(log);
+            }
+        }
+    }
+}
+
+/// The log for the metadata of the directory `dirname`, an absolute and
+/// normalized path.
+fn dir_log(dirname: &str, nlogs: usize) -> usize {
+    // FNV-1a, all cores have to pick the same log for a path.
+    let hash = dirname
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+    hash as usize % nlogs
+}
+
+/// The log for the metadata of the directory that holds `pathname`.
+fn parent_log(pathname: &str, nlogs: usize) -> usize {
+    match pathname.rfind('/') {
+        Some(0) | None => dir_log("/", nlogs),
+        Some(idx) => dir_log(&pathname[..idx], nlogs),
+    }
+}
+
+/// The log for the file descriptors of a process.
+fn pid_log(pid: Pid, nlogs: usize) -> usize {
+    pid % nlogs
+}
+
+/// The log for the data of a file (or the entries of a watch queue or pipe).
+///
+/// The root directory is numbered below `MNODE_OFFSET`, so the subtraction
+/// wraps around for it.
+fn mnode_log(mnode: Mnode, nlogs: usize) -> usize {
+    (mnode as usize).wrapping_sub(MNODE_OFFSET) % nlogs
+}
+
+/// Prepare `pathname` for a metadata operation. Absolute paths are
+/// normalized so the operation can be put in the log of their directory;
+/// relative paths depend on the working directory and have to go to all logs.
+///
+/// Returns the path and whether it picks the logs of the operation.
+fn shard_path(pathname: String) -> Result<(String, bool), KError> {
+    if pathname.starts_with('/') {
+        Ok((normalize_path("/", &pathname)?, true))
+    } else {
+        Ok((pathname, false))
     }
 }
 
@@ -78,9 +315,16 @@ impl LogMapper for Modify {
 pub enum Access {
     FileRead(Pid, FD, Mnode, Buffer, Len, Offset),
     FileInfo(Pid, Filename, Mnode, u64),
+    FileStat(Pid, FD, Mnode, u64),
     FdToMnode(Pid, FD),
-    FileNameToMnode(Pid, Filename),
+    FileNameToMnode(Pid, String, bool),
+    ReadDir(Pid, Filename, Buffer, Len),
+    Getcwd(Pid, Buffer, Len),
+    ReadLink(Pid, Filename, Buffer, Len),
+    FsStats,
     Synchronize(usize),
+    Poll(Pid, FD, Mnode),
+    LockPoll(Pid, FD, Mnode, Flags, Offset, Len),
 }
 
 //TODO: Stateless op to log mapping. Maintain some state for correct redirection.
@@ -90,17 +334,29 @@ impl LogMapper for Access {
         logs.clear();
         match self {
             Access::FileRead(_pid, _fd, mnode, _buffer, _len, _offser) => {
-                logs.push((*mnode as usize - MNODE_OFFSET) % nlogs)
+                logs.push(mnode_log(*mnode, nlogs))
             }
             Access::FileInfo(_pid, _filename, mnode, _info_ptr) => {
-                logs.push((*mnode as usize - MNODE_OFFSET) % nlogs)
+                logs.push(mnode_log(*mnode, nlogs))
             }
-            // TODO: Assume that all metadata modifying operations go through log 0.
-            Access::FdToMnode(_pid, _fd) => logs.push(0),
-            Access::FileNameToMnode(_pid, _filename) => logs.push(0),
+            Access::FileStat(_pid, _fd, mnode, _info_ptr) => logs.push(mnode_log(*mnode, nlogs)),
+            Access::FdToMnode(pid, _fd) => logs.push(pid_log(*pid, nlogs)),
+            Access::FileNameToMnode(_pid, filename, true) => logs.push(parent_log(filename, nlogs)),
+            // The caller synchronized all logs.
+            Access::FileNameToMnode(_pid, _filename, false) => logs.push(0),
+            Access::ReadDir(_pid, _filename, _buffer, _len) => logs.push(0),
+            // Chdir goes to all logs, so any log has the working directory.
+            Access::Getcwd(_pid, _buffer, _len) => logs.push(0),
+            // The caller synchronized all logs.
+            Access::ReadLink(_pid, _filename, _buffer, _len) => logs.push(0),
+            Access::FsStats => logs.push(0),
             // Log number start with 1 in CNR, however, replica uses mod
             // operation which starts with 0; hence `log_id - 1`.
             Access::Synchronize(log_id) => logs.push((*log_id - 1) % nlogs),
+            Access::Poll(_pid, _fd, mnode) => logs.push(mnode_log(*mnode, nlogs)),
+            Access::LockPoll(_pid, _fd, mnode, _flags, _offset, _len) => {
+                logs.push(mnode_log(*mnode, nlogs))
+            }
         }
     }
 }
@@ -116,7 +372,35 @@ pub enum MlnrNodeResult {
     FileInfo(FileInfo),
     FileRenamed,
     DirCreated,
+    DirRead(Len),
+    FileSeeked(u64),
+    FileTruncated,
+    FileDuplicated(FD),
+    FileMapped(Vec<(PAddr, usize)>),
+    Unpacked,
+    DirChanged,
+    CwdRead(Len),
+    FileLinked,
+    LinkRead(Len),
+    PipeCreated(FD, FD),
+    PipeRead(Vec<u8>),
+    /// The pipe or event queue is empty (or the pipe is full) and the caller
+    /// should wait and retry.
+    Blocked,
+    /// The pipe or event queue can be read (or written) without waiting.
+    Ready,
+    /// The operation can't be ordered by the logs it was put in (e.g., its
+    /// path leads through a symbolic link) and has to go to all logs.
+    Reroute,
+    FileLocked,
+    FileUnlocked,
+    WatchCreated(FD),
+    WatchAdded(u64),
+    WatchRemoved,
+    EventsRead(Vec<WatchEvent>),
+    FsStats(FsStats),
     MappedFileToMnode(u64),
+    MappedFdToMnode(Mnode, u64),
     Synchronized,
 }
 
@@ -138,24 +422,59 @@ impl MlnrKernelNode {
             })
     }
 
-    pub fn map_fd(pid: Pid, pathname: u64, flags: u64, modes: u64) -> Result<(FD, u64), KError> {
+    /// Close all files of the process and forget about it.
+    pub fn remove_process(pid: usize) -> Result<(u64, u64), KError> {
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
             .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let filename = userptr_to_str(pathname)?;
-                let response =
-                    replica.execute_mut_scan(Modify::FileOpen(pid, filename, flags, modes), *token);
-
+                let response = replica.execute_mut_scan(Modify::ProcessRemove(pid), *token);
                 match response {
-                    Ok(MlnrNodeResult::FileOpened(fd)) => Ok((fd, 0)),
+                    Ok(MlnrNodeResult::ProcessRemoved(pid)) => Ok((pid as u64, 0)),
                     Err(e) => Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
 
+    pub fn map_fd(pid: Pid, pathname: u64, flags: u64, modes: u64) -> Result<(FD, u64), KError> {
+        let file_flags = FileFlags::from(flags);
+        let new_mnode = match file_flags.is_create() {
+            true => Some(next_mnode()),
+            false => None,
+        };
+        // Truncating changes the file, so it has to be ordered with the writes
+        // in the log of the file.
+        let truncate = file_flags.is_truncate();
+        // A sharded open is also put in the log of the file, so look up the
+        // file it's expected to open.
+        let mnode = match !truncate && userptr_to_str(pathname)?.starts_with('/') {
+            true => MlnrKernelNode::filename_to_mnode(pid, pathname)
+                .ok()
+                .map(|(mnode, _)| mnode),
+            false => None,
+        };
+
+        let response = MlnrKernelNode::execute_path_op(pathname, |filename, sharded| {
+            Modify::FileOpen(
+                pid,
+                filename,
+                flags,
+                modes,
+                now(),
+                new_mnode,
+                mnode,
+                sharded && !truncate,
+            )
+        });
+        match response {
+            Ok(MlnrNodeResult::FileOpened(fd)) => Ok((fd, 0)),
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("Got unexpected response"),
+        }
+    }
+
     pub fn file_io(
         op: FileOperation,
         pid: Pid,
@@ -164,10 +483,16 @@ impl MlnrKernelNode {
         len: u64,
         offset: i64,
     ) -> Result<(Len, u64), KError> {
-        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
-            Ok((mnode, _)) => mnode,
+        let (mnode, ftype) = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, ftype)) => (mnode, ftype),
             Err(_) => return Err(KError::InvalidFileDescriptor),
         };
+        if ftype == u64::from(FileType::Pipe) {
+            return MlnrKernelNode::pipe_io(op, pid, fd, mnode, buffer, len, offset);
+        }
+        if ftype == u64::from(FileType::Watch) {
+            return MlnrKernelNode::watch_io(op, pid, fd, mnode, buffer, len, offset);
+        }
         let kcb = super::kcb::get_kcb();
         kcb.arch.cnr_replica.as_ref().map_or(
             Err(KError::ReplicaNotSet),
@@ -175,10 +500,19 @@ impl MlnrKernelNode {
                 FileOperation::Write | FileOperation::WriteAt => {
                     let kernslice = KernSlice::new(buffer, len as usize);
 
-                    let response = replica.execute_mut(
-                        Modify::FileWrite(pid, fd, mnode, kernslice.buffer, len, offset),
-                        *token,
-                    );
+                    let time = now();
+                    let response = MlnrKernelNode::execute_mnode_op(|sharded| {
+                        Modify::FileWrite(
+                            pid,
+                            fd,
+                            mnode,
+                            kernslice.buffer.clone(),
+                            len,
+                            offset,
+                            time,
+                            sharded,
+                        )
+                    });
 
                     match response {
                         Ok(MlnrNodeResult::FileAccessed(len)) => Ok((len, 0)),
@@ -204,39 +538,262 @@ impl MlnrKernelNode {
         )
     }
 
-    pub fn unmap_fd(pid: Pid, fd: u64) -> Result<(u64, u64), KError> {
+    /// Read into or write from the (already validated) user buffers in
+    /// `iovecs`.
+    ///
+    /// A vectored write to a file is a single `FileWrite` log entry, so it is
+    /// applied atomically on all replicas. Reads aren't logged and are done
+    /// buffer by buffer, as is I/O on pipes.
+    pub fn file_iov(
+        op: FileOperation,
+        pid: Pid,
+        fd: u64,
+        iovecs: &[IoVec],
+        offset: i64,
+    ) -> Result<(Len, u64), KError> {
+        let (mnode, ftype) = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, ftype)) => (mnode, ftype),
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
+        let len = iovecs
+            .iter()
+            .try_fold(0u64, |len, iov| len.checked_add(iov.len))
+            .ok_or(KError::InvalidLength)?;
+
+        if op == FileOperation::ReadV || ftype == u64::from(FileType::Pipe) {
+            let op = match op {
+                FileOperation::ReadV => FileOperation::Read,
+                _ => FileOperation::Write,
+            };
+            let mut done = 0;
+            for iov in iovecs.iter().filter(|iov| iov.len > 0) {
+                let offset = match offset {
+                    -1 => -1,
+                    offset => offset + done as i64,
+                };
+                let len = match MlnrKernelNode::file_io(op, pid, fd, iov.base, iov.len, offset) {
+                    Ok((len, _)) => len,
+                    // Report the part that was done before the error.
+                    Err(_e) if done > 0 => break,
+                    Err(e) => return Err(e),
+                };
+                done += len;
+                // Stop at the end of the file (or an empty pipe).
+                if len < iov.len {
+                    break;
+                }
+            }
+            return Ok((done, 0));
+        }
+
+        let kernslice = KernSlice::from_iovecs(iovecs, len as usize);
+        let time = now();
+        let response = MlnrKernelNode::execute_mnode_op(|sharded| {
+            Modify::FileWrite(
+                pid,
+                fd,
+                mnode,
+                kernslice.buffer.clone(),
+                len,
+                offset,
+                time,
+                sharded,
+            )
+        });
+        match response {
+            Ok(MlnrNodeResult::FileAccessed(len)) => Ok((len, 0)),
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("Got unexpected response"),
+        }
+    }
+
+    /// Read from or write to a pipe. Unless the file descriptor is
+    /// non-blocking, reads wait for data and writes wait until some of the
+    /// data is in the pipe.
+    ///
+    /// Waiting restarts the system call, so the core isn't kept in the
+    /// kernel and nothing is logged until the pipe is ready.
+    fn pipe_io(
+        op: FileOperation,
+        pid: Pid,
+        fd: u64,
+        mnode: Mnode,
+        buffer: u64,
+        len: u64,
+        offset: i64,
+    ) -> Result<(Len, u64), KError> {
+        // Pipes have no file offset.
+        if offset != -1 {
+            return Err(KError::InvalidOffset);
+        }
+
+        let kcb = super::kcb::get_kcb();
+        kcb.arch.cnr_replica.as_ref().map_or(
+            Err(KError::ReplicaNotSet),
+            |(replica, token)| match op {
+                FileOperation::Write | FileOperation::WriteAt => {
+                    let mut written = 0;
+                    loop {
+                        let response = replica.execute(Access::Poll(pid, fd, mnode), *token);
+                        match response {
+                            Ok(MlnrNodeResult::Ready) => {}
+                            Ok(MlnrNodeResult::Blocked) if written > 0 => return Ok((written, 0)),
+                            Ok(MlnrNodeResult::Blocked) => return Err(KError::Restart),
+                            Err(_e) if written > 0 => return Ok((written, 0)),
+                            Err(e) => return Err(e),
+                            Ok(_) => unreachable!("Got unexpected response"),
+                        }
+
+                        let remaining = len - written;
+                        let kernslice = KernSlice::new(buffer + written, remaining as usize);
+                        let response = replica.execute_mut(
+                            Modify::FileWrite(
+                                pid,
+                                fd,
+                                mnode,
+                                kernslice.buffer,
+                                remaining,
+                                -1,
+                                now(),
+                                true,
+                            ),
+                            *token,
+                        );
+
+                        match response {
+                            Ok(MlnrNodeResult::FileAccessed(len)) => written += len,
+                            // Another writer filled the pipe since the poll.
+                            Ok(MlnrNodeResult::Blocked) => {}
+                            // Report the part that was written before the error.
+                            Err(_e) if written > 0 => return Ok((written, 0)),
+                            Err(e) => return Err(e),
+                            Ok(_) => unreachable!("Got unexpected response"),
+                        }
+                        if written == len {
+                            return Ok((written, 0));
+                        }
+                    }
+                }
+
+                FileOperation::Read | FileOperation::ReadAt => {
+                    let response = replica.execute(Access::Poll(pid, fd, mnode), *token);
+                    match response {
+                        Ok(MlnrNodeResult::Ready) => {}
+                        Ok(MlnrNodeResult::Blocked) => return Err(KError::Restart),
+                        Err(e) => return Err(e),
+                        Ok(_) => unreachable!("Got unexpected response"),
+                    }
+
+                    let response =
+                        replica.execute_mut(Modify::PipeRead(pid, fd, mnode, len), *token);
+                    match response {
+                        Ok(MlnrNodeResult::PipeRead(data)) => {
+                            let mut userslice = UserSlice::new(buffer, len as usize);
+                            userslice[..data.len()].copy_from_slice(&data);
+                            Ok((data.len() as u64, 0))
+                        }
+                        // Another reader emptied the pipe since the poll.
+                        Ok(MlnrNodeResult::Blocked) => Err(KError::Restart),
+                        Err(e) => Err(e),
+                        Ok(_) => unreachable!("Got unexpected response"),
+                    }
+                }
+                _ => unreachable!(),
+            },
+        )
+    }
+
+    /// Read events from an event queue; unless the file descriptor is
+    /// non-blocking this waits for an event by restarting the system call.
+    /// Only whole events are read.
+    fn watch_io(
+        op: FileOperation,
+        pid: Pid,
+        fd: u64,
+        mnode: Mnode,
+        buffer: u64,
+        len: u64,
+        offset: i64,
+    ) -> Result<(Len, u64), KError> {
+        match op {
+            FileOperation::Read | FileOperation::ReadAt => {}
+            _ => return Err(KError::PermissionError),
+        }
+        if offset != -1 {
+            return Err(KError::InvalidOffset);
+        }
+        if len < core::mem::size_of::<WatchEvent>() as u64 {
+            return Err(KError::InvalidLength);
+        }
+
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
             .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let response = replica.execute_mut_scan(Modify::FileClose(pid, fd), *token);
+                let response = replica.execute(Access::Poll(pid, fd, mnode), *token);
+                match response {
+                    Ok(MlnrNodeResult::Ready) => {}
+                    Ok(MlnrNodeResult::Blocked) => return Err(KError::Restart),
+                    Err(e) => return Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
 
+                let response = replica.execute_mut(Modify::WatchRead(pid, fd, mnode, len), *token);
                 match response {
-                    Ok(MlnrNodeResult::FileClosed(_fd)) => Ok((0, 0)),
+                    Ok(MlnrNodeResult::EventsRead(events)) => {
+                        let user_ptr = UserPtr::new(&mut VAddr::from(buffer));
+                        let user_events = unsafe {
+                            core::slice::from_raw_parts_mut(
+                                user_ptr.as_mut_ptr::<WatchEvent>(),
+                                events.len(),
+                            )
+                        };
+                        user_events.copy_from_slice(&events);
+                        let read = events.len() * core::mem::size_of::<WatchEvent>();
+                        Ok((read as u64, 0))
+                    }
+                    // Another reader took the events since the poll.
+                    Ok(MlnrNodeResult::Blocked) => Err(KError::Restart),
                     Err(e) => Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
 
-    pub fn file_delete(pid: Pid, name: u64) -> Result<(u64, u64), KError> {
+    pub fn unmap_fd(pid: Pid, fd: u64) -> Result<(u64, u64), KError> {
+        // An invalid fd is reported by the close in all logs.
+        let mut mnode = MlnrKernelNode::fd_to_mnode(pid, fd)
+            .ok()
+            .map(|(mnode, _ftype)| mnode);
+
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
-            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let filename = userptr_to_str(name)?;
-                let response = replica.execute_mut_scan(Modify::FileDelete(pid, filename), *token);
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| loop {
+                let response = replica.execute_mut_scan(Modify::FileClose(pid, fd, mnode), *token);
 
                 match response {
-                    Ok(MlnrNodeResult::FileDeleted) => Ok((0, 0)),
-                    Err(e) => Err(e),
+                    Ok(MlnrNodeResult::FileClosed(_fd)) => return Ok((0, 0)),
+                    Ok(MlnrNodeResult::Reroute) => mnode = None,
+                    Err(e) => return Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
 
+    pub fn file_delete(pid: Pid, name: u64) -> Result<(u64, u64), KError> {
+        let response = MlnrKernelNode::execute_path_op(name, |filename, sharded| {
+            Modify::FileDelete(pid, filename, sharded)
+        });
+        match response {
+            Ok(MlnrNodeResult::FileDeleted) => Ok((0, 0)),
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("Got unexpected response"),
+        }
+    }
+
     pub fn file_info(pid: Pid, name: u64, info_ptr: u64) -> Result<(u64, u64), KError> {
         let (mnode, _) = MlnrKernelNode::filename_to_mnode(pid, name)?;
 
@@ -252,8 +809,34 @@ impl MlnrKernelNode {
                     Ok(MlnrNodeResult::FileInfo(f_info)) => {
                         let user_ptr = UserPtr::new(&mut VAddr::from(info_ptr));
                         unsafe {
-                            (*user_ptr.as_mut_ptr::<FileInfo>()).ftype = f_info.ftype;
-                            (*user_ptr.as_mut_ptr::<FileInfo>()).fsize = f_info.fsize;
+                            *user_ptr.as_mut_ptr::<FileInfo>() = f_info;
+                        }
+                        Ok((0, 0))
+                    }
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn file_stat(pid: Pid, fd: FD, info_ptr: u64) -> Result<(u64, u64), KError> {
+        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, _)) => mnode,
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
+
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(Access::FileStat(pid, fd, mnode, info_ptr), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::FileInfo(f_info)) => {
+                        let user_ptr = UserPtr::new(&mut VAddr::from(info_ptr));
+                        unsafe {
+                            *user_ptr.as_mut_ptr::<FileInfo>() = f_info;
                         }
                         Ok((0, 0))
                     }
@@ -283,103 +866,635 @@ impl MlnrKernelNode {
     }
 
     pub fn mkdir(pid: Pid, pathname: u64, modes: u64) -> Result<(u64, u64), KError> {
+        let mnode = next_mnode();
+        let response = MlnrKernelNode::execute_path_op(pathname, |filename, sharded| {
+            Modify::MkDir(pid, filename, modes, now(), mnode, sharded)
+        });
+        match response {
+            Ok(MlnrNodeResult::DirCreated) => Ok((0, 0)),
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("Got unexpected response"),
+        }
+    }
+
+    pub fn file_seek(pid: Pid, fd: FD, offset: i64, whence: u64) -> Result<(u64, u64), KError> {
+        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, _)) => mnode,
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
             .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let filename = userptr_to_str(pathname)?;
                 let response =
-                    replica.execute_mut_scan(Modify::MkDir(pid, filename, modes), *token);
+                    replica.execute_mut(Modify::FileSeek(pid, fd, mnode, offset, whence), *token);
 
                 match response {
-                    Ok(MlnrNodeResult::DirCreated) => Ok((0, 0)),
+                    Ok(MlnrNodeResult::FileSeeked(new_offset)) => Ok((new_offset, 0)),
                     Err(e) => Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
 
-    #[inline(always)]
-    pub fn fd_to_mnode(pid: Pid, fd: FD) -> Result<(u64, u64), KError> {
+    pub fn file_truncate(pid: Pid, fd: FD, len: u64) -> Result<(u64, u64), KError> {
+        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, _)) => mnode,
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
+        let time = now();
+        let response = MlnrKernelNode::execute_mnode_op(|sharded| {
+            Modify::FileTruncate(pid, fd, mnode, len, time, sharded)
+        });
+        match response {
+            Ok(MlnrNodeResult::FileTruncated) => Ok((0, 0)),
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("Got unexpected response"),
+        }
+    }
+
+    /// Get the physical memory regions of the local replica that hold the file
+    /// range, the holes in the range are filled on all replicas.
+    pub fn file_map(
+        pid: Pid,
+        fd: FD,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<(PAddr, usize)>, KError> {
+        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, _)) => mnode,
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
             .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let response = replica.execute(Access::FdToMnode(pid, fd), *token);
+                let response = replica
+                    .execute_mut(Modify::FileMap(pid, fd, mnode, offset as i64, len), *token);
 
                 match response {
-                    Ok(MlnrNodeResult::MappedFileToMnode(mnode)) => Ok((mnode, 0)),
+                    Ok(MlnrNodeResult::FileMapped(regions)) => Ok(regions),
                     Err(e) => Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
 
-    #[inline(always)]
-    pub fn filename_to_mnode(pid: Pid, filename: Filename) -> Result<(u64, u64), KError> {
+    pub fn file_dup(pid: Pid, fd: FD) -> Result<(u64, u64), KError> {
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
             .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let response = replica.execute(Access::FileNameToMnode(pid, filename), *token);
+                let response = replica.execute_mut_scan(Modify::FileDup(pid, fd), *token);
 
                 match response {
-                    Ok(MlnrNodeResult::MappedFileToMnode(mnode)) => Ok((mnode, 0)),
+                    Ok(MlnrNodeResult::FileDuplicated(newfd)) => Ok((newfd, 0)),
                     Err(e) => Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
 
-    pub fn synchronize_log(log_id: usize) -> Result<(u64, u64), KError> {
+    pub fn file_dup2(pid: Pid, oldfd: FD, newfd: FD) -> Result<(u64, u64), KError> {
         let kcb = super::kcb::get_kcb();
         kcb.arch
             .cnr_replica
             .as_ref()
             .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-                let response = replica.execute(Access::Synchronize(log_id), *token);
+                let response =
+                    replica.execute_mut_scan(Modify::FileDup2(pid, oldfd, newfd), *token);
+
                 match response {
-                    Ok(MlnrNodeResult::Synchronized) => Ok((0, 0)),
+                    Ok(MlnrNodeResult::FileDuplicated(newfd)) => Ok((newfd, 0)),
                     Err(e) => Err(e),
                     Ok(_) => unreachable!("Got unexpected response"),
                 }
             })
     }
-}
 
-impl Dispatch for MlnrKernelNode {
-    type ReadOperation = Access;
-    type WriteOperation = Modify;
-    type Response = Result<MlnrNodeResult, KError>;
+    /// Unpack a cpio archive (the initial ramdisk) into the file-system.
+    ///
+    /// Returns the number of files and directories that were created.
+    pub fn unpack_initrd(archive: &'static [u8]) -> Result<usize, KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let mut unpacked = 0;
+                for entry in CpioArchive::new(archive) {
+                    let entry = entry?;
+                    let name = entry.name.trim_start_matches("./").trim_start_matches('/');
+                    if name.is_empty() || name == "." {
+                        continue;
+                    }
+                    let ftype = match entry.file_type() {
+                        Some(ftype) => ftype,
+                        None => {
+                            warn!("Skip unsupported initrd entry {}", entry.name);
+                            continue;
+                        }
+                    };
+
+                    let mut pathname = String::try_with_capacity(name.len() + 1)?;
+                    pathname.try_push('/')?;
+                    pathname.try_push_str(name)?;
+                    let time = entry.mtime * 1_000_000_000;
+                    let response = replica.execute_mut_scan(
+                        Modify::Unpack(
+                            pathname,
+                            ftype,
+                            entry.modes().into(),
+                            entry.data,
+                            time,
+                            next_mnode(),
+                        ),
+                        *token,
+                    );
 
-    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
-        match op {
-            Access::FileRead(pid, fd, _mnode, buffer, len, offset) => {
-                let mut userslice = UserSlice::new(buffer, len as usize);
-                let process_lookup = self.process_map.read();
-                let p = process_lookup
-                    .get(&pid)
-                    .ok_or(KError::NoProcessFoundForPid)?;
+                    match response {
+                        Ok(MlnrNodeResult::Unpacked) => unpacked += 1,
+                        Err(e) => return Err(e),
+                        Ok(_) => unreachable!("Got unexpected response"),
+                    }
+                }
+                Ok(unpacked)
+            })
+    }
 
-                let fd = p.get_fd(fd as usize).ok_or(KError::PermissionError)?;
+    pub fn readdir(pid: Pid, pathname: u64, buffer: u64, len: u64) -> Result<(u64, u64), KError> {
+        // Entries are added and removed in the log of their directory.
+        MlnrKernelNode::synchronize_all()?;
 
-                let mnode_num = fd.get_mnode();
-                let flags = fd.get_flags();
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(Access::ReadDir(pid, pathname, buffer, len), *token);
 
-                // Check if the file has read-only or read-write permissions before reading it.
-                if !flags.is_read() {
-                    return Err(KError::PermissionError);
+                match response {
+                    Ok(MlnrNodeResult::DirRead(len)) => Ok((len, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
                 }
+            })
+    }
 
-                // If the arguments doesn't provide an offset,
-                // then use the offset associated with the FD.
-                let mut curr_offset: usize = offset as usize;
-                if offset == -1 {
-                    curr_offset = fd.get_offset();
-                }
+    #[inline(always)]
+    /// Get the mnode and the type (see `FileType`) of the file behind `fd`.
+    pub fn fd_to_mnode(pid: Pid, fd: FD) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(Access::FdToMnode(pid, fd), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::MappedFdToMnode(mnode, ftype)) => Ok((mnode, ftype)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    #[inline(always)]
+    pub fn filename_to_mnode(pid: Pid, filename: Filename) -> Result<(u64, u64), KError> {
+        let (filename, mut sharded) = shard_path(userptr_to_str(filename)?)?;
+        if !sharded {
+            MlnrKernelNode::synchronize_all()?;
+        }
+
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| loop {
+                let name = TryString::try_from(filename.as_str())?.into();
+                let response = replica.execute(Access::FileNameToMnode(pid, name, sharded), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::MappedFileToMnode(mnode)) => return Ok((mnode, 0)),
+                    Ok(MlnrNodeResult::Reroute) => {
+                        MlnrKernelNode::synchronize_all()?;
+                        sharded = false;
+                    }
+                    Err(e) => return Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn chdir(pid: Pid, pathname: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let dirname = userptr_to_str(pathname)?;
+                let response = replica.execute_mut_scan(Modify::Chdir(pid, dirname), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::DirChanged) => Ok((0, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn getcwd(pid: Pid, buffer: u64, len: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(Access::Getcwd(pid, buffer, len), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::CwdRead(len)) => Ok((len, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn file_link(pid: Pid, oldname: u64, newname: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let oldfilename = userptr_to_str(oldname)?;
+                let newfilename = userptr_to_str(newname)?;
+
+                let response = replica
+                    .execute_mut_scan(Modify::FileLink(pid, oldfilename, newfilename), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::FileLinked) => Ok((0, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn file_symlink(pid: Pid, target: u64, linkname: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let target = userptr_to_str(target)?;
+                let linkname = userptr_to_str(linkname)?;
+
+                let response = replica.execute_mut_scan(
+                    Modify::FileSymlink(pid, target, linkname, now(), next_mnode()),
+                    *token,
+                );
+
+                match response {
+                    Ok(MlnrNodeResult::FileLinked) => Ok((0, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn file_readlink(
+        pid: Pid,
+        pathname: u64,
+        buffer: u64,
+        len: u64,
+    ) -> Result<(u64, u64), KError> {
+        // Links are created and removed in the log of their directory.
+        MlnrKernelNode::synchronize_all()?;
+
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response =
+                    replica.execute(Access::ReadLink(pid, pathname, buffer, len), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::LinkRead(len)) => Ok((len, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn pipe(pid: Pid, flags: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response =
+                    replica.execute_mut_scan(Modify::Pipe(pid, flags, next_mnode()), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::PipeCreated(readfd, writefd)) => Ok((readfd, writefd)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Create an event queue for file watches, returns its file descriptor.
+    pub fn watch_init(pid: Pid, flags: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response =
+                    replica.execute_mut_scan(Modify::WatchInit(pid, flags, next_mnode()), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::WatchCreated(fd)) => Ok((fd, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Watch `pathname` for changes with the event queue `fd`, returns the
+    /// watch descriptor.
+    pub fn watch_add(pid: Pid, fd: FD, pathname: u64, events: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let filename = userptr_to_str(pathname)?;
+                let response =
+                    replica.execute_mut_scan(Modify::WatchAdd(pid, fd, filename, events), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::WatchAdded(wd)) => Ok((wd, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn watch_remove(pid: Pid, fd: FD, wd: u64) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute_mut_scan(Modify::WatchRemove(pid, fd, wd), *token);
+
+                match response {
+                    Ok(MlnrNodeResult::WatchRemoved) => Ok((0, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Take an advisory lock on a byte range of the file behind `fd`. Unless
+    /// `LOCK_NB` is set, this waits until conflicting locks are released.
+    ///
+    /// Waiting restarts the system call, the lock is only put in the log
+    /// once the range is free.
+    pub fn file_lock(
+        pid: Pid,
+        fd: FD,
+        flags: u64,
+        offset: u64,
+        len: u64,
+    ) -> Result<(u64, u64), KError> {
+        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, _)) => mnode,
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
+        let nonblock = LockFlags::from(flags).contains(LockFlags::LOCK_NB);
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                if !nonblock {
+                    let response = replica.execute(
+                        Access::LockPoll(pid, fd, mnode, flags, offset as i64, len),
+                        *token,
+                    );
+                    match response {
+                        Ok(MlnrNodeResult::Ready) => {}
+                        Ok(MlnrNodeResult::Blocked) => return Err(KError::Restart),
+                        Err(e) => return Err(e),
+                        Ok(_) => unreachable!("Got unexpected response"),
+                    }
+                }
+
+                let response = replica.execute_mut(
+                    Modify::FileLock(pid, fd, mnode, flags, offset as i64, len),
+                    *token,
+                );
+                match response {
+                    Ok(MlnrNodeResult::FileLocked) => Ok((0, 0)),
+                    // Someone else took the lock since the poll.
+                    Err(KError::WouldBlock) if !nonblock => Err(KError::Restart),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn file_unlock(pid: Pid, fd: FD, offset: u64, len: u64) -> Result<(u64, u64), KError> {
+        let mnode = match MlnrKernelNode::fd_to_mnode(pid, fd) {
+            Ok((mnode, _)) => mnode,
+            Err(_) => return Err(KError::InvalidFileDescriptor),
+        };
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute_mut(
+                    Modify::FileUnlock(pid, fd, mnode, offset as i64, len),
+                    *token,
+                );
+
+                match response {
+                    Ok(MlnrNodeResult::FileUnlocked) => Ok((0, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Get the usage of the file-system and copy it to `stats_ptr`.
+    pub fn statfs(stats_ptr: u64) -> Result<(u64, u64), KError> {
+        // Files are written through all logs, catch up with all of them so
+        // the numbers are up to date.
+        MlnrKernelNode::synchronize_all()?;
+        let nlogs = NUM_LOGS.load(Ordering::Relaxed);
+
+        let kcb = super::kcb::get_kcb();
+        let free = kcb
+            .physical_memory
+            .gmanager
+            .map_or(0, |gmanager| gmanager.free());
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(Access::FsStats, *token);
+
+                match response {
+                    Ok(MlnrNodeResult::FsStats(mut stats)) => {
+                        stats.free = free as u64;
+                        stats.logs = nlogs as u64;
+                        stats.replicas = NUM_REPLICAS.load(Ordering::Relaxed) as u64;
+                        let user_ptr = UserPtr::new(&mut VAddr::from(stats_ptr));
+                        unsafe {
+                            *user_ptr.as_mut_ptr::<FsStats>() = stats;
+                        }
+                        Ok((0, 0))
+                    }
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn synchronize_log(log_id: usize) -> Result<(u64, u64), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(Access::Synchronize(log_id), *token);
+                match response {
+                    Ok(MlnrNodeResult::Synchronized) => Ok((0, 0)),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Catch up with all logs, for reads that depend on operations in any log.
+    fn synchronize_all() -> Result<(), KError> {
+        for log_id in 1..=NUM_LOGS.load(Ordering::Relaxed) {
+            MlnrKernelNode::synchronize_log(log_id)?;
+        }
+        Ok(())
+    }
+
+    /// Execute the metadata operation `op(pathname, sharded)`. The operation
+    /// is first put in the logs picked for the path, if it can't be ordered
+    /// there it's repeated in all logs.
+    fn execute_path_op(
+        pathname: u64,
+        op: impl Fn(String, bool) -> Modify,
+    ) -> Result<MlnrNodeResult, KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let (filename, sharded) = shard_path(userptr_to_str(pathname)?)?;
+                if sharded {
+                    let name = TryString::try_from(filename.as_str())?.into();
+                    match replica.execute_mut_scan(op(name, true), *token) {
+                        Ok(MlnrNodeResult::Reroute) => {}
+                        response => return response,
+                    }
+                }
+                replica.execute_mut_scan(op(filename, false), *token)
+            })
+    }
+
+    /// Execute the operation `op(sharded)` on an mnode. The operation is
+    /// first put in the log of the mnode, if its events are watched it's
+    /// repeated in all logs.
+    fn execute_mnode_op(op: impl Fn(bool) -> Modify) -> Result<MlnrNodeResult, KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.arch
+            .cnr_replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                match replica.execute_mut(op(true), *token) {
+                    Ok(MlnrNodeResult::Reroute) => {}
+                    response => return response,
+                }
+                replica.execute_mut_scan(op(false), *token)
+            })
+    }
+}
+
+impl MlnrKernelNode {
+    /// Turn `pathname` into a normalized, absolute path; relative paths are
+    /// resolved from the working directory of `pid`.
+    fn absolute_path(&self, pid: Pid, pathname: &str) -> Result<String, KError> {
+        let process_lookup = self.process_map.read();
+        let p = process_lookup
+            .get(&pid)
+            .ok_or(KError::NoProcessFoundForPid)?;
+        normalize_path(&p.cwd, pathname)
+    }
+
+    /// Check if `event` on the mnode at `pathname` is reported to a watch of
+    /// the mnode or of the directory that holds it.
+    fn is_watched(&self, pathname: &str, mnode: Option<Mnode>, event: WatchEvents) -> bool {
+        self.fs
+            .lookup_parent(pathname)
+            .map_or(false, |parent| self.fs.is_watched(parent, event))
+            || mnode.map_or(false, |mnode| self.fs.is_watched(mnode, event))
+    }
+
+    /// Report `event` on the mnode at `pathname` to the watches of the mnode
+    /// and of the directory that holds it.
+    fn notify(&self, pathname: &str, mnode: Mnode, event: WatchEvents) {
+        if let Some(parent) = self.fs.lookup_parent(pathname) {
+            self.fs.notify(parent, event, mnode);
+        }
+        self.fs.notify(mnode, event, mnode);
+    }
+}
+
+impl Dispatch for MlnrKernelNode {
+    type ReadOperation = Access;
+    type WriteOperation = Modify;
+    type Response = Result<MlnrNodeResult, KError>;
+
+    fn dispatch(&self, op: Self::ReadOperation) -> Self::Response {
+        match op {
+            Access::FileRead(pid, fd, mnode, buffer, len, offset) => {
+                let mut userslice = UserSlice::new(buffer, len as usize);
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+
+                let fd = p.get_fd(fd, mnode)?;
+
+                let mnode_num = fd.get_mnode();
+                let flags = fd.get_flags();
+
+                // Check if the file has read-only or read-write permissions before reading it.
+                if !flags.is_read() {
+                    return Err(KError::PermissionError);
+                }
+                if offset < -1 {
+                    return Err(KError::InvalidOffset);
+                }
+
+                // If the arguments doesn't provide an offset,
+                // then use the offset associated with the FD.
+                let mut curr_offset: usize = offset as usize;
+                if offset == -1 {
+                    curr_offset = fd.get_offset();
+                }
 
                 match self.fs.read(mnode_num, &mut userslice, curr_offset) {
                     Ok(len) => {
@@ -395,38 +1510,43 @@ impl Dispatch for MlnrKernelNode {
             }
 
             Access::FileInfo(pid, name, _mnode, _info_ptr) => {
-                let _p = self
-                    .process_map
-                    .read()
-                    .get(&pid)
-                    .ok_or(KError::NoProcessFoundForPid)?;
-
-                let filename = userptr_to_str(name)?;
+                let filename = self.absolute_path(pid, &userptr_to_str(name)?)?;
                 let mnode = self.fs.lookup(&filename).ok_or(KError::InvalidFile)?;
 
                 let f_info = self.fs.file_info(*mnode);
                 Ok(MlnrNodeResult::FileInfo(f_info))
             }
 
+            Access::FileStat(pid, fd, mnode, _info_ptr) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+
+                let fd = p.get_fd(fd, mnode)?;
+                let f_info = self.fs.file_info(fd.get_mnode());
+                Ok(MlnrNodeResult::FileInfo(f_info))
+            }
+
             Access::FdToMnode(pid, fd) => {
                 let process_map_locked = self.process_map.read();
                 let p = process_map_locked
                     .get(&pid)
                     .ok_or(KError::NoProcessFoundForPid)?;
 
-                let fd = p.get_fd(fd as usize).ok_or(KError::PermissionError)?;
+                let fd = p.fds.get_fd(fd as usize).ok_or(KError::PermissionError)?;
                 let mnode_num = fd.get_mnode();
-                Ok(MlnrNodeResult::MappedFileToMnode(mnode_num))
+                let ftype = self.fs.file_info(mnode_num).ftype;
+                Ok(MlnrNodeResult::MappedFdToMnode(mnode_num, ftype))
             }
 
-            Access::FileNameToMnode(pid, name) => {
-                let _p = self
-                    .process_map
-                    .read()
-                    .get(&pid)
-                    .ok_or(KError::NoProcessFoundForPid)?;
-
-                let filename = userptr_to_str(name)?;
+            Access::FileNameToMnode(pid, name, sharded) => {
+                let filename = self.absolute_path(pid, &name)?;
+                // The log of the directory doesn't order changes to the
+                // directory the link leads to.
+                if sharded && self.fs.crosses_symlink(&filename, true) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
 
                 match self.fs.lookup(&filename) {
                     // match on (file_exists, mnode_number)
@@ -435,6 +1555,78 @@ impl Dispatch for MlnrKernelNode {
                 }
             }
 
+            Access::ReadDir(pid, name, buffer, len) => {
+                let dirname = self.absolute_path(pid, &userptr_to_str(name)?)?;
+                let mut userslice = UserSlice::new(buffer, len as usize);
+                let len = self.fs.readdir(&dirname, &mut userslice)?;
+                Ok(MlnrNodeResult::DirRead(len as u64))
+            }
+
+            Access::Getcwd(pid, buffer, len) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+
+                // Like `ReadDir`, only copy the path (and a terminating NUL) if
+                // it fits in the buffer but always return its length.
+                let mut userslice = UserSlice::new(buffer, len as usize);
+                let cwd = p.cwd.as_bytes();
+                if cwd.len() < userslice.len() {
+                    userslice[..cwd.len()].copy_from_slice(cwd);
+                    userslice[cwd.len()] = 0;
+                }
+                Ok(MlnrNodeResult::CwdRead(cwd.len() as u64))
+            }
+
+            Access::ReadLink(pid, name, buffer, len) => {
+                let linkname = self.absolute_path(pid, &userptr_to_str(name)?)?;
+                let mut userslice = UserSlice::new(buffer, len as usize);
+                let len = self.fs.readlink(&linkname, &mut userslice)?;
+                Ok(MlnrNodeResult::LinkRead(len as u64))
+            }
+
+            Access::FsStats => {
+                let (mnodes, used) = self.fs.usage();
+                Ok(MlnrNodeResult::FsStats(FsStats {
+                    mnodes: mnodes as u64,
+                    used: used as u64,
+                    ..Default::default()
+                }))
+            }
+
+            Access::Poll(pid, fd, mnode) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+
+                let flags = fd.get_flags();
+                match self.fs.is_ready(fd.get_mnode(), flags)? {
+                    true => Ok(MlnrNodeResult::Ready),
+                    false if flags.is_nonblock() => Err(KError::WouldBlock),
+                    false => Ok(MlnrNodeResult::Blocked),
+                }
+            }
+
+            Access::LockPoll(pid, fd, mnode, flags, offset, len) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+                let kind = lock_kind(fd, flags, offset)?;
+
+                match self
+                    .fs
+                    .can_lock(fd.get_mnode(), pid, kind, offset as u64, len)?
+                {
+                    true => Ok(MlnrNodeResult::Ready),
+                    false => Ok(MlnrNodeResult::Blocked),
+                }
+            }
+
             Access::Synchronize(_log_id) => {
                 // A NOP that just makes sure we've advanced the replica
                 Ok(MlnrNodeResult::Synchronized)
@@ -447,58 +1639,123 @@ impl Dispatch for MlnrKernelNode {
             Modify::ProcessAdd(pid) => {
                 let mut pmap = self.process_map.write();
                 pmap.try_reserve(1)?;
-                pmap.try_insert(pid, FileDesc::default())
+                pmap.try_insert(pid, FsContext::new()?)
                     .map_err(|_e| KError::FileDescForPidAlreadyAdded)?;
                 Ok(MlnrNodeResult::ProcessAdded(pid))
             }
 
             Modify::ProcessRemove(pid) => {
                 let mut pmap = self.process_map.write();
-                let _file_desc = pmap.remove(&pid).ok_or(KError::NoFileDescForPid)?;
-                Ok(MlnrNodeResult::ProcessRemoved(pid))
+                let mut ctx = pmap.remove(&pid).ok_or(KError::NoFileDescForPid)?;
+                // Release everything, even if some of it fails, and report the
+                // first error.
+                let mut result = Ok(());
+                for (mnode, flags) in ctx.fds.deallocate_all() {
+                    let r = self.fs.unlock_all(mnode, pid);
+                    result = result.and(r).and(self.fs.release(mnode, flags));
+                }
+                // The process no longer runs, its mappings are gone.
+                for (mnode, offset, len) in ctx.mappings.drain(..) {
+                    let r = self.fs.unmap_regions(mnode, offset, len);
+                    result = result
+                        .and(r)
+                        .and(self.fs.release(mnode, FileFlags::O_RDONLY));
+                }
+                result.map(|_| MlnrNodeResult::ProcessRemoved(pid))
             }
 
-            Modify::FileOpen(pid, filename, flags, modes) => {
+            Modify::FileOpen(pid, filename, flags, modes, time, new_mnode, expected, sharded) => {
+                let filename = self.absolute_path(pid, &filename)?;
+                // The log of the directory doesn't order changes to the
+                // directory the link leads to.
+                if sharded && self.fs.crosses_symlink(&filename, true) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
                 let flags = FileFlags::from(flags);
                 let mnode = self.fs.lookup(&filename);
+                // The file changed since the caller picked the logs.
+                if sharded && mnode.as_ref().map(|mnode| **mnode) != expected {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
                 if mnode.is_none() && !flags.is_create() {
                     return Err(KError::PermissionError);
                 }
+                // The existence check and the create below happen in the same log
+                // entry, so O_EXCL is decided by the log order on every replica.
+                if mnode.is_some() && flags.is_create() && flags.is_excl() {
+                    return Err(KError::AlreadyPresent);
+                }
+                // Truncating a file requires write access to it.
+                if mnode.is_some() && flags.is_truncate() && !flags.is_write() {
+                    return Err(KError::PermissionError);
+                }
+                // Events are ordered in all logs.
+                if sharded
+                    && mnode.is_none()
+                    && self.is_watched(&filename, None, WatchEvents::IN_CREATE)
+                {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
 
                 let mut pmap = self.process_map.write();
                 let p = pmap
                     .get_mut(&pid)
                     .expect("TODO: FileOpen process lookup failed");
-                let (fid, fd) = p.allocate_fd().ok_or(KError::NotSupported)?;
+                let (fid, fd) = p.fds.allocate_fd()?;
 
                 let mnode_num;
                 if let Some(mnode) = mnode {
                     // File exists and FileOpen is called with O_TRUNC flag.
                     if flags.is_truncate() {
-                        assert!(self.fs.truncate(&filename).is_ok());
+                        if let Err(e) = self.fs.truncate(*mnode, 0) {
+                            let fdesc = fid as usize;
+                            let _ = pmap.get_mut(&pid).unwrap().fds.deallocate_fd(fdesc)?;
+                            return Err(e);
+                        }
+                        self.fs.update_time(*mnode, TimeUpdate::Modified, time)?;
+                        self.fs.notify(*mnode, WatchEvents::IN_MODIFY, *mnode);
                     }
                     mnode_num = *mnode;
                 } else {
-                    match self.fs.create(&filename, modes) {
-                        Ok(m_num) => mnode_num = m_num,
+                    // The mnode number is only picked if O_CREAT is set.
+                    let created = new_mnode.ok_or(KError::PermissionError).and_then(|m_num| {
+                        self.fs
+                            .create_mnode(&filename, modes, FileType::File, Some(m_num))
+                    });
+                    match created {
+                        Ok(m_num) => {
+                            mnode_num = m_num;
+                            self.fs.update_time(m_num, TimeUpdate::Created, time)?;
+                            self.notify(&filename, m_num, WatchEvents::IN_CREATE);
+                        }
                         Err(e) => {
                             let fdesc = fid as usize;
-                            pmap.get_mut(&pid).unwrap().deallocate_fd(fdesc)?;
+                            let _ = pmap.get_mut(&pid).unwrap().fds.deallocate_fd(fdesc)?;
                             return Err(e);
                         }
                     }
                 }
 
+                // Keeps the mnode alive until the file is closed, even if it's
+                // deleted in the meantime.
+                if let Err(e) = self.fs.acquire(mnode_num, flags) {
+                    let _ = pmap
+                        .get_mut(&pid)
+                        .unwrap()
+                        .fds
+                        .deallocate_fd(fid as usize)?;
+                    return Err(e);
+                }
                 fd.update_fd(mnode_num, flags);
                 Ok(MlnrNodeResult::FileOpened(fid))
             }
 
-            Modify::FileWrite(pid, fd, _mnode, kernslice, _len, offset) => {
+            Modify::FileWrite(pid, fd, mnode, kernslice, _len, offset, time, sharded) => {
                 let process_lookup = self.process_map.read();
                 let p = process_lookup
                     .get(&pid)
                     .expect("TODO: FileWrite process lookup failed");
-                let fd = p.get_fd(fd as usize).ok_or(KError::PermissionError)?;
+                let fd = p.get_fd(fd, mnode)?;
 
                 let mnode_num = fd.get_mnode();
                 let flags = fd.get_flags();
@@ -508,10 +1765,35 @@ impl Dispatch for MlnrKernelNode {
                     return Err(KError::PermissionError);
                 }
 
+                // Pipes have no offset, the data is appended to the pipe.
+                if self.fs.is_pipe(mnode_num) {
+                    return match self.fs.write(mnode_num, &kernslice, 0) {
+                        Ok(len) => {
+                            self.fs.update_time(mnode_num, TimeUpdate::Modified, time)?;
+                            Ok(MlnrNodeResult::FileAccessed(len as u64))
+                        }
+                        Err(KError::WouldBlock) if !flags.is_nonblock() => {
+                            Ok(MlnrNodeResult::Blocked)
+                        }
+                        Err(e) => Err(e),
+                    };
+                }
+
+                if offset < -1 {
+                    return Err(KError::InvalidOffset);
+                }
+
+                // Events are ordered in all logs.
+                if sharded && self.fs.is_watched(mnode_num, WatchEvents::IN_MODIFY) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
+
                 let mut curr_offset: usize = offset as usize;
                 if offset == -1 {
                     if flags.is_append() {
                         // If offset value is not provided and file is opened with O_APPEND flag.
+                        // The file size is read while applying the log entry, so concurrent
+                        // appends are ordered by the log and never overwrite each other.
                         let finfo = self.fs.file_info(mnode_num);
                         curr_offset = finfo.fsize as usize;
                     } else {
@@ -526,49 +1808,377 @@ impl Dispatch for MlnrKernelNode {
                             // Update offset when FileWrite doesn't give an explicit offset value.
                             fd.update_offset(curr_offset + len);
                         }
+                        self.fs.update_time(mnode_num, TimeUpdate::Modified, time)?;
+                        self.fs.notify(mnode_num, WatchEvents::IN_MODIFY, mnode_num);
                         Ok(MlnrNodeResult::FileAccessed(len as u64))
                     }
                     Err(e) => Err(e),
                 }
             }
 
-            Modify::FileClose(pid, fd) => {
+            Modify::FileSeek(pid, fd, mnode, offset, whence) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+                if self.fs.is_pipe(fd.get_mnode()) || self.fs.is_watch(fd.get_mnode()) {
+                    return Err(KError::InvalidOffset);
+                }
+
+                let base = match SeekWhence::from(whence) {
+                    SeekWhence::Set => 0,
+                    SeekWhence::Current => fd.get_offset() as i64,
+                    SeekWhence::End => self.fs.file_info(fd.get_mnode()).fsize as i64,
+                    SeekWhence::Unknown => return Err(KError::InvalidFlags),
+                };
+
+                // The resulting offset can't be negative; it can be past EOF though.
+                let new_offset = base
+                    .checked_add(offset)
+                    .filter(|new_offset| *new_offset >= 0)
+                    .ok_or(KError::InvalidOffset)?;
+                fd.update_offset(new_offset as usize);
+                Ok(MlnrNodeResult::FileSeeked(new_offset as u64))
+            }
+
+            Modify::FileTruncate(pid, fd, mnode, len, time, sharded) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+
+                // Check if the file has write-only or read-write permissions before truncating it.
+                if !fd.get_flags().is_write() {
+                    return Err(KError::PermissionError);
+                }
+
+                let mnode_num = fd.get_mnode();
+                if sharded && self.fs.is_watched(mnode_num, WatchEvents::IN_MODIFY) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
+                self.fs.truncate(mnode_num, len as usize)?;
+                self.fs.update_time(mnode_num, TimeUpdate::Modified, time)?;
+                self.fs.notify(mnode_num, WatchEvents::IN_MODIFY, mnode_num);
+                Ok(MlnrNodeResult::FileTruncated)
+            }
+
+            Modify::FileClose(pid, fd, mnode) => {
                 let mut process_lookup = self.process_map.write();
                 let p = process_lookup
                     .get_mut(&pid)
                     .expect("TODO: FileClose process lookup failed");
-                p.deallocate_fd(fd as usize)?;
+                // The fd was reopened after the caller looked up its mnode.
+                if mnode.is_some() && p.fds.get_fd(fd as usize).map(|fd| fd.get_mnode()) != mnode {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
+                // Like with `fcntl`, closing any fd of a file drops the locks
+                // the process holds on it.
+                if let Some(fdesc) = p.fds.get_fd(fd as usize) {
+                    self.fs.unlock_all(fdesc.get_mnode(), pid)?;
+                }
+                if let Some((mnode, flags)) = p.fds.deallocate_fd(fd as usize)? {
+                    self.fs.release(mnode, flags)?;
+                }
                 Ok(MlnrNodeResult::FileClosed(fd))
             }
 
-            Modify::FileDelete(pid, filename) => {
-                let _p = self
-                    .process_map
-                    .read()
-                    .get(&pid)
+            Modify::FileDup(pid, fd) => {
+                let mut process_lookup = self.process_map.write();
+                let p = process_lookup
+                    .get_mut(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let newfd = p.fds.dup(fd as usize)?;
+                Ok(MlnrNodeResult::FileDuplicated(newfd))
+            }
+
+            Modify::FileDup2(pid, oldfd, newfd) => {
+                let mut process_lookup = self.process_map.write();
+                let p = process_lookup
+                    .get_mut(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let replaced = match oldfd == newfd {
+                    true => None,
+                    false => p.fds.get_fd(newfd as usize).map(|fd| fd.get_mnode()),
+                };
+                let (newfd, closed) = p.fds.dup2(oldfd as usize, newfd as usize)?;
+                // Replacing `newfd` closes it, which drops the locks on its file.
+                if let Some(mnode) = replaced {
+                    self.fs.unlock_all(mnode, pid)?;
+                }
+                if let Some((mnode, flags)) = closed {
+                    self.fs.release(mnode, flags)?;
+                }
+                Ok(MlnrNodeResult::FileDuplicated(newfd))
+            }
+
+            Modify::FileMap(pid, fd, mnode, offset, len) => {
+                let mut process_lookup = self.process_map.write();
+                let p = process_lookup
+                    .get_mut(&pid)
                     .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+
+                // Check if the file has read-only or read-write permissions before mapping it.
+                if !fd.get_flags().is_read() {
+                    return Err(KError::PermissionError);
+                }
+
+                let mnode_num = fd.get_mnode();
+                FallibleVec::try_reserve(&mut p.mappings, 1)?;
+                let regions = self
+                    .fs
+                    .map_regions(mnode_num, offset as usize, len as usize)?;
+                // The mapping keeps the file open, so it outlives its last link.
+                self.fs.acquire(mnode_num, FileFlags::O_RDONLY)?;
+                p.mappings.push((mnode_num, offset as usize, len as usize));
+                Ok(MlnrNodeResult::FileMapped(regions))
+            }
+
+            Modify::FileDelete(pid, filename, sharded) => {
+                let filename = self.absolute_path(pid, &filename)?;
+                if sharded && self.fs.crosses_symlink(&filename, false) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
+                // The entry is gone afterwards, so look it up beforehand.
+                let mnode = self.fs.lookup_nofollow(&filename);
+                let parent = self.fs.lookup_parent(&filename);
+                // Events are ordered in all logs.
+                if sharded && self.is_watched(&filename, mnode, WatchEvents::IN_DELETE) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
                 let _is_deleted = self.fs.delete(&filename)?;
+                if let Some(mnode) = mnode {
+                    if let Some(parent) = parent {
+                        self.fs.notify(parent, WatchEvents::IN_DELETE, mnode);
+                    }
+                    self.fs.notify(mnode, WatchEvents::IN_DELETE, mnode);
+                }
                 Ok(MlnrNodeResult::FileDeleted)
             }
 
             Modify::FileRename(pid, oldname, newname) => {
-                let _p = self
-                    .process_map
-                    .read()
-                    .get(&pid)
-                    .ok_or(KError::NoProcessFoundForPid)?;
+                let oldname = self.absolute_path(pid, &oldname)?;
+                let newname = self.absolute_path(pid, &newname)?;
+                let old_parent = self.fs.lookup_parent(&oldname);
                 let _is_renamed = self.fs.rename(&oldname, &newname)?;
+                if let Some(mnode) = self.fs.lookup_nofollow(&newname) {
+                    self.notify(&newname, mnode, WatchEvents::IN_RENAME);
+                    // Moving an entry to another directory changes both directories.
+                    match old_parent {
+                        Some(parent) if old_parent != self.fs.lookup_parent(&newname) => {
+                            self.fs.notify(parent, WatchEvents::IN_RENAME, mnode)
+                        }
+                        _ => {}
+                    }
+                }
                 Ok(MlnrNodeResult::FileRenamed)
             }
 
-            Modify::MkDir(pid, filename, modes) => {
-                let _p = self
-                    .process_map
-                    .read()
+            Modify::MkDir(pid, filename, modes, time, mnode, sharded) => {
+                let filename = self.absolute_path(pid, &filename)?;
+                if sharded && self.fs.crosses_symlink(&filename, false) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
+                // Events are ordered in all logs.
+                if sharded && self.is_watched(&filename, None, WatchEvents::IN_CREATE) {
+                    return Ok(MlnrNodeResult::Reroute);
+                }
+                let mnode =
+                    self.fs
+                        .create_mnode(&filename, modes, FileType::Directory, Some(mnode))?;
+                self.fs.update_time(mnode, TimeUpdate::Created, time)?;
+                self.notify(&filename, mnode, WatchEvents::IN_CREATE);
+                Ok(MlnrNodeResult::DirCreated)
+            }
+
+            Modify::Chdir(pid, dirname) => {
+                let dirname = self.absolute_path(pid, &dirname)?;
+                let mnode = self.fs.lookup(&dirname).ok_or(KError::InvalidFile)?;
+                if self.fs.file_info(*mnode).ftype != FileType::Directory.into() {
+                    return Err(KError::DirectoryError);
+                }
+
+                let mut pmap = self.process_map.write();
+                let p = pmap.get_mut(&pid).ok_or(KError::NoProcessFoundForPid)?;
+                p.cwd = dirname;
+                Ok(MlnrNodeResult::DirChanged)
+            }
+
+            Modify::FileLink(pid, oldname, newname) => {
+                let oldname = self.absolute_path(pid, &oldname)?;
+                let newname = self.absolute_path(pid, &newname)?;
+                self.fs.link(&oldname, &newname)?;
+                if let Some(mnode) = self.fs.lookup_nofollow(&newname) {
+                    self.notify(&newname, mnode, WatchEvents::IN_CREATE);
+                }
+                Ok(MlnrNodeResult::FileLinked)
+            }
+
+            Modify::FileSymlink(pid, target, linkname, time, mnode) => {
+                // The target is stored as given, relative targets are resolved
+                // from the directory of the link when it is followed.
+                let linkname = self.absolute_path(pid, &linkname)?;
+                let mnode = self.fs.create_symlink(&target, &linkname, Some(mnode))?;
+                self.fs.update_time(mnode, TimeUpdate::Created, time)?;
+                self.notify(&linkname, mnode, WatchEvents::IN_CREATE);
+                Ok(MlnrNodeResult::FileLinked)
+            }
+
+            Modify::Unpack(pathname, ftype, modes, data, time, mnode) => {
+                let mnode = self.fs.unpack(&pathname, modes, ftype, data, mnode)?;
+                self.fs.update_time(mnode, TimeUpdate::Created, time)?;
+                Ok(MlnrNodeResult::Unpacked)
+            }
+
+            Modify::Pipe(pid, flags, mnode_num) => {
+                let nonblock = FileFlags::from(flags) & FileFlags::O_NONBLOCK;
+                let read_flags = FileFlags::O_RDONLY | nonblock;
+                let write_flags = FileFlags::O_WRONLY | nonblock;
+
+                let mut pmap = self.process_map.write();
+                let p = pmap.get_mut(&pid).ok_or(KError::NoProcessFoundForPid)?;
+                self.fs.pipe(mnode_num)?;
+                self.fs.acquire(mnode_num, read_flags)?;
+                self.fs.acquire(mnode_num, write_flags)?;
+
+                let readfd = p.fds.allocate_fd().map(|(fid, fd)| {
+                    fd.update_fd(mnode_num, read_flags);
+                    fid
+                });
+                let fds = readfd.and_then(|readfd| match p.fds.allocate_fd() {
+                    Ok((writefd, fd)) => {
+                        fd.update_fd(mnode_num, write_flags);
+                        Ok((readfd, writefd))
+                    }
+                    Err(e) => {
+                        let _ = p.fds.deallocate_fd(readfd as usize)?;
+                        Err(e)
+                    }
+                });
+
+                match fds {
+                    Ok((readfd, writefd)) => Ok(MlnrNodeResult::PipeCreated(readfd, writefd)),
+                    Err(e) => {
+                        // Removes the pipe again.
+                        self.fs.release(mnode_num, read_flags)?;
+                        self.fs.release(mnode_num, write_flags)?;
+                        Err(e)
+                    }
+                }
+            }
+
+            Modify::FileLock(pid, fd, mnode, flags, offset, len) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
                     .get(&pid)
                     .ok_or(KError::NoProcessFoundForPid)?;
-                let _is_created = self.fs.mkdir(&filename, modes)?;
-                Ok(MlnrNodeResult::DirCreated)
+                let fd = p.get_fd(fd, mnode)?;
+                let kind = lock_kind(fd, flags, offset)?;
+
+                self.fs
+                    .lock(fd.get_mnode(), pid, kind, offset as u64, len)?;
+                Ok(MlnrNodeResult::FileLocked)
+            }
+
+            Modify::FileUnlock(pid, fd, mnode, offset, len) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+                if offset < 0 {
+                    return Err(KError::InvalidOffset);
+                }
+
+                self.fs.unlock(fd.get_mnode(), pid, offset as u64, len)?;
+                Ok(MlnrNodeResult::FileUnlocked)
+            }
+
+            Modify::WatchInit(pid, flags, mnode_num) => {
+                let flags = FileFlags::O_RDONLY | (FileFlags::from(flags) & FileFlags::O_NONBLOCK);
+
+                let mut pmap = self.process_map.write();
+                let p = pmap.get_mut(&pid).ok_or(KError::NoProcessFoundForPid)?;
+                self.fs.watch_init(mnode_num)?;
+                self.fs.acquire(mnode_num, flags)?;
+
+                match p.fds.allocate_fd() {
+                    Ok((fid, fd)) => {
+                        fd.update_fd(mnode_num, flags);
+                        Ok(MlnrNodeResult::WatchCreated(fid))
+                    }
+                    Err(e) => {
+                        // Removes the event queue again.
+                        self.fs.release(mnode_num, flags)?;
+                        Err(e)
+                    }
+                }
+            }
+
+            Modify::WatchAdd(pid, fd, filename, events) => {
+                let filename = self.absolute_path(pid, &filename)?;
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.fds.get_fd(fd as usize).ok_or(KError::PermissionError)?;
+
+                let wd = self
+                    .fs
+                    .watch_add(fd.get_mnode(), &filename, WatchEvents::from(events))?;
+                Ok(MlnrNodeResult::WatchAdded(wd))
+            }
+
+            Modify::WatchRemove(pid, fd, wd) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.fds.get_fd(fd as usize).ok_or(KError::PermissionError)?;
+
+                self.fs.watch_remove(fd.get_mnode(), wd)?;
+                Ok(MlnrNodeResult::WatchRemoved)
+            }
+
+            Modify::WatchRead(pid, fd, mnode, len) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+
+                let flags = fd.get_flags();
+                if !flags.is_read() {
+                    return Err(KError::PermissionError);
+                }
+
+                let count = len as usize / core::mem::size_of::<WatchEvent>();
+                match self.fs.read_events(fd.get_mnode(), count) {
+                    Ok(events) => Ok(MlnrNodeResult::EventsRead(events)),
+                    Err(KError::WouldBlock) if !flags.is_nonblock() => Ok(MlnrNodeResult::Blocked),
+                    Err(e) => Err(e),
+                }
+            }
+
+            Modify::PipeRead(pid, fd, mnode, len) => {
+                let process_lookup = self.process_map.read();
+                let p = process_lookup
+                    .get(&pid)
+                    .ok_or(KError::NoProcessFoundForPid)?;
+                let fd = p.get_fd(fd, mnode)?;
+
+                let flags = fd.get_flags();
+                if !flags.is_read() {
+                    return Err(KError::PermissionError);
+                }
+
+                match self.fs.pipe_read(fd.get_mnode(), len as usize) {
+                    Ok(data) => Ok(MlnrNodeResult::PipeRead(data)),
+                    Err(KError::WouldBlock) if !flags.is_nonblock() => Ok(MlnrNodeResult::Blocked),
+                    Err(e) => Err(e),
+                }
             }
         }
     }
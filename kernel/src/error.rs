@@ -74,6 +74,11 @@ pub enum KError {
     OpenFileLimit,
     FileDescForPidAlreadyAdded,
     NoFileDescForPid,
+    InvalidArchive,
+    SymlinkLoop,
+    WouldBlock,
+    BrokenPipe,
+    Restart,
 }
 
 impl From<CapacityError<crate::memory::Frame>> for KError {
@@ -134,6 +139,8 @@ impl From<KError> for SystemCallError {
             KError::InvalidVSpaceOperation { .. } => SystemCallError::NotSupported,
             KError::InvalidProcessOperation { .. } => SystemCallError::NotSupported,
             KError::BadAddress { .. } => SystemCallError::BadAddress,
+            KError::AlreadyPresent => SystemCallError::AlreadyExists,
+            KError::WouldBlock => SystemCallError::WouldBlock,
             _ => SystemCallError::InternalError,
         }
     }
@@ -204,6 +211,11 @@ impl fmt::Display for KError {
                 write!(f, "PID is already stored in scheduler state")
             }
             KError::NoFileDescForPid => write!(f, "No file-descriptors found for Pid"),
+            KError::InvalidArchive => write!(f, "The initial ramdisk is not a valid cpio archive"),
+            KError::SymlinkLoop => write!(f, "Too many levels of symbolic links"),
+            KError::WouldBlock => write!(f, "The operation would block"),
+            KError::BrokenPipe => write!(f, "The pipe has no reader left"),
+            KError::Restart => write!(f, "The system call has to be restarted"),
 
             KError::ProcessCreate  => write!(f, "Unable to create process"),
             KError::NoProcessFoundForPid => write!(f, "No process was associated with the given Pid."),
@@ -0,0 +1,205 @@
+// Copyright © 2021 VMware, Inc. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! A parser for archives in the cpio "newc" format, which is used for the
+//! initial ramdisk (e.g., `find . | cpio -o -H newc > initrd.cpio`).
+
+use kpi::io::{FileModes, FileType};
+
+use crate::error::KError;
+
+/// Magic number at the start of every newc header.
+const NEWC_MAGIC: &[u8] = b"070701";
+
+/// Size of a newc header; the magic followed by 13 8-digit hex fields.
+const NEWC_HEADER_LEN: usize = 110;
+
+/// Name of the entry that marks the end of the archive.
+const TRAILER: &str = "TRAILER!!!";
+
+/// File type bits in the mode field.
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+
+/// A file or directory stored in the archive.
+#[derive(Debug, PartialEq)]
+pub struct Entry<'a> {
+    /// Path of the entry, relative to the archive root.
+    pub name: &'a str,
+    /// File type and permission bits as stored in the archive.
+    pub mode: u32,
+    /// Modification time in seconds since the unix epoch.
+    pub mtime: u64,
+    /// Content of the entry.
+    pub data: &'a [u8],
+}
+
+impl<'a> Entry<'a> {
+    /// Type of the entry, or None for types the file-system doesn't support.
+    pub fn file_type(&self) -> Option<FileType> {
+        match self.mode & S_IFMT {
+            S_IFDIR => Some(FileType::Directory),
+            S_IFREG => Some(FileType::File),
+            _ => None,
+        }
+    }
+
+    /// Access rights of the owner of the entry.
+    pub fn modes(&self) -> FileModes {
+        FileModes::from(((self.mode >> 6) & 0o7) as u64)
+    }
+}
+
+/// Iterates over all entries of a newc archive.
+pub struct CpioArchive<'a> {
+    archive: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CpioArchive<'a> {
+    pub fn new(archive: &'a [u8]) -> CpioArchive<'a> {
+        CpioArchive { archive, offset: 0 }
+    }
+
+    /// Parse the entry at the current offset and advance past it.
+    fn parse_entry(&mut self) -> Result<Entry<'a>, KError> {
+        let header = self
+            .archive
+            .get(self.offset..self.offset + NEWC_HEADER_LEN)
+            .ok_or(KError::InvalidArchive)?;
+        if &header[..NEWC_MAGIC.len()] != NEWC_MAGIC {
+            return Err(KError::InvalidArchive);
+        }
+
+        let mode = header_field(header, 1)? as u32;
+        let mtime = header_field(header, 5)? as u64;
+        let filesize = header_field(header, 6)?;
+        let namesize = header_field(header, 11)?;
+
+        // The name is NUL terminated, the name and the data are padded to 4 bytes.
+        let name_start = self.offset + NEWC_HEADER_LEN;
+        let name = self
+            .archive
+            .get(name_start..name_start + namesize.saturating_sub(1))
+            .ok_or(KError::InvalidArchive)?;
+        let name = core::str::from_utf8(name).map_err(|_e| KError::InvalidArchive)?;
+
+        let data_start = align4(name_start + namesize);
+        let data = self
+            .archive
+            .get(data_start..data_start + filesize)
+            .ok_or(KError::InvalidArchive)?;
+
+        self.offset = align4(data_start + filesize);
+        Ok(Entry {
+            name,
+            mode,
+            mtime,
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for CpioArchive<'a> {
+    type Item = Result<Entry<'a>, KError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.archive.len() {
+            return None;
+        }
+
+        match self.parse_entry() {
+            Ok(entry) if entry.name == TRAILER => {
+                self.offset = self.archive.len();
+                None
+            }
+            Ok(entry) => Some(Ok(entry)),
+            Err(e) => {
+                // Stop after the first malformed entry.
+                self.offset = self.archive.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Read the `idx`-th hex field of a newc header.
+fn header_field(header: &[u8], idx: usize) -> Result<usize, KError> {
+    let start = NEWC_MAGIC.len() + idx * 8;
+    let field =
+        core::str::from_utf8(&header[start..start + 8]).map_err(|_e| KError::InvalidArchive)?;
+    usize::from_str_radix(field, 16).map_err(|_e| KError::InvalidArchive)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use alloc::format;
+    use alloc::vec::Vec;
+
+    /// Append a newc entry to `archive`.
+    fn add_entry(archive: &mut Vec<u8>, name: &str, mode: u32, data: &[u8]) {
+        let header = format!(
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            1,
+            mode,
+            0,
+            0,
+            1,
+            1234,
+            data.len(),
+            0,
+            0,
+            0,
+            0,
+            name.len() + 1,
+            0
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(name.as_bytes());
+        archive.push(0);
+        archive.resize(align4(archive.len()), 0);
+        archive.extend_from_slice(data);
+        archive.resize(align4(archive.len()), 0);
+    }
+
+    #[test]
+    /// Parse an archive with a directory and a file in it.
+    fn test_parse_archive() {
+        let mut archive = Vec::new();
+        add_entry(&mut archive, "etc", S_IFDIR | 0o755, &[]);
+        add_entry(&mut archive, "etc/config", S_IFREG | 0o644, b"threads=4\n");
+        add_entry(&mut archive, TRAILER, 0, &[]);
+
+        let entries: Vec<Entry> = CpioArchive::new(&archive).map(|e| e.unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "etc");
+        assert_eq!(entries[0].file_type(), Some(FileType::Directory));
+        assert_eq!(entries[0].modes(), FileModes::S_IRWXU);
+        assert_eq!(entries[1].name, "etc/config");
+        assert_eq!(entries[1].file_type(), Some(FileType::File));
+        assert_eq!(entries[1].modes(), FileModes::S_IRUSR | FileModes::S_IWUSR);
+        assert_eq!(entries[1].mtime, 1234);
+        assert_eq!(entries[1].data, b"threads=4\n");
+    }
+
+    #[test]
+    /// A truncated or corrupt archive results in an error.
+    fn test_parse_invalid_archive() {
+        let mut archive = Vec::new();
+        add_entry(&mut archive, "file", S_IFREG | 0o644, b"content");
+
+        let mut entries = CpioArchive::new(&archive[..archive.len() - 4]);
+        assert_eq!(entries.next(), Some(Err(KError::InvalidArchive)));
+        assert_eq!(entries.next(), None);
+
+        archive[0] = b'1';
+        let mut entries = CpioArchive::new(&archive);
+        assert_eq!(entries.next(), Some(Err(KError::InvalidArchive)));
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright © 2021 VMware, Inc. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::convert::TryFrom;
+
+use hashbrown::HashMap;
+use kpi::io::*;
+
+use crate::error::KError;
+use crate::fallible_string::TryString;
+
+use super::{Mnode, Modes};
+
+#[derive(Debug, PartialEq)]
+/// A directory maps the names of its entries to their mnode numbers.
+pub struct Directory {
+    entries: HashMap<String, Arc<Mnode>>,
+    modes: FileModes,
+}
+
+impl Directory {
+    /// Initialize an empty directory.
+    pub fn new(modes: Modes) -> Directory {
+        Directory {
+            entries: HashMap::new(),
+            modes: FileModes::from(modes),
+        }
+    }
+
+    /// Get the modes of the directory.
+    pub fn get_mode(&self) -> FileModes {
+        self.modes
+    }
+
+    /// Find the mnode number for the entry `name`.
+    pub fn lookup(&self, name: &str) -> Option<&Arc<Mnode>> {
+        self.entries.get(name)
+    }
+
+    /// Check if the directory has an entry called `name`.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Add a new entry to the directory; fails if the name already exists.
+    pub fn insert(&mut self, name: &str, mnode: Arc<Mnode>) -> Result<(), KError> {
+        if self.entries.contains_key(name) {
+            return Err(KError::AlreadyPresent);
+        }
+
+        let key = TryString::try_from(name)?.into();
+        self.entries.try_reserve(1)?;
+        self.entries.insert(key, mnode);
+        Ok(())
+    }
+
+    /// Point the existing entry `name` to a different mnode, returns the one
+    /// it referred to before.
+    pub fn replace(&mut self, name: &str, mnode: Arc<Mnode>) -> Option<Arc<Mnode>> {
+        self.entries
+            .get_mut(name)
+            .map(|entry| core::mem::replace(entry, mnode))
+    }
+
+    /// Remove the entry `name` from the directory.
+    pub fn remove(&mut self, name: &str) -> Option<Arc<Mnode>> {
+        self.entries.remove(name)
+    }
+
+    /// Returns true if the directory has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of entries in the directory.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Iterate over all (name, mnode) entries of the directory.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Arc<Mnode>)> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    /// Insert, lookup and remove directory entries.
+    fn test_dir_insert_remove() {
+        let mut dir = Directory::new(FileModes::S_IRWXU.into());
+        assert!(dir.is_empty());
+        assert_eq!(dir.insert("file.txt", Arc::new(2)), Ok(()));
+        assert_eq!(
+            dir.insert("file.txt", Arc::new(3)),
+            Err(KError::AlreadyPresent)
+        );
+        assert_eq!(dir.len(), 1);
+        assert!(dir.contains("file.txt"));
+        assert_eq!(dir.lookup("file.txt"), Some(&Arc::new(2)));
+        assert_eq!(dir.lookup("other.txt"), None);
+        assert_eq!(dir.replace("file.txt", Arc::new(3)), Some(Arc::new(2)));
+        assert_eq!(dir.replace("other.txt", Arc::new(4)), None);
+        assert_eq!(dir.lookup("file.txt"), Some(&Arc::new(3)));
+        assert!(!dir.contains("other.txt"));
+
+        assert_eq!(dir.remove("file.txt"), Some(Arc::new(3)));
+        assert_eq!(dir.remove("file.txt"), None);
+        assert!(dir.is_empty());
+    }
+}
@@ -1,16 +1,27 @@
 // Copyright © 2021 VMware, Inc. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use super::{Fd, MAX_FILES_PER_PROCESS};
+use alloc::sync::Arc;
+
+use kpi::io::FileFlags;
+
+use super::{Fd, FileDescriptor, Mnode, MAX_FILES_PER_PROCESS};
 use crate::error::KError;
 
+/// The file descriptor table of a process.
+///
+/// Each slot points to an open file description; duplicated fds share the
+/// same description, and with it the flags and the file offset.
+///
+/// Operations that close a slot return the mnode and the flags of the
+/// description if that was its last fd, so the caller can release the file.
 pub struct FileDesc {
-    fds: arrayvec::ArrayVec<Option<Fd>, MAX_FILES_PER_PROCESS>,
+    fds: arrayvec::ArrayVec<Option<Arc<Fd>>, MAX_FILES_PER_PROCESS>,
 }
 
 impl Default for FileDesc {
     fn default() -> Self {
-        const NONE_FD: Option<Fd> = None;
+        const NONE_FD: Option<Arc<Fd>> = None;
         FileDesc {
             fds: arrayvec::ArrayVec::from([NONE_FD; MAX_FILES_PER_PROCESS]),
         }
@@ -18,26 +29,153 @@ impl Default for FileDesc {
 }
 
 impl FileDesc {
-    pub fn allocate_fd(&mut self) -> Option<(u64, &mut Fd)> {
-        if let Some(fid) = self.fds.iter().position(|fd| fd.is_none()) {
-            self.fds[fid] = Some(Default::default());
-            Some((fid as u64, self.fds[fid as usize].as_mut().unwrap()))
-        } else {
-            None
-        }
+    pub fn allocate_fd(&mut self) -> Result<(u64, &mut Fd), KError> {
+        let fid = self.free_fd()?;
+        self.fds[fid] = Some(Arc::try_new(Default::default())?);
+        // The description was just created, so there is no other reference to it.
+        let fd = Arc::get_mut(self.fds[fid].as_mut().unwrap()).unwrap();
+        Ok((fid as u64, fd))
     }
 
-    pub fn deallocate_fd(&mut self, fd: usize) -> Result<usize, KError> {
-        match self.fds.get_mut(fd) {
-            Some(fdinfo) => {
-                *fdinfo = None;
-                Ok(fd)
-            }
-            None => Err(KError::InvalidFileDescriptor),
-        }
+    pub fn deallocate_fd(&mut self, fd: usize) -> Result<Option<(Mnode, FileFlags)>, KError> {
+        let description = self
+            .fds
+            .get_mut(fd)
+            .and_then(|fdinfo| fdinfo.take())
+            .ok_or(KError::InvalidFileDescriptor)?;
+        Ok(last_reference(description))
+    }
+
+    /// Close all file descriptors, e.g., when the process exits.
+    pub fn deallocate_all(&mut self) -> impl Iterator<Item = (Mnode, FileFlags)> + '_ {
+        self.fds
+            .iter_mut()
+            .filter_map(|fdinfo| fdinfo.take())
+            .filter_map(last_reference)
     }
 
     pub fn get_fd(&self, index: usize) -> Option<&Fd> {
-        self.fds[index].as_ref()
+        self.fds.get(index)?.as_deref()
+    }
+
+    /// Duplicate `oldfd` into the lowest free slot of the table.
+    pub fn dup(&mut self, oldfd: usize) -> Result<u64, KError> {
+        let description = self.get_description(oldfd)?;
+        let fid = self.free_fd()?;
+        self.fds[fid] = Some(description);
+        Ok(fid as u64)
+    }
+
+    /// Duplicate `oldfd` into `newfd`, closing whatever `newfd` referred to.
+    pub fn dup2(
+        &mut self,
+        oldfd: usize,
+        newfd: usize,
+    ) -> Result<(u64, Option<(Mnode, FileFlags)>), KError> {
+        let description = self.get_description(oldfd)?;
+        let slot = self
+            .fds
+            .get_mut(newfd)
+            .ok_or(KError::InvalidFileDescriptor)?;
+        let closed = slot.replace(description).and_then(last_reference);
+        Ok((newfd as u64, closed))
+    }
+
+    /// Find the lowest unused file descriptor.
+    fn free_fd(&self) -> Result<usize, KError> {
+        self.fds
+            .iter()
+            .position(|fd| fd.is_none())
+            .ok_or(KError::OpenFileLimit)
+    }
+
+    /// Get a new reference to the open file description behind `fd`.
+    fn get_description(&self, fd: usize) -> Result<Arc<Fd>, KError> {
+        self.fds
+            .get(fd)
+            .and_then(|fd| fd.clone())
+            .ok_or(KError::InvalidFileDescriptor)
+    }
+}
+
+/// Get the mnode and flags of a dropped open file description if no other fd
+/// refers to it.
+fn last_reference(description: Arc<Fd>) -> Option<(Mnode, FileFlags)> {
+    Arc::try_unwrap(description)
+        .ok()
+        .map(|fd| (fd.get_mnode(), fd.get_flags()))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::fs::FileDescriptor;
+
+    #[test]
+    /// Duplicated file descriptors share the flags and the offset.
+    fn test_dup_shares_offset() {
+        let mut fdesc = FileDesc::default();
+        let (fid, fd) = fdesc.allocate_fd().unwrap();
+        fd.update_fd(2, FileFlags::O_RDWR);
+
+        let dupfid = fdesc.dup(fid as usize).unwrap();
+        assert_eq!(dupfid, 1);
+        assert_eq!(fdesc.dup2(fid as usize, 5), Ok((5, None)));
+
+        fdesc.get_fd(fid as usize).unwrap().update_offset(10);
+        assert_eq!(fdesc.get_fd(dupfid as usize).unwrap().get_offset(), 10);
+        assert_eq!(fdesc.get_fd(5).unwrap().get_offset(), 10);
+        assert_eq!(fdesc.get_fd(5).unwrap().get_mnode(), 2);
+
+        // Closing one of them leaves the others open.
+        assert_eq!(fdesc.deallocate_fd(fid as usize), Ok(None));
+        assert_eq!(fdesc.get_fd(dupfid as usize).unwrap().get_offset(), 10);
+        assert_eq!(fdesc.dup2(1, 1), Ok((1, None)));
+        assert_eq!(fdesc.get_fd(1).unwrap().get_offset(), 10);
+
+        // The description is released with its last fd.
+        assert_eq!(fdesc.deallocate_fd(1), Ok(None));
+        assert_eq!(fdesc.deallocate_fd(5), Ok(Some((2, FileFlags::O_RDWR))));
+        assert_eq!(fdesc.deallocate_fd(5), Err(KError::InvalidFileDescriptor));
+    }
+
+    #[test]
+    /// Overwriting or closing all fds releases the descriptions.
+    fn test_release_descriptions() {
+        let mut fdesc = FileDesc::default();
+        let (fid, fd) = fdesc.allocate_fd().unwrap();
+        fd.update_fd(2, FileFlags::O_RDWR);
+        let (other, fd) = fdesc.allocate_fd().unwrap();
+        fd.update_fd(3, FileFlags::O_RDONLY);
+        assert_eq!(fdesc.dup(fid as usize), Ok(2));
+
+        // `other` is replaced by a duplicate of `fid`.
+        assert_eq!(
+            fdesc.dup2(fid as usize, other as usize),
+            Ok((other, Some((3, FileFlags::O_RDONLY))))
+        );
+        let mut released = fdesc.deallocate_all();
+        assert_eq!(released.next(), Some((2, FileFlags::O_RDWR)));
+        assert_eq!(released.next(), None);
+        drop(released);
+        assert!(fdesc.get_fd(fid as usize).is_none());
+    }
+
+    #[test]
+    /// Duplicating an invalid fd or into an out-of-range slot fails.
+    fn test_dup_invalid() {
+        let mut fdesc = FileDesc::default();
+        assert_eq!(fdesc.dup(0), Err(KError::InvalidFileDescriptor));
+        let (fid, _fd) = fdesc.allocate_fd().unwrap();
+        assert_eq!(
+            fdesc.dup2(fid as usize, MAX_FILES_PER_PROCESS),
+            Err(KError::InvalidFileDescriptor)
+        );
+        assert!(fdesc.get_fd(MAX_FILES_PER_PROCESS).is_none());
+
+        for _i in 1..MAX_FILES_PER_PROCESS {
+            assert!(fdesc.dup(fid as usize).is_ok());
+        }
+        assert_eq!(fdesc.dup(fid as usize), Err(KError::OpenFileLimit));
     }
 }
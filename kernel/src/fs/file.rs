@@ -1,76 +1,126 @@
 // Copyright © 2021 VMware, Inc. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use alloc::collections::TryReserveError;
+use alloc::alloc::{alloc_zeroed, dealloc};
 use alloc::vec::Vec;
-use core::mem::size_of;
+use core::alloc::Layout;
+use core::cmp::{max, min};
+use core::ptr::NonNull;
 
-use fallible_collections::{FallibleVec, FallibleVecGlobal};
+use fallible_collections::FallibleVec;
+use hashbrown::HashMap;
 use kpi::io::*;
 
 use crate::error::KError;
-use crate::memory::BASE_PAGE_SIZE;
+use crate::memory::{kernel_vaddr_to_paddr, PAddr, VAddr, BASE_PAGE_SIZE, LARGE_PAGE_SIZE};
 
 use super::Modes;
 
-#[derive(Debug, Eq, PartialEq)]
-/// The buffer is used by the file. Each buffer is BASE_PAGE_SIZE
-/// long and a file consists of many such buffers.
-struct Buffer {
-    data: Vec<u8>,
+#[derive(Debug)]
+/// A contiguous, zero-initialized piece of file data. An extent is either
+/// BASE_PAGE_SIZE or LARGE_PAGE_SIZE long and starts at a file offset which
+/// is aligned to its length.
+///
+/// The memory is aligned to the extent length as well, so an extent can be
+/// mapped into a process as a single (base or large) page.
+struct Extent {
+    data: NonNull<u8>,
+    len: usize,
+    /// Number of process mappings of the extent, it isn't freed while it
+    /// is mapped.
+    mappings: usize,
 }
 
-impl Buffer {
-    /// This function tries to allocate a vector of BASE_PAGE_SIZE long
-    /// and returns a buffer in case of the success; error otherwise.
-    pub fn try_alloc_buffer() -> Result<Buffer, TryReserveError> {
-        Vec::try_with_capacity(BASE_PAGE_SIZE).map(|data| Buffer { data })
+// The extent owns its memory, just like a `Vec<u8>` would.
+unsafe impl Send for Extent {}
+unsafe impl Sync for Extent {}
+
+impl Extent {
+    /// This function tries to allocate a zero-filled extent of `len` bytes.
+    pub fn try_alloc_extent(len: usize) -> Result<Extent, KError> {
+        debug_assert!(len == BASE_PAGE_SIZE || len == LARGE_PAGE_SIZE);
+        let layout = Layout::from_size_align(len, len).map_err(|_e| KError::OutOfMemory)?;
+        let data = NonNull::new(unsafe { alloc_zeroed(layout) }).ok_or(KError::OutOfMemory)?;
+        Ok(Extent {
+            data,
+            len,
+            mappings: 0,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn data(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.data.as_ptr(), self.len) }
+    }
+
+    fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.data.as_ptr(), self.len) }
+    }
+
+    fn is_mapped(&self) -> bool {
+        self.mappings > 0
+    }
+
+    /// Physical address of the extent memory.
+    fn paddr(&self) -> PAddr {
+        kernel_vaddr_to_paddr(VAddr::from(self.data.as_ptr() as usize))
+    }
+}
+
+impl Drop for Extent {
+    fn drop(&mut self) {
+        unsafe {
+            dealloc(
+                self.data.as_ptr(),
+                Layout::from_size_align_unchecked(self.len, self.len),
+            )
+        };
     }
 }
 
+impl PartialEq for Extent {
+    fn eq(&self, other: &Extent) -> bool {
+        self.data() == other.data()
+    }
+}
+
+impl Eq for Extent {}
+
 #[derive(Debug, Eq, PartialEq)]
-/// File type has a list of buffers and modes to access the file
+/// File type has a map of extents and modes to access the file
 pub struct File {
-    mcache: Vec<Buffer>,
+    /// Extents indexed by the file offset they start at. Parts of the file
+    /// without an extent are holes, which read as zero.
+    extents: HashMap<usize, Extent>,
+    /// The size is tracked separately as the file can end in a hole.
+    size: usize,
     modes: FileModes,
     // TODO: Add more file related attributes
 }
 
 impl File {
-    /// Initialize a file. Pre-intialize the buffer list with 64 size.
+    /// Initialize an empty file.
     pub fn new(modes: Modes) -> Result<File, KError> {
         let modes = FileModes::from(modes);
-        let mcache = Vec::try_with_capacity(64 * size_of::<Buffer>())?;
-        Ok(File { mcache, modes })
+        Ok(File {
+            extents: HashMap::new(),
+            size: 0,
+            modes,
+        })
     }
 
-    /// This method returns the current-size of the file. This method follows
-    /// the same convention as a vector length. So, size of the file is equal
-    /// to the data in it and not the max-allocated buffer-size.
+    /// This method returns the current-size of the file. The size includes
+    /// the holes in the file, and not the memory allocated for it.
     pub fn get_size(&self) -> usize {
-        let buffer_num = self.mcache.len();
-        match buffer_num {
-            0 => 0,
-            1 => self.mcache[buffer_num - 1].data.len(),
-            _ => {
-                match self.mcache[buffer_num - 1].data.len() {
-                    // If resize_file()/write() added some empty buffers to be filled
-                    // later, then scan all the buffers to get the file-size.
-                    0 => {
-                        let mut len = 0;
-                        for buf in &self.mcache {
-                            match buf.data.len() {
-                                0 => break,
-                                curr_buff_len => len += curr_buff_len,
-                            }
-                        }
-                        len
-                    }
-                    // If file is filled till last buffer
-                    last_buffer_len => ((buffer_num - 1) * BASE_PAGE_SIZE + last_buffer_len),
-                }
-            }
-        }
+        self.size
+    }
+
+    /// Memory (in bytes) allocated for the extents of the file.
+    pub fn get_allocated(&self) -> usize {
+        self.extents.values().map(|extent| extent.len()).sum()
     }
 
     /// This method returns the mode in which file is created.
@@ -78,69 +128,77 @@ impl File {
         self.modes
     }
 
-    /// This method is internally used by write_file() method. The additional
-    /// length is initialzed to zero.
-    pub fn increase_file_size(
-        &mut self,
-        curr_file_len: usize,
-        new_len: usize,
-    ) -> Result<(), KError> {
-        if new_len == 0 {
-            return Ok(());
+    /// Find the start offset of the extent which holds `offset`, if any.
+    fn extent_start(&self, offset: usize) -> Option<usize> {
+        let large_start = offset - offset % LARGE_PAGE_SIZE;
+        match self.extents.get(&large_start) {
+            Some(extent) if extent.len() == LARGE_PAGE_SIZE => Some(large_start),
+            _ => {
+                let base_start = offset - offset % BASE_PAGE_SIZE;
+                if self.extents.contains_key(&base_start) {
+                    Some(base_start)
+                } else {
+                    None
+                }
+            }
         }
+    }
 
-        let free_in_last_buffer = match self.mcache.last() {
-            Some(buffer) => BASE_PAGE_SIZE - buffer.data.len(),
-            None => 0,
-        };
-
-        let add_new = new_len - curr_file_len;
-        if add_new <= free_in_last_buffer {
-            // Don't need to add new buffer
-            let offset = self.mcache.last().unwrap().data.len();
-            self.mcache
-                .last_mut()
-                .unwrap()
-                .data
-                .try_resize(offset + add_new, 0)
-                .map_err(|e| e.into())
+    /// Allocate the extent which holds `offset` for a write starting at
+    /// `write_start`. A write that fills the file sequentially past the
+    /// first large page gets large-page extents, everything else (small
+    /// files and sparse writes) gets base-page extents.
+    fn allocate_extent(&mut self, offset: usize, write_start: usize) -> Result<usize, KError> {
+        let large_start = offset - offset % LARGE_PAGE_SIZE;
+        // Only mapped extents are kept past the end of file, a region after it
+        // which has none of them is free to be covered by a single large extent.
+        let (start, len) = if large_start >= LARGE_PAGE_SIZE
+            && large_start >= self.size
+            && write_start <= large_start
+            && self.is_hole(large_start, LARGE_PAGE_SIZE)
+        {
+            (large_start, LARGE_PAGE_SIZE)
         } else {
-            // Add new buffer
-            if !self.mcache.is_empty() {
-                self.mcache
-                    .last_mut()
-                    .unwrap()
-                    .data
-                    .try_resize(BASE_PAGE_SIZE, 0)?;
-            }
-
-            let remaining = add_new - free_in_last_buffer;
-            let new_buffers = ceil(remaining, BASE_PAGE_SIZE);
-            let mut vec = Vec::try_with_capacity(new_buffers)?;
+            (offset - offset % BASE_PAGE_SIZE, BASE_PAGE_SIZE)
+        };
 
-            for _i in 0..new_buffers {
-                let mut buffer = Buffer::try_alloc_buffer()?;
-                // TODO(error-handling): On failure, might want to
-                // shrink previous buffers again?
-                buffer.data.try_resize(BASE_PAGE_SIZE, 0)?;
+        let extent = Extent::try_alloc_extent(len)?;
+        self.extents.try_reserve(1)?;
+        self.extents.insert(start, extent);
+        Ok(start)
+    }
 
-                debug_assert!(vec.len() < vec.capacity(), "ensured by try_with_capacity");
-                vec.push(buffer);
-            }
+    /// Check that no extent starts in `start..start + len`.
+    fn is_hole(&self, start: usize, len: usize) -> bool {
+        (start..start + len)
+            .step_by(BASE_PAGE_SIZE)
+            .all(|offset| !self.extents.contains_key(&offset))
+    }
 
-            // Filled all the buffers with zeros, resize the last buffer:
-            if new_len % BASE_PAGE_SIZE != 0 {
-                let sure_bytes_to_write = (new_buffers - 1) * BASE_PAGE_SIZE;
-                let bytes_in_last_buffer = new_len - (self.get_size() + sure_bytes_to_write);
+    /// Drop the extents past the end of file, unless they are mapped.
+    fn free_extents_past_end(&mut self) {
+        let size = self.size;
+        self.extents
+            .retain(|start, extent| *start < size || extent.is_mapped());
+    }
 
-                // TODO(error-handling): shrink others again on error?
-                vec.last_mut()
-                    .unwrap()
-                    .data
-                    .try_resize(bytes_in_last_buffer, 0)?;
+    /// Add (or remove) a mapping to every extent in `offset..end`.
+    fn update_mappings(&mut self, offset: usize, end: usize, mapped: bool) {
+        let mut curr = offset;
+        while curr < end {
+            match self.extent_start(curr) {
+                Some(extent_start) => {
+                    let extent = self.extents.get_mut(&extent_start).unwrap();
+                    if mapped {
+                        extent.mappings += 1;
+                    } else {
+                        debug_assert!(extent.is_mapped(), "Extent wasn't mapped");
+                        extent.mappings = extent.mappings.saturating_sub(1);
+                    }
+                    curr = extent_start + extent.len();
+                }
+                None => curr = curr - curr % BASE_PAGE_SIZE + BASE_PAGE_SIZE,
             }
-
-            self.mcache.try_append(&mut vec).map_err(|e| e.into())
         }
     }
 
@@ -153,40 +211,37 @@ impl File {
         start_offset: usize,
         end_offset: usize,
     ) -> Result<usize, KError> {
-        let mut buffer_num = offset_to_buffernum(start_offset, BASE_PAGE_SIZE);
-        let mut offset_in_buffer = start_offset - (buffer_num * BASE_PAGE_SIZE);
+        let len = end_offset - start_offset;
         let mut copied = 0;
-        let mut dst_start = 0;
-        let mut dst_end;
 
-        let len = end_offset - start_offset;
         while copied < len {
-            let useful_data_curr_buffer = self.mcache[buffer_num].data.len() - offset_in_buffer;
+            let offset = start_offset + copied;
             let remaining = len - copied;
 
-            let src_start = offset_in_buffer;
-            let src_end;
-            if remaining >= useful_data_curr_buffer {
-                dst_end = dst_start + useful_data_curr_buffer;
-                src_end = src_start + useful_data_curr_buffer;
-                copied += useful_data_curr_buffer;
-            } else {
-                dst_end = dst_start + remaining;
-                src_end = src_start + remaining;
-                copied += remaining;
+            match self.extent_start(offset) {
+                Some(extent_start) => {
+                    let extent = &self.extents[&extent_start];
+                    let offset_in_extent = offset - extent_start;
+                    let bytes = min(remaining, extent.len() - offset_in_extent);
+                    user_slice[copied..copied + bytes].copy_from_slice(
+                        &extent.data()[offset_in_extent..offset_in_extent + bytes],
+                    );
+                    copied += bytes;
+                }
+                None => {
+                    // A hole in the file reads as zero.
+                    let bytes = min(remaining, BASE_PAGE_SIZE - offset % BASE_PAGE_SIZE);
+                    user_slice[copied..copied + bytes].fill(0);
+                    copied += bytes;
+                }
             }
-            user_slice[dst_start..dst_end]
-                .copy_from_slice(&self.mcache[buffer_num].data[src_start..src_end]);
-            buffer_num += 1;
-            dst_start = dst_end;
-            offset_in_buffer = 0;
         }
 
         Ok(copied)
     }
 
     /// This method is internally called on a write() system-call. The user provided the
-    /// data in a user-slice and the method copies that data into the file buffers. Beside
+    /// data in a user-slice and the method copies that data into the file extents. Beside
     /// the slice the user also provides the length of the data and it can also specify an
     /// arbitrary offset in the file to write the data.
     pub fn write_file(
@@ -195,70 +250,121 @@ impl File {
         len: usize,
         start_offset: usize,
     ) -> Result<usize, KError> {
-        // If offset is specified, then resize the file to the offset + len.
-        // If offset is more than file size then fill the file with zeros till the offset.
-        let curr_file_len = self.get_size();
-        let new_len = start_offset + len;
-        if new_len > 0
-            && new_len > curr_file_len
-            && self.increase_file_size(curr_file_len, new_len).is_err()
-        {
-            return Err(KError::OutOfMemory);
+        let end_offset = start_offset.checked_add(len).ok_or(KError::InvalidOffset)?;
+
+        // Allocate all the missing extents first, so a failed allocation
+        // doesn't leave a partial write behind.
+        let mut offset = start_offset;
+        while offset < end_offset {
+            let extent_start = match self.extent_start(offset) {
+                Some(extent_start) => extent_start,
+                None => match self.allocate_extent(offset, start_offset) {
+                    Ok(extent_start) => extent_start,
+                    Err(_e) => {
+                        // Extents that were added inside of holes are zero-filled,
+                        // drop the ones past the end of file.
+                        self.free_extents_past_end();
+                        return Err(KError::OutOfMemory);
+                    }
+                },
+            };
+            offset = extent_start + self.extents[&extent_start].len();
         }
 
-        let mut buffer_num = offset_to_buffernum(start_offset, BASE_PAGE_SIZE);
-        let mut offset_in_buffer = start_offset - (buffer_num * BASE_PAGE_SIZE);
         let mut copied = 0;
-        let mut dst_start = 0;
-        let mut dst_end;
-
         while copied < len {
-            let useful_data_curr_buffer = BASE_PAGE_SIZE - offset_in_buffer;
-            let remaining = len - copied;
+            let offset = start_offset + copied;
+            let extent_start = self.extent_start(offset).unwrap();
+            let extent = self.extents.get_mut(&extent_start).unwrap();
+            let offset_in_extent = offset - extent_start;
+            let bytes = min(len - copied, extent.len() - offset_in_extent);
+
+            extent.data_mut()[offset_in_extent..offset_in_extent + bytes]
+                .copy_from_slice(&user_slice[copied..copied + bytes]);
+            copied += bytes;
+        }
 
-            let src_start = offset_in_buffer;
-            let src_end;
-            if remaining >= useful_data_curr_buffer {
-                dst_end = dst_start + useful_data_curr_buffer;
-                src_end = src_start + useful_data_curr_buffer;
-                copied += useful_data_curr_buffer;
-            } else {
-                dst_end = dst_start + remaining;
-                src_end = src_start + remaining;
-                copied += remaining;
-            }
+        // If offset is more than file size then the file is extended with a hole till the offset.
+        self.size = max(self.size, end_offset);
+        Ok(len)
+    }
 
-            self.mcache[buffer_num].data[src_start..src_end]
-                .copy_from_slice(&user_slice[dst_start..dst_end]);
-            buffer_num += 1;
-            dst_start = dst_end;
-            offset_in_buffer = 0;
+    /// Truncate the file to `new_len` bytes. Growing the file adds a hole at
+    /// the end, shrinking it frees the extents past `new_len` (unless they
+    /// are mapped).
+    pub fn file_truncate(&mut self, new_len: usize) -> Result<(), KError> {
+        if new_len < self.size {
+            self.size = new_len;
+            self.free_extents_past_end();
+
+            // Zero everything past the new end, so it reads as zero if the file grows again.
+            for (start, extent) in self.extents.iter_mut() {
+                if *start + extent.len() > new_len {
+                    let from = new_len.saturating_sub(*start);
+                    extent.data_mut()[from..].fill(0);
+                }
+            }
         }
 
-        Ok(len)
+        self.size = new_len;
+        Ok(())
     }
 
-    /// Truncate the file in reasponse of O_TRUNC flag.
-    pub fn file_truncate(&mut self) {
-        self.mcache.clear();
+    /// Returns the physical memory regions which hold the file range
+    /// `offset..offset + len`, allocating extents for the holes in it.
+    ///
+    /// Large extents are returned as one region if they are fully inside the
+    /// range, everything else is returned in base-page sized regions. The
+    /// extents are kept until the range is unmapped with `unmap_regions`.
+    pub fn map_regions(
+        &mut self,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<(PAddr, usize)>, KError> {
+        let end = offset.checked_add(len).ok_or(KError::InvalidOffset)?;
+        if offset % BASE_PAGE_SIZE != 0 || len == 0 || end > self.size {
+            return Err(KError::InvalidOffset);
+        }
+
+        let mut regions = Vec::new();
+        let mut curr = offset;
+        while curr < end {
+            let extent_start = match self.extent_start(curr) {
+                Some(extent_start) => extent_start,
+                None => self.allocate_extent(curr, offset)?,
+            };
+            let extent = &self.extents[&extent_start];
+            let region = if extent_start >= offset && extent_start + extent.len() <= end {
+                (extent.paddr(), extent.len())
+            } else {
+                (extent.paddr() + (curr - extent_start), BASE_PAGE_SIZE)
+            };
+            regions.try_push(region)?;
+            curr += region.1;
+        }
+
+        self.update_mappings(offset, end, true);
+        Ok(regions)
     }
-}
 
-/// This is used to determine, how many buffers to add dependeing on the number
-/// of bytes and buffer-size.
-fn ceil(bytes: usize, buffer_size: usize) -> usize {
-    let mut val = bytes / buffer_size;
-    if bytes > val * buffer_size {
-        val += 1;
+    /// Drop a mapping of the file range `offset..offset + len` which was
+    /// returned by `map_regions`.
+    pub fn unmap_regions(&mut self, offset: usize, len: usize) {
+        self.update_mappings(offset, offset + len, false);
+        self.free_extents_past_end();
     }
-    val
 }
 
-/// This method converts the file offset to buffer number with-in a file.
-/// The assumption is that the buffer-size is equal for all the buffers
-/// in a file.
-fn offset_to_buffernum(offset: usize, buffer_size: usize) -> usize {
-    offset / buffer_size
+impl Drop for File {
+    fn drop(&mut self) {
+        // Mapped files stay open, so this shouldn't happen; but better leak
+        // the memory than free it under a mapping.
+        for (_start, extent) in self.extents.drain() {
+            if extent.is_mapped() {
+                core::mem::forget(extent);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -266,36 +372,12 @@ pub mod test {
     use super::*;
 
     #[test]
-    /// This method test the offset to buffer number conversion for a file.
-    /// It uses BASE_PAGE_SIZE as buffer size.
-    fn test_offset_to_buffernum() {
-        let mut buffer_num: i64 = -1;
-        for i in 0..10000 {
-            if (i % BASE_PAGE_SIZE) == 0 {
-                buffer_num += 1;
-            }
-            assert_eq!(offset_to_buffernum(i, BASE_PAGE_SIZE), buffer_num as usize);
-        }
-    }
-
-    #[test]
-    /// This method tests the ceil method.
-    fn test_ceil() {
-        let mut cval = 0;
-        for i in 0..10000 {
-            assert_eq!(ceil(i, BASE_PAGE_SIZE), cval as usize);
-            if (i % BASE_PAGE_SIZE) == 0 {
-                cval += 1;
-            }
-        }
-    }
-
-    #[test]
-    /// This method test the size of the allocated buffer.
-    fn test_buffer_alloc() {
-        let buffer = Buffer::try_alloc_buffer().unwrap();
-        assert_eq!(buffer.data.len(), 0);
-        assert_eq!(buffer.data.capacity(), BASE_PAGE_SIZE);
+    /// This method test the size of the allocated extent.
+    fn test_extent_alloc() {
+        let extent = Extent::try_alloc_extent(BASE_PAGE_SIZE).unwrap();
+        assert_eq!(extent.len(), BASE_PAGE_SIZE);
+        assert!(extent.data().iter().all(|b| *b == 0));
+        assert_eq!(extent.paddr().as_u64() % BASE_PAGE_SIZE as u64, 0);
     }
 
     #[test]
@@ -304,25 +386,20 @@ pub mod test {
         let file = File::new(FileModes::S_IRWXU.into()).unwrap();
         assert_eq!(file.get_mode(), FileModes::S_IRWXU);
         assert_eq!(file.get_size(), 0);
-        assert_eq!(file.mcache.len(), 0);
-        assert_eq!(file.mcache.capacity(), 64 * size_of::<Buffer>());
+        assert_eq!(file.extents.len(), 0);
     }
 
     #[test]
-    /// This tests the resize file method.
+    /// This tests the resize file method; growing a file doesn't allocate memory.
     fn test_resize_file() {
         let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
         assert_eq!(file.get_mode(), FileModes::S_IRWXU);
-        assert_eq!(file.mcache.len(), 0);
-        assert_eq!(file.mcache.capacity(), 64 * size_of::<Buffer>());
-
         assert_eq!(file.get_size(), 0);
 
         for i in 0..10000 {
-            assert!(file.increase_file_size(file.get_size(), i).is_ok());
+            assert!(file.file_truncate(i).is_ok());
             assert_eq!(file.get_size(), i);
-            let buffer_num = ceil(i, BASE_PAGE_SIZE);
-            assert_eq!(file.mcache.len(), buffer_num);
+            assert_eq!(file.extents.len(), 0);
         }
     }
 
@@ -331,18 +408,18 @@ pub mod test {
     fn test_write_file() {
         let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
         assert_eq!(file.get_mode(), FileModes::S_IRWXU);
-        assert_eq!(file.mcache.len(), 0);
-        assert_eq!(file.mcache.capacity(), 64 * size_of::<Buffer>());
+        assert_eq!(file.extents.len(), 0);
 
         let buffer: &mut [u8] = &mut [0xb; 10000];
         for i in 0..10000 {
             file.write_file(buffer, i, 0).unwrap();
             assert_eq!(file.get_size(), i);
         }
+        assert_eq!(file.extents.len(), 3);
 
-        // verify the content for first buffer
+        // verify the content for first extent
         for i in 0..4096 {
-            assert_eq!(file.mcache[0].data[i], 0xb);
+            assert_eq!(file.extents[&0].data()[i], 0xb);
         }
     }
 
@@ -351,8 +428,7 @@ pub mod test {
     fn test_read_file() {
         let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
         assert_eq!(file.get_mode(), FileModes::S_IRWXU);
-        assert_eq!(file.mcache.len(), 0);
-        assert_eq!(file.mcache.capacity(), 64 * size_of::<Buffer>());
+        assert_eq!(file.extents.len(), 0);
 
         let wbuffer: &mut [u8] = &mut [0xb; 10000];
         let rbuffer: &mut [u8] = &mut [0; 10000];
@@ -366,6 +442,141 @@ pub mod test {
         }
     }
 
+    #[test]
+    /// Writing past the end of file leaves a hole which reads as zero and has no extents.
+    fn test_sparse_file() {
+        let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
+        let wbuffer: &mut [u8] = &mut [0xb; 100];
+        let rbuffer: &mut [u8] = &mut [0xff; 3 * BASE_PAGE_SIZE];
+
+        let offset = 1 << 40;
+        assert_eq!(file.write_file(wbuffer, 100, offset), Ok(100));
+        assert_eq!(file.get_size(), offset + 100);
+        assert_eq!(file.extents.len(), 1);
+
+        assert_eq!(file.write_file(wbuffer, 100, 0), Ok(100));
+        assert_eq!(file.extents.len(), 2);
+
+        let start = offset - 2 * BASE_PAGE_SIZE;
+        assert_eq!(
+            file.read_file(rbuffer, start, start + 3 * BASE_PAGE_SIZE),
+            Ok(3 * BASE_PAGE_SIZE)
+        );
+        assert!(rbuffer[..2 * BASE_PAGE_SIZE].iter().all(|b| *b == 0));
+        assert!(rbuffer[2 * BASE_PAGE_SIZE..2 * BASE_PAGE_SIZE + 100]
+            .iter()
+            .all(|b| *b == 0xb));
+        assert!(rbuffer[2 * BASE_PAGE_SIZE + 100..].iter().all(|b| *b == 0));
+
+        // The end of the write doesn't fit in a file offset.
+        assert_eq!(
+            file.write_file(wbuffer, 100, usize::MAX - 10),
+            Err(KError::InvalidOffset)
+        );
+        assert_eq!(file.get_size(), offset + 100);
+    }
+
+    #[test]
+    /// Large sequential writes use large-page extents past the first large page.
+    fn test_large_extents() {
+        let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
+        let wbuffer: &mut [u8] = &mut [0xb; BASE_PAGE_SIZE];
+
+        let mut offset = 0;
+        while offset < 3 * LARGE_PAGE_SIZE {
+            assert_eq!(
+                file.write_file(wbuffer, BASE_PAGE_SIZE, offset),
+                Ok(BASE_PAGE_SIZE)
+            );
+            offset += BASE_PAGE_SIZE;
+        }
+        assert_eq!(file.get_size(), 3 * LARGE_PAGE_SIZE);
+        assert_eq!(file.extents.len(), LARGE_PAGE_SIZE / BASE_PAGE_SIZE + 2);
+        assert_eq!(file.extents[&LARGE_PAGE_SIZE].len(), LARGE_PAGE_SIZE);
+        assert_eq!(file.extents[&(2 * LARGE_PAGE_SIZE)].len(), LARGE_PAGE_SIZE);
+        assert_eq!(file.get_allocated(), 3 * LARGE_PAGE_SIZE);
+
+        // A sparse write far past the end of file only gets a base-page extent.
+        assert_eq!(file.write_file(wbuffer, 1, 8 * LARGE_PAGE_SIZE + 10), Ok(1));
+        assert_eq!(file.extents[&(8 * LARGE_PAGE_SIZE)].len(), BASE_PAGE_SIZE);
+    }
+
+    #[test]
+    /// Mapping a file range returns page-aligned regions and allocates the holes in it.
+    fn test_map_regions() {
+        let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
+        let wbuffer: &mut [u8] = &mut [0xb; 100];
+        assert_eq!(file.write_file(wbuffer, 100, 0), Ok(100));
+        assert_eq!(file.file_truncate(3 * BASE_PAGE_SIZE), Ok(()));
+        assert_eq!(file.extents.len(), 1);
+
+        let regions = file.map_regions(0, 3 * BASE_PAGE_SIZE).unwrap();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(file.extents.len(), 3);
+        for (paddr, size) in regions {
+            assert_eq!(size, BASE_PAGE_SIZE);
+            assert_eq!(paddr.as_u64() % BASE_PAGE_SIZE as u64, 0);
+        }
+        assert_eq!(
+            file.extents[&0].paddr(),
+            file.map_regions(0, 1).unwrap()[0].0
+        );
+
+        assert_eq!(
+            file.map_regions(1, BASE_PAGE_SIZE),
+            Err(KError::InvalidOffset)
+        );
+        assert_eq!(
+            file.map_regions(0, 3 * BASE_PAGE_SIZE + 1),
+            Err(KError::InvalidOffset)
+        );
+
+        // A mapped file keeps its extents when it shrinks.
+        assert_eq!(file.file_truncate(50), Ok(()));
+        assert_eq!(file.extents.len(), 3);
+        assert!(file.extents[&0].data()[50..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    /// Mapped extents past the end of file are kept until they are unmapped,
+    /// writes use them instead of allocating over them.
+    fn test_unmap_regions() {
+        let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
+        let wbuffer: &mut [u8] = &mut [0xb; 3 * BASE_PAGE_SIZE];
+        let rbuffer: &mut [u8] = &mut [0xff; 3 * BASE_PAGE_SIZE];
+        assert_eq!(file.file_truncate(LARGE_PAGE_SIZE + BASE_PAGE_SIZE), Ok(()));
+
+        let regions = file.map_regions(LARGE_PAGE_SIZE, BASE_PAGE_SIZE).unwrap();
+        assert_eq!(file.file_truncate(LARGE_PAGE_SIZE), Ok(()));
+        assert_eq!(file.extents.len(), 1);
+
+        // A sequential write from the mapped extent doesn't replace it with a large one.
+        assert_eq!(
+            file.write_file(wbuffer, 3 * BASE_PAGE_SIZE, LARGE_PAGE_SIZE),
+            Ok(3 * BASE_PAGE_SIZE)
+        );
+        assert_eq!(file.extents[&LARGE_PAGE_SIZE].len(), BASE_PAGE_SIZE);
+        assert_eq!(file.extents[&LARGE_PAGE_SIZE].paddr(), regions[0].0);
+        assert_eq!(
+            file.read_file(
+                rbuffer,
+                LARGE_PAGE_SIZE,
+                LARGE_PAGE_SIZE + 3 * BASE_PAGE_SIZE
+            ),
+            Ok(3 * BASE_PAGE_SIZE)
+        );
+        assert!(rbuffer.iter().all(|b| *b == 0xb));
+
+        // Mappings are counted, the extent is freed with the last one.
+        let _regions = file.map_regions(LARGE_PAGE_SIZE, BASE_PAGE_SIZE).unwrap();
+        assert_eq!(file.file_truncate(0), Ok(()));
+        assert_eq!(file.extents.len(), 1);
+        file.unmap_regions(LARGE_PAGE_SIZE, BASE_PAGE_SIZE);
+        assert_eq!(file.extents.len(), 1);
+        file.unmap_regions(LARGE_PAGE_SIZE, BASE_PAGE_SIZE);
+        assert_eq!(file.extents.len(), 0);
+    }
+
     #[test]
     /// This test checks if the file truncation works as expected.
     fn test_file_truncate() {
@@ -375,9 +586,40 @@ pub mod test {
         assert_eq!(file.write_file(wbuffer, 10000, 0), Ok(10000));
         assert_eq!(file.get_size(), 10000);
 
-        file.file_truncate();
+        assert_eq!(file.file_truncate(0), Ok(()));
         assert_eq!(file.get_size(), 0);
-        assert_eq!(file.mcache.len(), 0);
+        assert_eq!(file.extents.len(), 0);
+    }
+
+    #[test]
+    /// This test shrinks and grows a file and checks that the new part is zero-filled.
+    fn test_file_truncate_to_length() {
+        let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
+        let wbuffer: &mut [u8] = &mut [0xb; 10000];
+        let rbuffer: &mut [u8] = &mut [0xff; 10000];
+
+        assert_eq!(file.write_file(wbuffer, 10000, 0), Ok(10000));
+        assert_eq!(file.file_truncate(5000), Ok(()));
+        assert_eq!(file.get_size(), 5000);
+        assert_eq!(file.extents.len(), 2);
+
+        assert_eq!(file.file_truncate(BASE_PAGE_SIZE), Ok(()));
+        assert_eq!(file.get_size(), BASE_PAGE_SIZE);
+        assert_eq!(file.extents.len(), 1);
+
+        assert_eq!(file.file_truncate(10000), Ok(()));
+        assert_eq!(file.get_size(), 10000);
+        assert_eq!(file.extents.len(), 1);
+        assert_eq!(file.read_file(rbuffer, 0, 10000), Ok(10000));
+        assert!(rbuffer[..BASE_PAGE_SIZE].iter().all(|b| *b == 0xb));
+        assert!(rbuffer[BASE_PAGE_SIZE..].iter().all(|b| *b == 0));
+
+        // The tail of a partially truncated extent reads as zero.
+        assert_eq!(file.file_truncate(100), Ok(()));
+        assert_eq!(file.file_truncate(200), Ok(()));
+        assert_eq!(file.read_file(rbuffer, 0, 200), Ok(200));
+        assert!(rbuffer[..100].iter().all(|b| *b == 0xb));
+        assert!(rbuffer[100..200].iter().all(|b| *b == 0));
     }
 
     #[test]
@@ -385,8 +627,7 @@ pub mod test {
     fn test_overwrite_file() {
         let mut file = File::new(FileModes::S_IRWXU.into()).unwrap();
         assert_eq!(file.get_mode(), FileModes::S_IRWXU);
-        assert_eq!(file.mcache.len(), 0);
-        assert_eq!(file.mcache.capacity(), 64 * size_of::<Buffer>());
+        assert_eq!(file.extents.len(), 0);
 
         let buffer: &mut [u8] = &mut [0xb; 10000];
         for i in 0..10000 {
@@ -400,13 +641,13 @@ pub mod test {
             assert_eq!(file.get_size(), 9999);
         }
 
-        // verify the content for first buffer
+        // verify the content for first extent
         for i in 0..4095 {
-            assert_eq!(file.mcache[0].data[i], 0xa);
+            assert_eq!(file.extents[&0].data()[i], 0xa);
         }
-        // verify the content for second buffer
+        // verify the content for second extent
         for i in 0..4096 {
-            assert_eq!(file.mcache[1].data[i], 0xb);
+            assert_eq!(file.extents[&BASE_PAGE_SIZE].data()[i], 0xb);
         }
     }
 }
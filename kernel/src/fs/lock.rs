@@ -0,0 +1,179 @@
+// Copyright © 2021 VMware, Inc. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Advisory byte-range locks with the semantics of `fcntl` record locks:
+//! locks are owned by a process and only conflict with locks of other
+//! processes.
+
+use alloc::vec::Vec;
+
+use fallible_collections::FallibleVec;
+
+use crate::error::KError;
+use crate::process::Pid;
+
+/// Kind of an advisory lock.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum LockKind {
+    /// Any number of processes can hold a shared lock on a range.
+    Shared,
+    /// Only one process can hold an exclusive lock on a range.
+    Exclusive,
+}
+
+/// A lock on the bytes `start..end` of a file.
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct RangeLock {
+    owner: Pid,
+    kind: LockKind,
+    start: u64,
+    end: u64,
+}
+
+impl RangeLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// The advisory locks held on a file.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct LockTable {
+    /// The locks of one owner never overlap each other.
+    locks: Vec<RangeLock>,
+}
+
+impl LockTable {
+    /// Lock the bytes `start..end` for `owner`, replacing the locks the owner
+    /// already holds in the range. Fails with `WouldBlock` if another process
+    /// holds a conflicting lock.
+    pub fn lock(&mut self, owner: Pid, kind: LockKind, start: u64, end: u64) -> Result<(), KError> {
+        if !self.can_lock(owner, kind, start, end) {
+            return Err(KError::WouldBlock);
+        }
+
+        // Room for the new lock and for splitting an existing one.
+        FallibleVec::try_reserve(&mut self.locks, 2)?;
+        self.remove(owner, start, end);
+        self.locks.push(RangeLock {
+            owner,
+            kind,
+            start,
+            end,
+        });
+        Ok(())
+    }
+
+    /// Check that no other process holds a lock which conflicts with locking
+    /// the bytes `start..end` for `owner`.
+    pub fn can_lock(&self, owner: Pid, kind: LockKind, start: u64, end: u64) -> bool {
+        !self.locks.iter().any(|lock| {
+            lock.owner != owner
+                && lock.overlaps(start, end)
+                && (kind == LockKind::Exclusive || lock.kind == LockKind::Exclusive)
+        })
+    }
+
+    /// Unlock the bytes `start..end` for `owner`.
+    pub fn unlock(&mut self, owner: Pid, start: u64, end: u64) -> Result<(), KError> {
+        FallibleVec::try_reserve(&mut self.locks, 1)?;
+        self.remove(owner, start, end);
+        Ok(())
+    }
+
+    /// Drop all locks of `owner`.
+    pub fn unlock_all(&mut self, owner: Pid) {
+        self.locks.retain(|lock| lock.owner != owner);
+    }
+
+    /// Cut `start..end` out of the locks of `owner`. The caller reserves room
+    /// for one more lock, in case a lock has to be split in two.
+    fn remove(&mut self, owner: Pid, start: u64, end: u64) {
+        let mut idx = 0;
+        while idx < self.locks.len() {
+            let lock = &mut self.locks[idx];
+            if lock.owner != owner || !lock.overlaps(start, end) {
+                idx += 1;
+                continue;
+            }
+
+            if lock.start < start && lock.end > end {
+                let tail = RangeLock {
+                    start: end,
+                    ..lock.clone()
+                };
+                lock.end = start;
+                self.locks.push(tail);
+                idx += 1;
+            } else if lock.start < start {
+                lock.end = start;
+                idx += 1;
+            } else if lock.end > end {
+                lock.start = end;
+                idx += 1;
+            } else {
+                self.locks.swap_remove(idx);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    /// Shared locks can overlap, exclusive locks can't.
+    fn test_lock_conflicts() {
+        let mut locks = LockTable::default();
+        assert_eq!(locks.lock(1, LockKind::Shared, 0, 100), Ok(()));
+        assert_eq!(locks.lock(2, LockKind::Shared, 50, 150), Ok(()));
+        assert_eq!(
+            locks.lock(3, LockKind::Exclusive, 99, 100),
+            Err(KError::WouldBlock)
+        );
+        assert_eq!(locks.lock(3, LockKind::Exclusive, 150, 200), Ok(()));
+        assert!(locks.can_lock(1, LockKind::Shared, 0, 100));
+        assert!(!locks.can_lock(1, LockKind::Exclusive, 0, 100));
+        assert_eq!(
+            locks.lock(1, LockKind::Exclusive, 0, 100),
+            Err(KError::WouldBlock)
+        );
+
+        // Process 2 only holds a shared lock on 100..150 after this.
+        assert_eq!(locks.unlock(2, 0, 100), Ok(()));
+        assert_eq!(locks.lock(1, LockKind::Exclusive, 0, 100), Ok(()));
+        assert_eq!(
+            locks.lock(2, LockKind::Shared, 0, 101),
+            Err(KError::WouldBlock)
+        );
+
+        locks.unlock_all(1);
+        assert_eq!(locks.lock(2, LockKind::Exclusive, 0, 150), Ok(()));
+    }
+
+    #[test]
+    /// Locking or unlocking part of an own lock splits it.
+    fn test_lock_split() {
+        let mut locks = LockTable::default();
+        assert_eq!(locks.lock(1, LockKind::Exclusive, 0, 100), Ok(()));
+        assert_eq!(locks.lock(1, LockKind::Shared, 40, 60), Ok(()));
+        assert_eq!(locks.locks.len(), 3);
+
+        // Only the middle is shared now.
+        assert_eq!(locks.lock(2, LockKind::Shared, 40, 60), Ok(()));
+        assert_eq!(
+            locks.lock(2, LockKind::Shared, 30, 50),
+            Err(KError::WouldBlock)
+        );
+
+        assert_eq!(locks.unlock(1, 0, 50), Ok(()));
+        assert_eq!(locks.lock(2, LockKind::Shared, 0, 40), Ok(()));
+        assert_eq!(
+            locks.lock(2, LockKind::Shared, 60, 70),
+            Err(KError::WouldBlock)
+        );
+        assert_eq!(locks.unlock(1, 0, u64::MAX), Ok(()));
+        assert_eq!(locks.lock(2, LockKind::Exclusive, 0, u64::MAX), Ok(()));
+    }
+}
@@ -2,17 +2,34 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use alloc::string::String;
+use alloc::vec::Vec;
 use core::convert::TryFrom;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU64, Ordering};
 
-use kpi::io::FileType;
+use kpi::io::{FileFlags, FileModes, FileType, WatchEvent};
 
 use crate::arch::process::UserSlice;
 use crate::error::KError;
 use crate::fallible_string::TryString;
+use crate::memory::PAddr;
 
+use super::dir::Directory;
 use super::file::*;
+use super::lock::LockTable;
+use super::pipe::{Pipe, PIPE_CAPACITY};
+use super::watch::{EventQueue, MAX_QUEUED_EVENTS};
 use super::{Mnode, Modes};
 
+/// Which timestamps of a memnode are updated by an operation.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum TimeUpdate {
+    /// The memnode was just created; sets all timestamps.
+    Created,
+    /// The content of the memnode was modified.
+    Modified,
+}
+
 /// Memnode representation, similar to Inode for a memory-fs.
 #[derive(Debug)]
 pub struct MemNode {
@@ -20,6 +37,23 @@ pub struct MemNode {
     name: String,
     node_type: FileType,
     file: Option<File>,
+    dir: Option<Directory>,
+    pipe: Option<Pipe>,
+    /// The events of file watches, for an event queue.
+    events: Option<EventQueue>,
+    /// The path a symbolic link points to.
+    target: Option<String>,
+    nlink: u64,
+    /// Number of open file descriptions referring to the mnode.
+    nopen: u64,
+    /// Advisory locks held on the mnode.
+    locks: LockTable,
+    /// Timestamps in nanoseconds since the unix epoch. These are atomics so
+    /// they can be updated while holding the lock in read mode. Reads don't
+    /// update the access time (like `noatime`), it stays the creation time.
+    ctime: AtomicU64,
+    mtime: AtomicU64,
+    atime: AtomicU64,
 }
 
 /// Required for the testing
@@ -29,6 +63,16 @@ impl PartialEq for MemNode {
             && (self.name == other.name)
             && (self.node_type == other.node_type)
             && (self.file == other.file)
+            && (self.dir == other.dir)
+            && (self.pipe == other.pipe)
+            && (self.events == other.events)
+            && (self.target == other.target)
+            && (self.nlink == other.nlink)
+            && (self.nopen == other.nopen)
+            && (self.locks == other.locks)
+            && (self.ctime.load(Ordering::Relaxed) == other.ctime.load(Ordering::Relaxed))
+            && (self.mtime.load(Ordering::Relaxed) == other.mtime.load(Ordering::Relaxed))
+            && (self.atime.load(Ordering::Relaxed) == other.atime.load(Ordering::Relaxed))
     }
 }
 
@@ -40,36 +84,71 @@ impl Default for MemNode {
             name: String::new(),
             node_type: FileType::File,
             file: None,
+            dir: None,
+            pipe: None,
+            events: None,
+            target: None,
+            nlink: 0,
+            nopen: 0,
+            locks: Default::default(),
+            ctime: AtomicU64::new(0),
+            mtime: AtomicU64::new(0),
+            atime: AtomicU64::new(0),
         }
     }
 }
 
 impl MemNode {
-    /// Initialize a memory-node for a directory or a file.
+    /// Initialize a memory-node for a directory, a file, a symbolic link, a
+    /// pipe or an event queue.
     pub fn new(
         mnode_num: Mnode,
-        pathname: &str,
+        name: &str,
         modes: Modes,
         node_type: FileType,
     ) -> Result<MemNode, KError> {
-        let file = match node_type {
-            FileType::Directory => None,
+        let (file, dir) = match node_type {
+            FileType::Directory => (None, Some(Directory::new(modes))),
             FileType::File => match File::new(modes) {
-                Ok(file) => Some(file),
+                Ok(file) => (Some(file), None),
                 Err(e) => return Err(e),
             },
+            // The target is set with `set_target` once the link is created.
+            FileType::Symlink | FileType::Pipe | FileType::Watch => (None, None),
+        };
+        let pipe = match node_type {
+            FileType::Pipe => Some(Pipe::new()?),
+            _ => None,
+        };
+        let events = match node_type {
+            FileType::Watch => Some(EventQueue::new()?),
+            _ => None,
         };
 
         Ok(MemNode {
             mnode_num,
-            name: TryString::try_from(pathname)?.into(),
+            name: TryString::try_from(name)?.into(),
             node_type,
             file,
+            dir,
+            pipe,
+            events,
+            target: None,
+            nlink: 1,
+            nopen: 0,
+            locks: Default::default(),
+            ctime: AtomicU64::new(0),
+            mtime: AtomicU64::new(0),
+            atime: AtomicU64::new(0),
         })
     }
 
     /// Write to an in-memory file.
     pub fn write(&mut self, buffer: &[u8], offset: usize) -> Result<usize, KError> {
+        // Pipes have no offset, the data is appended.
+        if let Some(pipe) = self.pipe.as_mut() {
+            return pipe.write(buffer);
+        }
         // Return if the user doesn't have write permissions for the file.
         if self.node_type != FileType::File || !self.file.as_ref().unwrap().get_mode().is_writable()
         {
@@ -80,6 +159,14 @@ impl MemNode {
         self.file.as_mut().unwrap().write_file(buffer, len, offset)
     }
 
+    /// Set the initial content of a file, ignoring its modes.
+    pub fn init_content(&mut self, buffer: &[u8]) -> Result<usize, KError> {
+        match self.file.as_mut() {
+            Some(file) => file.write_file(buffer, buffer.len(), 0),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
     /// Read from an in-memory file.
     pub fn read(&self, buffer: &mut UserSlice, offset: usize) -> Result<usize, KError> {
         // Return if the user doesn't have read permissions for the file.
@@ -118,21 +205,199 @@ impl MemNode {
         self.file.as_ref().unwrap().get_size()
     }
 
+    /// Memory (in bytes) allocated to hold the data of a file, pipe or
+    /// event queue.
+    pub fn get_allocated(&self) -> usize {
+        match (self.file.as_ref(), self.pipe.as_ref(), self.events.as_ref()) {
+            (Some(file), _, _) => file.get_allocated(),
+            (None, Some(_pipe), _) => PIPE_CAPACITY,
+            (None, None, Some(_events)) => MAX_QUEUED_EVENTS * size_of::<WatchEvent>(),
+            (None, None, None) => 0,
+        }
+    }
+
+    /// Take up to `len` bytes out of a pipe.
+    pub fn pipe_read(&mut self, len: usize) -> Result<Vec<u8>, KError> {
+        match self.pipe.as_mut() {
+            Some(pipe) => pipe.read(len),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Get the pipe of the mnode, `None` if it isn't a pipe.
+    pub fn get_pipe(&self) -> Option<&Pipe> {
+        self.pipe.as_ref()
+    }
+
+    /// Get the event queue of the mnode, `None` if it isn't one.
+    pub fn get_events(&self) -> Option<&EventQueue> {
+        self.events.as_ref()
+    }
+
+    /// Get the event queue of the mnode to add or take out events.
+    pub fn get_events_mut(&mut self) -> Option<&mut EventQueue> {
+        self.events.as_mut()
+    }
+
     /// Get the type of mnode; Directory or file.
     pub fn get_mnode_type(&self) -> FileType {
         self.node_type
     }
 
-    /// Truncate the file in reasponse of O_TRUNC flag.
-    pub fn file_truncate(&mut self) -> Result<(), KError> {
+    /// Get the mnode number.
+    pub fn get_mnode_num(&self) -> Mnode {
+        self.mnode_num
+    }
+
+    /// Get the modes the file or directory was created with.
+    pub fn get_modes(&self) -> FileModes {
+        match (&self.file, &self.dir) {
+            (Some(file), _) => file.get_mode(),
+            (None, Some(dir)) => dir.get_mode(),
+            // Symbolic links can always be followed; the target decides.
+            (None, None) if self.node_type == FileType::Symlink => FileModes::S_IRWXU,
+            (None, None) if self.node_type == FileType::Pipe => {
+                FileModes::S_IRUSR | FileModes::S_IWUSR
+            }
+            (None, None) if self.node_type == FileType::Watch => FileModes::S_IRUSR,
+            (None, None) => FileModes::empty(),
+        }
+    }
+
+    /// Get the number of links to the mnode.
+    pub fn get_nlink(&self) -> u64 {
+        self.nlink
+    }
+
+    /// Account for a new hard link to the mnode.
+    pub fn increase_nlink(&mut self) {
+        self.nlink += 1;
+    }
+
+    /// Account for a removed link, returns the number of remaining links.
+    pub fn decrease_nlink(&mut self) -> u64 {
+        self.nlink -= 1;
+        self.nlink
+    }
+
+    /// Account for a new open file description of the mnode.
+    pub fn increase_nopen(&mut self, flags: FileFlags) {
+        self.nopen += 1;
+        if let Some(pipe) = self.pipe.as_mut() {
+            pipe.open(flags);
+        }
+    }
+
+    /// Account for a closed file description, returns the number of
+    /// remaining ones.
+    pub fn decrease_nopen(&mut self, flags: FileFlags) -> u64 {
+        self.nopen -= 1;
+        if let Some(pipe) = self.pipe.as_mut() {
+            pipe.close(flags);
+        }
+        self.nopen
+    }
+
+    /// An mnode is removed once it has neither links nor open descriptions.
+    pub fn is_unused(&self) -> bool {
+        self.nlink == 0 && self.nopen == 0
+    }
+
+    /// Get the advisory locks held on the mnode.
+    pub fn get_locks(&self) -> &LockTable {
+        &self.locks
+    }
+
+    /// Get the advisory locks held on the mnode, to change them.
+    pub fn get_locks_mut(&mut self) -> &mut LockTable {
+        &mut self.locks
+    }
+
+    /// Get the path a symbolic link points to, `None` for other mnodes.
+    pub fn get_target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    /// Set the path a symbolic link points to.
+    pub fn set_target(&mut self, target: String) -> Result<(), KError> {
+        if self.node_type != FileType::Symlink {
+            return Err(KError::InvalidFile);
+        }
+        self.target = Some(target);
+        Ok(())
+    }
+
+    /// Get the (creation, modification, access) timestamps.
+    pub fn get_times(&self) -> (u64, u64, u64) {
+        (
+            self.ctime.load(Ordering::Relaxed),
+            self.mtime.load(Ordering::Relaxed),
+            self.atime.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Update the timestamps of the mnode, `time` is in nanoseconds since
+    /// the unix epoch.
+    pub fn update_time(&self, update: TimeUpdate, time: u64) {
+        match update {
+            TimeUpdate::Created => {
+                self.ctime.store(time, Ordering::Relaxed);
+                self.mtime.store(time, Ordering::Relaxed);
+                self.atime.store(time, Ordering::Relaxed);
+            }
+            TimeUpdate::Modified => self.mtime.store(time, Ordering::Relaxed),
+        }
+    }
+
+    /// Update the name of the mnode after it was renamed.
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    /// Get the directory entries; fails if the mnode is not a directory.
+    pub fn get_dir(&self) -> Result<&Directory, KError> {
+        self.dir.as_ref().ok_or(KError::DirectoryError)
+    }
+
+    /// Get the mutable directory entries; fails if the mnode is not a directory.
+    pub fn get_dir_mut(&mut self) -> Result<&mut Directory, KError> {
+        self.dir.as_mut().ok_or(KError::DirectoryError)
+    }
+
+    /// Truncate the file to `len` bytes, either for the O_TRUNC flag or an
+    /// explicit truncate call.
+    pub fn file_truncate(&mut self, len: usize) -> Result<(), KError> {
         if self.node_type != FileType::File || !self.file.as_ref().unwrap().get_mode().is_writable()
         {
             return Err(KError::PermissionError);
         }
 
-        // The method doesn't fail after this point, so returning Ok().
-        self.file.as_mut().unwrap().file_truncate();
-        Ok(())
+        self.file.as_mut().unwrap().file_truncate(len)
+    }
+
+    /// Get the physical memory regions backing a file range, to map them into a process.
+    pub fn map_regions(
+        &mut self,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<(PAddr, usize)>, KError> {
+        if self.node_type != FileType::File || !self.file.as_ref().unwrap().get_mode().is_readable()
+        {
+            return Err(KError::PermissionError);
+        }
+
+        self.file.as_mut().unwrap().map_regions(offset, len)
+    }
+
+    /// Drop a mapping of a file range that was returned by `map_regions`.
+    pub fn unmap_regions(&mut self, offset: usize, len: usize) -> Result<(), KError> {
+        match self.file.as_mut() {
+            Some(file) => {
+                file.unmap_regions(offset, len);
+                Ok(())
+            }
+            None => Err(KError::InvalidFile),
+        }
     }
 }
 
@@ -140,7 +405,6 @@ impl MemNode {
 pub mod test {
     use super::*;
     use alloc::string::ToString;
-    use kpi::io::*;
 
     #[test]
     /// Create mnode directory and verify the values.
@@ -149,6 +413,8 @@ pub mod test {
         let memnode =
             MemNode::new(1, filename, FileModes::S_IRWXU.into(), FileType::Directory).unwrap();
         assert_eq!(memnode.file, None);
+        assert_eq!(memnode.dir, Some(Directory::new(FileModes::S_IRWXU.into())));
+        assert_eq!(memnode.get_modes(), FileModes::S_IRWXU);
         assert_eq!(memnode.mnode_num, 1);
         assert_eq!(memnode.name, filename.to_string());
         assert_eq!(memnode.node_type, FileType::Directory);
@@ -163,6 +429,8 @@ pub mod test {
             memnode.file,
             Some(File::new(FileModes::S_IRWXU.into()).unwrap())
         );
+        assert_eq!(memnode.dir, None);
+        assert_eq!(memnode.get_dir(), Err(KError::DirectoryError));
         assert_eq!(memnode.mnode_num, 1);
         assert_eq!(memnode.name, filename.to_string());
         assert_eq!(memnode.node_type, FileType::File);
@@ -395,13 +663,29 @@ pub mod test {
         assert_eq!(30, memnode.get_file_size());
     }
 
+    #[test]
+    /// Test that timestamps are updated independently.
+    fn test_update_time() {
+        let memnode =
+            MemNode::new(1, "file.txt", FileModes::S_IRWXU.into(), FileType::File).unwrap();
+        assert_eq!(memnode.get_nlink(), 1);
+        assert_eq!(memnode.get_times(), (0, 0, 0));
+
+        memnode.update_time(TimeUpdate::Created, 10);
+        assert_eq!(memnode.get_times(), (10, 10, 10));
+        memnode.update_time(TimeUpdate::Modified, 20);
+        assert_eq!(memnode.get_times(), (10, 20, 10));
+        memnode.update_time(TimeUpdate::Created, 30);
+        assert_eq!(memnode.get_times(), (30, 30, 30));
+    }
+
     #[test]
     /// Test file_truncate for writable file; should succeed.
     fn test_file_truncate_for_writable_file() {
         let filename = "file.txt";
         let mut memnode =
             MemNode::new(1, filename, FileModes::S_IRWXU.into(), FileType::File).unwrap();
-        assert_eq!(memnode.file_truncate(), Ok(()));
+        assert_eq!(memnode.file_truncate(0), Ok(()));
     }
 
     #[test]
@@ -410,7 +694,7 @@ pub mod test {
         let filename = "file.txt";
         let mut memnode =
             MemNode::new(1, filename, FileModes::S_IRWXU.into(), FileType::Directory).unwrap();
-        assert_eq!(memnode.file_truncate(), Err(KError::PermissionError));
+        assert_eq!(memnode.file_truncate(0), Err(KError::PermissionError));
     }
 
     #[test]
@@ -419,6 +703,29 @@ pub mod test {
         let filename = "file.txt";
         let mut memnode =
             MemNode::new(1, filename, FileModes::S_IRUSR.into(), FileType::File).unwrap();
-        assert_eq!(memnode.file_truncate(), Err(KError::PermissionError));
+        assert_eq!(memnode.file_truncate(0), Err(KError::PermissionError));
+    }
+
+    #[test]
+    /// A symbolic link stores its target and has no content.
+    fn test_mnode_symlink() {
+        let mut memnode =
+            MemNode::new(1, "link", FileModes::S_IRWXU.into(), FileType::Symlink).unwrap();
+        assert_eq!(memnode.get_target(), None);
+        assert_eq!(memnode.set_target("dir/file.txt".to_string()), Ok(()));
+        assert_eq!(memnode.get_target(), Some("dir/file.txt"));
+        assert_eq!(memnode.get_modes(), FileModes::S_IRWXU);
+        assert_eq!(memnode.write(&[0xb; 10], 0), Err(KError::PermissionError));
+        assert_eq!(memnode.get_dir(), Err(KError::DirectoryError));
+
+        let mut memnode =
+            MemNode::new(2, "file.txt", FileModes::S_IRWXU.into(), FileType::File).unwrap();
+        assert_eq!(
+            memnode.set_target("dir".to_string()),
+            Err(KError::InvalidFile)
+        );
+        memnode.increase_nlink();
+        assert_eq!(memnode.get_nlink(), 2);
+        assert_eq!(memnode.decrease_nlink(), 1);
     }
 }
@@ -3,32 +3,46 @@
 
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+use fallible_collections::{FallibleVec, FallibleVecGlobal};
 use hashbrown::HashMap;
 use kpi::io::*;
-use spin::RwLock;
 
 use crate::arch::process::UserSlice;
 use crate::error::KError;
-use crate::fallible_string::TryString;
+use crate::fallible_string::{FallibleString, TryString};
+use crate::memory::PAddr;
+use crate::process::Pid;
 
 pub use rwlock::RwLock as NrLock;
 
+pub mod cpio;
 pub mod fd;
+pub mod lock;
 
+mod dir;
 mod file;
 mod mnode;
+mod pipe;
 mod rwlock;
 #[cfg(test)]
 mod test;
+mod watch;
 
+use lock::LockKind;
 use mnode::MemNode;
+pub use mnode::TimeUpdate;
+use watch::WatchTable;
 
 /// The maximum number of open files for a process.
 pub const MAX_FILES_PER_PROCESS: usize = 4096;
 
+/// The maximum number of symbolic links followed while resolving a path.
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+
 /// Mnode number.
 pub type Mnode = u64;
 /// Flags for fs calls.
@@ -59,9 +73,20 @@ pub trait FileSystem {
     fn lookup(&self, pathname: &str) -> Option<Arc<Mnode>>;
     fn file_info(&self, mnode: Mnode) -> FileInfo;
     fn delete(&self, pathname: &str) -> Result<(), KError>;
-    fn truncate(&self, pathname: &str) -> Result<(), KError>;
+    fn truncate(&self, mnode_num: Mnode, len: usize) -> Result<(), KError>;
+    fn map_regions(
+        &self,
+        mnode_num: Mnode,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<(PAddr, usize)>, KError>;
+    fn unmap_regions(&self, mnode_num: Mnode, offset: usize, len: usize) -> Result<(), KError>;
     fn rename(&self, oldname: &str, newname: &str) -> Result<(), KError>;
     fn mkdir(&self, pathname: &str, modes: Modes) -> Result<(), KError>;
+    fn readdir(&self, pathname: &str, buffer: &mut UserSlice) -> Result<usize, KError>;
+    fn link(&self, oldname: &str, newname: &str) -> Result<(), KError>;
+    fn symlink(&self, target: &str, linkname: &str) -> Result<Mnode, KError>;
+    fn readlink(&self, pathname: &str, buffer: &mut UserSlice) -> Result<usize, KError>;
 }
 
 /// Abstract definition of a file descriptor.
@@ -123,9 +148,10 @@ pub struct MlnrFS {
     /// Only create file will lock the hashmap in write mode,
     /// every other operation is locked in read mode.
     mnodes: NrLock<HashMap<Mnode, NrLock<MemNode>>>,
-    files: RwLock<HashMap<String, Arc<Mnode>>>,
     root: (String, Mnode),
     nextmemnode: AtomicUsize,
+    /// Taken after `mnodes` when both locks are needed.
+    watches: NrLock<WatchTable>,
 }
 
 unsafe impl Sync for MlnrFS {}
@@ -149,60 +175,594 @@ impl Default for MlnrFS {
                 .unwrap(),
             ),
         );
-        let files = RwLock::new(HashMap::new());
-        files.write().insert(
-            TryString::try_from(rootdir)
-                .expect("Not enough memory to initialize system")
-                .into(),
-            Arc::try_new(1).expect("Not enough memory to initialize system"),
-        );
         let root = (
             TryString::try_from(rootdir)
                 .expect("Not enough memory to initialize system")
                 .into(),
-            1,
+            rootmnode,
         );
 
         MlnrFS {
             mnodes,
-            files,
             root,
             nextmemnode: AtomicUsize::new(MNODE_OFFSET),
+            watches: Default::default(),
         }
     }
 }
 
+/// Split a path into its non-empty components.
+///
+/// Relative paths are resolved from the root directory.
+fn path_components(pathname: &str) -> impl Iterator<Item = &str> {
+    pathname.split('/').filter(|name| !name.is_empty())
+}
+
+/// Turn `pathname` into an absolute path without `.`, `..` or duplicate
+/// slashes; relative paths are resolved from the directory `cwd`.
+pub fn normalize_path(cwd: &str, pathname: &str) -> Result<String, KError> {
+    let base = if pathname.starts_with('/') { "" } else { cwd };
+
+    let mut components: Vec<&str> = Vec::new();
+    for name in path_components(base).chain(path_components(pathname)) {
+        match name {
+            "." => {}
+            // The parent of the root directory is the root directory.
+            ".." => {
+                components.pop();
+            }
+            name => components.try_push(name)?,
+        }
+    }
+
+    let mut normalized = String::try_with_capacity(base.len() + pathname.len() + 1)?;
+    if components.is_empty() {
+        normalized.try_push('/')?;
+    }
+    for name in components {
+        normalized.try_push('/')?;
+        normalized.try_push_str(name)?;
+    }
+    Ok(normalized)
+}
+
+/// Split a path into the path of its parent directory and the name of its
+/// last component. Returns `None` if the path refers to the root directory.
+fn split_path(pathname: &str) -> Option<(&str, &str)> {
+    let pathname = pathname.trim_end_matches('/');
+    if pathname.is_empty() {
+        return None;
+    }
+
+    match pathname.rfind('/') {
+        Some(idx) => Some((&pathname[..idx], &pathname[idx + 1..])),
+        None => Some(("", pathname)),
+    }
+}
+
+/// Turn the `offset` and `len` of a lock into a byte range; a length of 0
+/// means the range extends to the end of the file.
+fn lock_range(offset: u64, len: u64) -> Result<(u64, u64), KError> {
+    match len {
+        0 => Ok((offset, u64::MAX)),
+        len => offset
+            .checked_add(len)
+            .map(|end| (offset, end))
+            .ok_or(KError::InvalidOffset),
+    }
+}
+
+/// Find the entry `name` in the directory `parent`.
+fn dir_entry(
+    mnodes: &HashMap<Mnode, NrLock<MemNode>>,
+    parent: Mnode,
+    name: &str,
+) -> Result<Option<Mnode>, KError> {
+    let parent = mnodes.get(&parent).ok_or(KError::InvalidFile)?.read();
+    Ok(parent.get_dir()?.lookup(name).map(|mnode| **mnode))
+}
+
+/// Check if `mnode_num` is an event queue.
+fn is_watch(mnodes: &HashMap<Mnode, NrLock<MemNode>>, mnode_num: Mnode) -> bool {
+    mnodes
+        .get(&mnode_num)
+        .map_or(false, |memnode| memnode.read().get_events().is_some())
+}
+
+/// Check if `mnode_num` is a directory.
+fn is_dir(mnodes: &HashMap<Mnode, NrLock<MemNode>>, mnode_num: Mnode) -> bool {
+    mnodes
+        .get(&mnode_num)
+        .map_or(false, |memnode| memnode.read().get_dir().is_ok())
+}
+
+/// Check if the path `pathname` is the directory `dir` itself or somewhere
+/// below it; both paths are normalized and don't contain symbolic links.
+fn contains(dir: &str, pathname: &str) -> bool {
+    pathname.starts_with(dir)
+        && (pathname.len() == dir.len() || pathname.as_bytes()[dir.len()] == b'/')
+}
+
+/// Drop a link to `mnode_num` after it was removed from its directory. The
+/// mnode goes away with its last link, unless it is still open; then it's
+/// removed when the last file description is released.
+fn unlink(mnodes: &mut HashMap<Mnode, NrLock<MemNode>>, mnode_num: Mnode) {
+    let unused = mnodes.get(&mnode_num).map_or(false, |memnode| {
+        let mut memnode = memnode.write();
+        memnode.decrease_nlink();
+        memnode.is_unused()
+    });
+    if unused {
+        mnodes.remove(&mnode_num);
+    }
+}
+
+/// Outcome of walking a path up to the first symbolic link.
+enum Walk {
+    /// The path was resolved to this mnode.
+    Found(Mnode),
+    /// The path with the symbolic link replaced by its target.
+    Symlink(String),
+}
+
 impl MlnrFS {
     /// Get the next available memnode number.
     fn get_next_mno(&self) -> usize {
         self.nextmemnode.fetch_add(1, Ordering::Relaxed)
     }
-}
 
-impl FileSystem for MlnrFS {
-    fn create(&self, pathname: &str, modes: Modes) -> Result<u64, KError> {
-        // Check if the file with the same name already exists.
-        if self.files.read().get(pathname).is_some() {
-            return Err(KError::AlreadyPresent);
+    /// Walk the directory tree from the root and find the mnode for `pathname`,
+    /// following all symbolic links.
+    fn resolve(
+        &self,
+        mnodes: &HashMap<Mnode, NrLock<MemNode>>,
+        pathname: &str,
+    ) -> Result<Mnode, KError> {
+        self.walk(mnodes, pathname, true)
+    }
+
+    /// Find the mnode for `pathname`; a symbolic link in the last component
+    /// is only followed if `follow` is set.
+    fn walk(
+        &self,
+        mnodes: &HashMap<Mnode, NrLock<MemNode>>,
+        pathname: &str,
+        follow: bool,
+    ) -> Result<Mnode, KError> {
+        self.walk_expanded(mnodes, pathname, follow)
+            .map(|(mnode_num, _expanded)| mnode_num)
+    }
+
+    /// Like `walk`, also returns the path with all symbolic links expanded if
+    /// it went through any.
+    fn walk_expanded(
+        &self,
+        mnodes: &HashMap<Mnode, NrLock<MemNode>>,
+        pathname: &str,
+        follow: bool,
+    ) -> Result<(Mnode, Option<String>), KError> {
+        let mut expanded: Option<String> = None;
+        for _depth in 0..=MAX_SYMLINK_DEPTH {
+            let pathname = expanded.as_deref().unwrap_or(pathname);
+            match self.walk_once(mnodes, pathname, follow)? {
+                Walk::Found(mnode_num) => return Ok((mnode_num, expanded)),
+                Walk::Symlink(next) => expanded = Some(next),
+            }
         }
-        let pathname_string = TryString::try_from(pathname)?.into();
+        Err(KError::SymlinkLoop)
+    }
 
-        let mnode_num = self.get_next_mno() as u64;
-        // TODO(error-handling): can we ignore or should we decrease mnode_num
-        // on error?
-        let arc_mnode_num = Arc::try_new(mnode_num)?;
+    /// Walk the directory tree from the root until the end of `pathname` or
+    /// the first symbolic link that has to be followed.
+    fn walk_once(
+        &self,
+        mnodes: &HashMap<Mnode, NrLock<MemNode>>,
+        pathname: &str,
+        follow: bool,
+    ) -> Result<Walk, KError> {
+        let mut mnode_num = self.root.1;
+        let mut start = 0;
+        for name in pathname.split('/') {
+            let (parent, end) = (&pathname[..start], start + name.len());
+            start = end + 1;
+            if name.is_empty() {
+                continue;
+            }
+
+            let next = {
+                let memnode = mnodes.get(&mnode_num).ok_or(KError::InvalidFile)?.read();
+                **memnode.get_dir()?.lookup(name).ok_or(KError::InvalidFile)?
+            };
+
+            let rest = &pathname[end..];
+            if follow || path_components(rest).next().is_some() {
+                let memnode = mnodes.get(&next).ok_or(KError::InvalidFile)?.read();
+                if let Some(target) = memnode.get_target() {
+                    // Relative targets are resolved from the directory of the link.
+                    let mut expanded = normalize_path(parent, target)?;
+                    expanded.try_push_str(rest)?;
+                    return Ok(Walk::Symlink(expanded));
+                }
+            }
+            mnode_num = next;
+        }
+        Ok(Walk::Found(mnode_num))
+    }
+
+    /// Check if walking `pathname` runs into a symbolic link; the last
+    /// component only counts if `follow` is set.
+    pub fn crosses_symlink(&self, pathname: &str, follow: bool) -> bool {
+        matches!(
+            self.walk_once(&self.mnodes.read(), pathname, follow),
+            Ok(Walk::Symlink(_))
+        )
+    }
+
+    /// Get the number of mnodes and the memory (in bytes) allocated for the
+    /// data of all files and pipes.
+    pub fn usage(&self) -> (usize, usize) {
+        let mnodes = self.mnodes.read();
+        let allocated = mnodes
+            .values()
+            .map(|memnode| memnode.read().get_allocated())
+            .sum();
+        (mnodes.len(), allocated)
+    }
+
+    /// Account for a new open file description of an mnode.
+    pub fn acquire(&self, mnode_num: Mnode, flags: FileFlags) -> Result<(), KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => {
+                memnode.write().increase_nopen(flags);
+                Ok(())
+            }
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Release an open file description of an mnode; an mnode that was
+    /// deleted while it was open is removed with its last description.
+    pub fn release(&self, mnode_num: Mnode, flags: FileFlags) -> Result<(), KError> {
+        let unused = match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => {
+                let mut memnode = memnode.write();
+                memnode.decrease_nopen(flags);
+                memnode.is_unused()
+            }
+            None => return Err(KError::InvalidFile),
+        };
+        // An unused mnode can't be reached anymore, so nothing changes it
+        // before we get the map lock.
+        if unused {
+            if let Some(memnode) = self.mnodes.write().remove(&mnode_num) {
+                if memnode.read().get_events().is_some() {
+                    self.watches.write().remove_queue(mnode_num);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Take an advisory lock for `pid` on `len` bytes of an mnode, starting
+    /// at `offset`; a length of 0 locks up to the end of the file, however
+    /// large it gets.
+    pub fn lock(
+        &self,
+        mnode_num: Mnode,
+        pid: Pid,
+        kind: LockKind,
+        offset: u64,
+        len: u64,
+    ) -> Result<(), KError> {
+        let (start, end) = lock_range(offset, len)?;
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().get_locks_mut().lock(pid, kind, start, end),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Check if `pid` can take an advisory lock on a range of an mnode
+    /// without waiting.
+    pub fn can_lock(
+        &self,
+        mnode_num: Mnode,
+        pid: Pid,
+        kind: LockKind,
+        offset: u64,
+        len: u64,
+    ) -> Result<bool, KError> {
+        let (start, end) = lock_range(offset, len)?;
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => Ok(memnode.read().get_locks().can_lock(pid, kind, start, end)),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Release the advisory locks `pid` holds on a range of an mnode.
+    pub fn unlock(&self, mnode_num: Mnode, pid: Pid, offset: u64, len: u64) -> Result<(), KError> {
+        let (start, end) = lock_range(offset, len)?;
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().get_locks_mut().unlock(pid, start, end),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Release all advisory locks `pid` holds on an mnode.
+    pub fn unlock_all(&self, mnode_num: Mnode, pid: Pid) -> Result<(), KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => {
+                memnode.write().get_locks_mut().unlock_all(pid);
+                Ok(())
+            }
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Create an anonymous pipe with the mnode number `mnode_num`. The pipe
+    /// has no name and is removed once both of its ends are released.
+    pub fn pipe(&self, mnode_num: Mnode) -> Result<(), KError> {
         let mut mnodes = self.mnodes.write();
+        debug_assert!(!mnodes.contains_key(&mnode_num), "Mnode number in use");
         mnodes.try_reserve(1)?;
 
-        // TODO: For now all newly created mnode are for file. How to differentiate
-        // between a file and a directory. Take input from the user?
-        let memnode = MemNode::new(mnode_num, pathname, modes, FileType::File)?;
+        let mut memnode = MemNode::new(mnode_num, "", FileModes::S_IRWXU.into(), FileType::Pipe)?;
+        memnode.decrease_nlink();
+        mnodes.insert(mnode_num, NrLock::new(memnode));
+        Ok(())
+    }
+
+    /// Take up to `len` bytes out of a pipe.
+    pub fn pipe_read(&self, mnode_num: Mnode, len: usize) -> Result<Vec<u8>, KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().pipe_read(len),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Check if a read or write (depending on `flags`) on `mnode_num` can
+    /// make progress without waiting; only pipes and event queues ever have
+    /// to wait.
+    pub fn is_ready(&self, mnode_num: Mnode, flags: FileFlags) -> Result<bool, KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => {
+                let memnode = memnode.read();
+                match (memnode.get_pipe(), memnode.get_events()) {
+                    (Some(pipe), _) => Ok(pipe.is_ready(flags)),
+                    (_, Some(events)) => Ok(!events.is_empty()),
+                    _ => Ok(true),
+                }
+            }
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Check if `mnode_num` is a pipe.
+    pub fn is_pipe(&self, mnode_num: Mnode) -> bool {
+        self.mnodes
+            .read()
+            .get(&mnode_num)
+            .map_or(false, |memnode| memnode.read().get_pipe().is_some())
+    }
+
+    /// Create an anonymous event queue for file watches with the mnode
+    /// number `mnode_num`; it is removed once it is released.
+    pub fn watch_init(&self, mnode_num: Mnode) -> Result<(), KError> {
+        let mut mnodes = self.mnodes.write();
+        debug_assert!(!mnodes.contains_key(&mnode_num), "Mnode number in use");
+        mnodes.try_reserve(1)?;
 
-        self.files.write().insert(pathname_string, arc_mnode_num);
+        let mut memnode = MemNode::new(mnode_num, "", FileModes::S_IRUSR.into(), FileType::Watch)?;
+        memnode.decrease_nlink();
         mnodes.insert(mnode_num, NrLock::new(memnode));
+        Ok(())
+    }
 
+    /// Let the event queue `queue` watch `pathname` for `events`, returns
+    /// the watch descriptor.
+    pub fn watch_add(
+        &self,
+        queue: Mnode,
+        pathname: &str,
+        events: WatchEvents,
+    ) -> Result<u64, KError> {
+        let mnodes = self.mnodes.read();
+        if !is_watch(&mnodes, queue) {
+            return Err(KError::InvalidFile);
+        }
+        let target = self.resolve(&mnodes, pathname)?;
+        self.watches.write().add(queue, target, events)
+    }
+
+    /// Remove the watch `wd` from the event queue `queue`.
+    pub fn watch_remove(&self, queue: Mnode, wd: u64) -> Result<(), KError> {
+        let mnodes = self.mnodes.read();
+        if !is_watch(&mnodes, queue) {
+            return Err(KError::InvalidFile);
+        }
+        self.watches.write().remove(queue, wd)
+    }
+
+    /// Take up to `count` events out of the event queue `queue`.
+    pub fn read_events(&self, queue: Mnode, count: usize) -> Result<Vec<WatchEvent>, KError> {
+        match self.mnodes.read().get(&queue) {
+            Some(memnode) => match memnode.write().get_events_mut() {
+                Some(events) => events.read(count),
+                None => Err(KError::InvalidFile),
+            },
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Check if `mnode_num` is an event queue.
+    pub fn is_watch(&self, mnode_num: Mnode) -> bool {
+        is_watch(&self.mnodes.read(), mnode_num)
+    }
+
+    /// Check if a queue watches `target` for `event`.
+    pub fn is_watched(&self, target: Mnode, event: WatchEvents) -> bool {
+        self.watches.read().matching(target, event).next().is_some()
+    }
+
+    /// Report `event` on the mnode `mnode` to the queues that watch `target`;
+    /// that is either `mnode` itself or the directory that holds it.
+    pub fn notify(&self, target: Mnode, event: WatchEvents, mnode: Mnode) {
+        let mnodes = self.mnodes.read();
+        let watches = self.watches.read();
+        for (queue, wd) in watches.matching(target, event) {
+            if let Some(events) = mnodes.get(&queue) {
+                if let Some(events) = events.write().get_events_mut() {
+                    events.push(WatchEvent {
+                        wd,
+                        mask: event.bits(),
+                        mnode,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Find the directory that holds `pathname`.
+    pub fn lookup_parent(&self, pathname: &str) -> Option<Mnode> {
+        let (parent, _name) = split_path(pathname)?;
+        self.resolve(&self.mnodes.read(), parent).ok()
+    }
+
+    /// Find the mnode for `pathname` without following a symbolic link in
+    /// the last component.
+    pub fn lookup_nofollow(&self, pathname: &str) -> Option<Mnode> {
+        self.walk(&self.mnodes.read(), pathname, false).ok()
+    }
+
+    /// Update the timestamps of an mnode, `time` is in nanoseconds since the
+    /// unix epoch.
+    ///
+    /// The time is passed in by the caller (and not read here) so every
+    /// replica stores the same timestamps.
+    pub fn update_time(
+        &self,
+        mnode_num: Mnode,
+        update: TimeUpdate,
+        time: u64,
+    ) -> Result<(), KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(mnode) => {
+                mnode.read().update_time(update, time);
+                Ok(())
+            }
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    /// Create a file or directory for an entry of the initial ramdisk.
+    ///
+    /// Directories that already exist are reused; the content of files is
+    /// written regardless of their modes, so read-only files can be unpacked.
+    /// A new file or directory gets the mnode number `mnode_num`.
+    pub fn unpack(
+        &self,
+        pathname: &str,
+        modes: Modes,
+        node_type: FileType,
+        data: &[u8],
+        mnode_num: Mnode,
+    ) -> Result<Mnode, KError> {
+        match self.create_mnode(pathname, modes, node_type, Some(mnode_num)) {
+            Ok(mnode_num) if node_type == FileType::File => {
+                match self.mnodes.read().get(&mnode_num) {
+                    Some(mnode) => mnode.write().init_content(data)?,
+                    None => return Err(KError::InvalidFile),
+                };
+                Ok(mnode_num)
+            }
+            Ok(mnode_num) => Ok(mnode_num),
+            Err(KError::AlreadyPresent) if node_type == FileType::Directory => {
+                let mnodes = self.mnodes.read();
+                let mnode_num = self.resolve(&mnodes, pathname)?;
+                match mnodes.get(&mnode_num).map(|m| m.read().get_mnode_type()) {
+                    Some(FileType::Directory) => Ok(mnode_num),
+                    _ => Err(KError::AlreadyPresent),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create a new file or directory and add it to the parent directory.
+    ///
+    /// The caller can pick the mnode number, so every replica uses the same
+    /// number no matter in which order it applies operations of different
+    /// logs. Otherwise, the next available number is used.
+    pub fn create_mnode(
+        &self,
+        pathname: &str,
+        modes: Modes,
+        node_type: FileType,
+        mnode_num: Option<Mnode>,
+    ) -> Result<Mnode, KError> {
+        // The root directory always exists.
+        let (parent, name) = split_path(pathname).ok_or(KError::AlreadyPresent)?;
+
+        let mut mnodes = self.mnodes.write();
+        let parent_mnode = self.resolve(&mnodes, parent)?;
+        // Check if the file with the same name already exists.
+        if mnodes
+            .get(&parent_mnode)
+            .ok_or(KError::InvalidFile)?
+            .read()
+            .get_dir()?
+            .contains(name)
+        {
+            return Err(KError::AlreadyPresent);
+        }
+        mnodes.try_reserve(1)?;
+
+        let mnode_num = mnode_num.unwrap_or_else(|| self.get_next_mno() as u64);
+        debug_assert!(!mnodes.contains_key(&mnode_num), "Mnode number in use");
+        let arc_mnode_num = Arc::try_new(mnode_num)?;
+        let memnode = MemNode::new(mnode_num, name, modes, node_type)?;
+
+        mnodes
+            .get(&parent_mnode)
+            .ok_or(KError::InvalidFile)?
+            .write()
+            .get_dir_mut()?
+            .insert(name, arc_mnode_num)?;
+        mnodes.insert(mnode_num, NrLock::new(memnode));
+
+        Ok(mnode_num)
+    }
+
+    /// Create a symbolic link to `target`; the mnode number is picked like
+    /// for `create_mnode`.
+    pub fn create_symlink(
+        &self,
+        target: &str,
+        linkname: &str,
+        mnode_num: Option<Mnode>,
+    ) -> Result<Mnode, KError> {
+        if target.is_empty() {
+            return Err(KError::InvalidFile);
+        }
+
+        let target = TryString::try_from(target)?.into();
+        let mnode_num = self.create_mnode(
+            linkname,
+            FileModes::S_IRWXU.into(),
+            FileType::Symlink,
+            mnode_num,
+        )?;
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().set_target(target)?,
+            None => return Err(KError::InvalidFile),
+        };
         Ok(mnode_num)
     }
+}
+
+impl FileSystem for MlnrFS {
+    fn create(&self, pathname: &str, modes: Modes) -> Result<u64, KError> {
+        self.create_mnode(pathname, modes, FileType::File, None)
+    }
 
     fn write(&self, mnode_num: Mnode, buffer: &[u8], offset: usize) -> Result<usize, KError> {
         match self.mnodes.read().get(&mnode_num) {
@@ -224,96 +784,249 @@ impl FileSystem for MlnrFS {
     }
 
     fn lookup(&self, pathname: &str) -> Option<Arc<Mnode>> {
-        self.files.read().get(pathname).cloned()
+        let mnode_num = self.resolve(&self.mnodes.read(), pathname).ok()?;
+        Arc::try_new(mnode_num).ok()
     }
 
     fn file_info(&self, mnode: Mnode) -> FileInfo {
         match self.mnodes.read().get(&mnode) {
-            Some(mnode) => match mnode.read().get_mnode_type() {
-                FileType::Directory => FileInfo {
-                    fsize: 0,
-                    ftype: FileType::Directory.into(),
-                },
-                FileType::File => FileInfo {
-                    fsize: mnode.read().get_file_size() as u64,
-                    ftype: FileType::File.into(),
-                },
-            },
+            Some(mnode) => {
+                let memnode = mnode.read();
+                let fsize = match memnode.get_mnode_type() {
+                    FileType::Directory => 0,
+                    FileType::File => memnode.get_file_size() as u64,
+                    FileType::Symlink => memnode.get_target().map_or(0, |t| t.len()) as u64,
+                    FileType::Pipe => memnode.get_pipe().map_or(0, |pipe| pipe.len()) as u64,
+                    FileType::Watch => memnode.get_events().map_or(0, |events| {
+                        events.len() * core::mem::size_of::<WatchEvent>()
+                    }) as u64,
+                };
+                let (ctime, mtime, atime) = memnode.get_times();
+
+                FileInfo {
+                    ftype: memnode.get_mnode_type().into(),
+                    fsize,
+                    fmode: memnode.get_modes().into(),
+                    mnode: memnode.get_mnode_num(),
+                    nlink: memnode.get_nlink(),
+                    ctime,
+                    mtime,
+                    atime,
+                }
+            }
             None => unreachable!("file_info: shouldn't reach here"),
         }
     }
 
     fn delete(&self, pathname: &str) -> Result<(), KError> {
-        let mut files = self.files.write();
-        if let Some(mnode) = files.get(pathname) {
-            if Arc::strong_count(mnode) == 1 {
-                self.mnodes.write().remove(mnode);
-            } else {
-                return Err(KError::PermissionError);
+        // The root directory can't be removed.
+        let (parent, name) = split_path(pathname).ok_or(KError::PermissionError)?;
+
+        let mut mnodes = self.mnodes.write();
+        let parent_mnode = self.resolve(&mnodes, parent)?;
+        let mnode_num = {
+            let parent = mnodes.get(&parent_mnode).ok_or(KError::InvalidFile)?.read();
+            **parent.get_dir()?.lookup(name).ok_or(KError::InvalidFile)?
+        };
+
+        // Only empty directories can be removed.
+        if let Some(memnode) = mnodes.get(&mnode_num) {
+            if let Ok(dir) = memnode.read().get_dir() {
+                if !dir.is_empty() {
+                    return Err(KError::DirectoryError);
+                }
             }
-        } else {
-            return Err(KError::InvalidFile);
         }
 
-        let r = files.remove(pathname);
+        let r = mnodes
+            .get(&parent_mnode)
+            .ok_or(KError::InvalidFile)?
+            .write()
+            .get_dir_mut()?
+            .remove(name);
         assert!(r.is_some(), "Didn't remove the mnode?");
+
+        unlink(&mut mnodes, mnode_num);
         Ok(())
     }
 
-    fn truncate(&self, pathname: &str) -> Result<(), KError> {
-        match self.files.read().get(pathname) {
-            Some(mnode) => match self.mnodes.read().get(mnode) {
-                Some(memnode) => memnode.write().file_truncate(),
-                None => Err(KError::InvalidFile),
-            },
+    fn truncate(&self, mnode_num: Mnode, len: usize) -> Result<(), KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().file_truncate(len),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    fn map_regions(
+        &self,
+        mnode_num: Mnode,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<(PAddr, usize)>, KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().map_regions(offset, len),
+            None => Err(KError::InvalidFile),
+        }
+    }
+
+    fn unmap_regions(&self, mnode_num: Mnode, offset: usize, len: usize) -> Result<(), KError> {
+        match self.mnodes.read().get(&mnode_num) {
+            Some(memnode) => memnode.write().unmap_regions(offset, len),
             None => Err(KError::InvalidFile),
         }
     }
 
     fn rename(&self, oldname: &str, newname: &str) -> Result<(), KError> {
-        if self.files.read().get(oldname).is_none() {
-            return Err(KError::InvalidFile);
+        // The root directory can't be renamed or replaced.
+        let (old_parent_path, old_name) = split_path(oldname).ok_or(KError::PermissionError)?;
+        let (new_parent_path, new_name) = split_path(newname).ok_or(KError::PermissionError)?;
+        let name: String = TryString::try_from(new_name)?.into();
+
+        // Everything is checked before the first modification and the whole
+        // rename happens under the write lock, so it either fails without
+        // changing anything or lookups see the new name in place of the old.
+        let mut mnodes = self.mnodes.write();
+        let (old_parent, old_expanded) = self.walk_expanded(&mnodes, old_parent_path, true)?;
+        let (new_parent, new_expanded) = self.walk_expanded(&mnodes, new_parent_path, true)?;
+        let old_mnode = dir_entry(&mnodes, old_parent, old_name)?.ok_or(KError::InvalidFile)?;
+        let new_mnode = dir_entry(&mnodes, new_parent, new_name)?;
+
+        // Nothing to do if both names are links to the same file.
+        if new_mnode == Some(old_mnode) {
+            return Ok(());
+        }
+        // A directory can't be moved into itself. Directories have a single
+        // link, so this is decided by the paths with the symbolic links expanded.
+        let old_is_dir = is_dir(&mnodes, old_mnode);
+        if old_is_dir {
+            let old_parent_path = old_expanded.as_deref().unwrap_or(old_parent_path);
+            let new_parent_path = new_expanded.as_deref().unwrap_or(new_parent_path);
+            if contains(
+                &normalize_path(old_parent_path, old_name)?,
+                &normalize_path("/", new_parent_path)?,
+            ) {
+                return Err(KError::PermissionError);
+            }
+        }
+        // A directory can only replace an empty directory, a file only a file.
+        if let Some(new_mnode) = new_mnode {
+            let new_memnode = mnodes.get(&new_mnode).ok_or(KError::InvalidFile)?.read();
+            match (old_is_dir, new_memnode.get_dir()) {
+                (true, Ok(dir)) if dir.is_empty() => {}
+                (false, Err(_e)) => {}
+                _ => return Err(KError::DirectoryError),
+            }
         }
-        let newname_key = TryString::try_from(newname)?.into();
 
-        // If the newfile exists then overwrite it with the oldfile.
-        if self.files.read().get(newname).is_some() {
-            self.delete(newname).unwrap();
+        let entry = mnodes
+            .get(&old_parent)
+            .ok_or(KError::InvalidFile)?
+            .read()
+            .get_dir()?
+            .lookup(old_name)
+            .ok_or(KError::InvalidFile)?
+            .clone();
+        {
+            let mut parent = mnodes.get(&new_parent).ok_or(KError::InvalidFile)?.write();
+            let dir = parent.get_dir_mut()?;
+            if new_mnode.is_some() {
+                // Replacing an entry doesn't allocate, so it can't fail.
+                dir.replace(new_name, entry);
+            } else {
+                dir.insert(new_name, entry)?;
+            }
         }
+        let r = mnodes
+            .get(&old_parent)
+            .ok_or(KError::InvalidFile)?
+            .write()
+            .get_dir_mut()?
+            .remove(old_name);
+        assert!(r.is_some(), "Didn't remove the old name?");
 
-        // TODO: Can we optimize it somehow?
-        let mut lock_at_root = self.files.write();
-        match lock_at_root.remove_entry(oldname) {
-            Some((_key, oldnmode)) => match lock_at_root.insert(newname_key, oldnmode) {
-                None => Ok(()),
-                Some(_) => Err(KError::PermissionError),
-            },
-            None => Err(KError::InvalidFile),
+        if let Some(memnode) = mnodes.get(&old_mnode) {
+            memnode.write().set_name(name);
         }
+        if let Some(new_mnode) = new_mnode {
+            unlink(&mut mnodes, new_mnode);
+        }
+        Ok(())
     }
 
-    /// Create a directory. The implementation is quite simplistic for now, and only used
-    /// by leveldb benchmark.
     fn mkdir(&self, pathname: &str, modes: Modes) -> Result<(), KError> {
-        // Check if the file with the same name already exists.
-        if self.files.read().get(pathname).is_some() {
-            return Err(KError::AlreadyPresent);
+        self.create_mnode(pathname, modes, FileType::Directory, None)
+            .map(|_mnode_num| ())
+    }
+
+    fn readdir(&self, pathname: &str, buffer: &mut UserSlice) -> Result<usize, KError> {
+        let mnodes = self.mnodes.read();
+        let mnode_num = self.resolve(&mnodes, pathname)?;
+        let memnode = mnodes.get(&mnode_num).ok_or(KError::InvalidFile)?.read();
+        let dir = memnode.get_dir()?;
+
+        let mut entries: Vec<DirEntry> = Vec::try_with_capacity(dir.len())?;
+        for (name, mnode) in dir.iter() {
+            let ftype = mnodes
+                .get(mnode)
+                .ok_or(KError::InvalidFile)?
+                .read()
+                .get_mnode_type();
+            entries.try_push(DirEntry {
+                name: name.as_str(),
+                mnode: **mnode,
+                ftype: ftype.into(),
+            })?;
         }
+        entries.sort_unstable_by(|a, b| a.name.cmp(b.name));
 
-        let pathname_key = TryString::try_from(pathname)?.into();
-        let mnode_num = self.get_next_mno() as u64;
-        // TODO(error-handling): Should we decrease mnode-num or ignore?
-        let arc_mnode_num = Arc::try_new(mnode_num)?;
-        let mut mnodes = self.mnodes.write();
-        mnodes.try_reserve(1)?;
+        // Like `GetProcessInfo`, only copy the entries if they fit in the
+        // buffer but always return the serialized length.
+        let serialized = serde_cbor::to_vec(&entries).map_err(|_e| KError::OutOfMemory)?;
+        if serialized.len() <= buffer.len() {
+            buffer[..serialized.len()].copy_from_slice(serialized.as_slice());
+        }
+        Ok(serialized.len())
+    }
 
-        let memnode = match MemNode::new(mnode_num, pathname, modes, FileType::Directory) {
-            Ok(memnode) => memnode,
-            Err(e) => return Err(e),
-        };
-        self.files.write().insert(pathname_key, arc_mnode_num);
-        mnodes.insert(mnode_num, NrLock::new(memnode));
+    fn link(&self, oldname: &str, newname: &str) -> Result<(), KError> {
+        // Like `linkat` without `AT_SYMLINK_FOLLOW`, a symbolic link in
+        // `oldname` is linked itself.
+        let (parent, name) = split_path(newname).ok_or(KError::AlreadyPresent)?;
 
+        let mnodes = self.mnodes.read();
+        let mnode_num = self.walk(&mnodes, oldname, false)?;
+        let memnode = mnodes.get(&mnode_num).ok_or(KError::InvalidFile)?;
+        // Hard links to directories would allow cycles in the tree.
+        if memnode.read().get_mnode_type() == FileType::Directory {
+            return Err(KError::PermissionError);
+        }
+
+        let parent_mnode = self.resolve(&mnodes, parent)?;
+        mnodes
+            .get(&parent_mnode)
+            .ok_or(KError::InvalidFile)?
+            .write()
+            .get_dir_mut()?
+            .insert(name, Arc::try_new(mnode_num)?)?;
+        memnode.write().increase_nlink();
         Ok(())
     }
+
+    fn symlink(&self, target: &str, linkname: &str) -> Result<Mnode, KError> {
+        self.create_symlink(target, linkname, None)
+    }
+
+    fn readlink(&self, pathname: &str, buffer: &mut UserSlice) -> Result<usize, KError> {
+        let mnodes = self.mnodes.read();
+        let mnode_num = self.walk(&mnodes, pathname, false)?;
+        let memnode = mnodes.get(&mnode_num).ok_or(KError::InvalidFile)?.read();
+        let target = memnode.get_target().ok_or(KError::InvalidFile)?;
+
+        // Like `readdir`, only copy the target if it fits in the buffer but
+        // always return its length.
+        if target.len() <= buffer.len() {
+            buffer[..target.len()].copy_from_slice(target.as_bytes());
+        }
+        Ok(target.len())
+    }
 }
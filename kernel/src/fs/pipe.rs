@@ -0,0 +1,174 @@
+// Copyright © 2021 VMware, Inc. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! An anonymous pipe; a bounded ring buffer with a read and a write end.
+
+use alloc::vec::Vec;
+
+use fallible_collections::FallibleVecGlobal;
+use kpi::io::FileFlags;
+
+use crate::error::KError;
+use crate::memory::BASE_PAGE_SIZE;
+
+/// Number of bytes a pipe can hold; writes to a full pipe are cut short.
+pub const PIPE_CAPACITY: usize = BASE_PAGE_SIZE;
+
+#[derive(Debug, PartialEq)]
+pub struct Pipe {
+    buffer: Vec<u8>,
+    /// Index of the oldest unread byte in `buffer`.
+    head: usize,
+    /// Number of unread bytes.
+    len: usize,
+    /// Open file descriptions of the read end.
+    readers: usize,
+    /// Open file descriptions of the write end.
+    writers: usize,
+}
+
+impl Pipe {
+    /// Create an empty pipe without any open ends.
+    pub fn new() -> Result<Pipe, KError> {
+        let mut buffer = Vec::try_with_capacity(PIPE_CAPACITY)?;
+        buffer.resize(PIPE_CAPACITY, 0);
+        Ok(Pipe {
+            buffer,
+            head: 0,
+            len: 0,
+            readers: 0,
+            writers: 0,
+        })
+    }
+
+    /// Number of bytes that are waiting to be read.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there is nothing to read.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check if a read (for `O_RDONLY`) or a write (for `O_WRONLY`) on the
+    /// pipe returns without waiting.
+    pub fn is_ready(&self, flags: FileFlags) -> bool {
+        let readable = !self.is_empty() || self.writers == 0;
+        let writable = self.len < PIPE_CAPACITY || self.readers == 0;
+        (!flags.is_read() || readable) && (!flags.is_write() || writable)
+    }
+
+    /// Account for a new open file description of the read or write end.
+    pub fn open(&mut self, flags: FileFlags) {
+        if flags.is_read() {
+            self.readers += 1;
+        }
+        if flags.is_write() {
+            self.writers += 1;
+        }
+    }
+
+    /// Account for a closed file description of the read or write end.
+    pub fn close(&mut self, flags: FileFlags) {
+        if flags.is_read() {
+            self.readers -= 1;
+        }
+        if flags.is_write() {
+            self.writers -= 1;
+        }
+    }
+
+    /// Append as much of `buffer` as fits into the pipe.
+    ///
+    /// Fails with `WouldBlock` if the pipe is full and with `BrokenPipe` if
+    /// nobody is left to read the data.
+    pub fn write(&mut self, buffer: &[u8]) -> Result<usize, KError> {
+        if self.readers == 0 {
+            return Err(KError::BrokenPipe);
+        }
+        if buffer.is_empty() {
+            return Ok(0);
+        }
+        if self.len == PIPE_CAPACITY {
+            return Err(KError::WouldBlock);
+        }
+
+        let len = core::cmp::min(buffer.len(), PIPE_CAPACITY - self.len);
+        for (i, byte) in buffer[..len].iter().enumerate() {
+            self.buffer[(self.head + self.len + i) % PIPE_CAPACITY] = *byte;
+        }
+        self.len += len;
+        Ok(len)
+    }
+
+    /// Take up to `len` bytes out of the pipe.
+    ///
+    /// Fails with `WouldBlock` if the pipe is empty but can still be written
+    /// to; an empty result means all write ends are closed (end-of-file).
+    pub fn read(&mut self, len: usize) -> Result<Vec<u8>, KError> {
+        if self.is_empty() && self.writers > 0 && len > 0 {
+            return Err(KError::WouldBlock);
+        }
+
+        let len = core::cmp::min(len, self.len);
+        let mut data = Vec::try_with_capacity(len)?;
+        for i in 0..len {
+            data.push(self.buffer[(self.head + i) % PIPE_CAPACITY]);
+        }
+        self.head = (self.head + len) % PIPE_CAPACITY;
+        self.len -= len;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    /// Data is read in the order it was written, also across the end of the buffer.
+    fn test_pipe_read_write() {
+        let mut pipe = Pipe::new().unwrap();
+        pipe.open(FileFlags::O_RDONLY);
+        pipe.open(FileFlags::O_WRONLY);
+        assert_eq!(pipe.read(10), Err(KError::WouldBlock));
+
+        let data = [0xb; PIPE_CAPACITY - 10];
+        assert_eq!(pipe.write(&data), Ok(PIPE_CAPACITY - 10));
+        assert_eq!(
+            pipe.read(PIPE_CAPACITY - 20).unwrap().len(),
+            PIPE_CAPACITY - 20
+        );
+        assert_eq!(pipe.write(&[1, 2, 3, 4, 5]), Ok(5));
+        assert_eq!(pipe.len(), 15);
+        assert_eq!(pipe.read(10), Ok(alloc::vec![0xb; 10]));
+        assert_eq!(pipe.read(10), Ok(alloc::vec![1, 2, 3, 4, 5]));
+        assert!(pipe.is_empty());
+    }
+
+    #[test]
+    /// Writes to a full pipe are cut short, readers see end-of-file once all
+    /// writers are gone.
+    fn test_pipe_full_and_closed() {
+        let mut pipe = Pipe::new().unwrap();
+        pipe.open(FileFlags::O_RDONLY);
+        pipe.open(FileFlags::O_WRONLY);
+        assert!(!pipe.is_ready(FileFlags::O_RDONLY));
+        assert!(pipe.is_ready(FileFlags::O_WRONLY));
+
+        let data = [0xb; PIPE_CAPACITY + 10];
+        assert_eq!(pipe.write(&data), Ok(PIPE_CAPACITY));
+        assert_eq!(pipe.write(&data), Err(KError::WouldBlock));
+        assert!(pipe.is_ready(FileFlags::O_RDONLY));
+        assert!(!pipe.is_ready(FileFlags::O_WRONLY));
+
+        pipe.close(FileFlags::O_WRONLY);
+        assert_eq!(pipe.read(PIPE_CAPACITY).unwrap().len(), PIPE_CAPACITY);
+        assert_eq!(pipe.read(10), Ok(Vec::new()));
+
+        pipe.open(FileFlags::O_WRONLY);
+        pipe.close(FileFlags::O_RDONLY);
+        assert_eq!(pipe.write(&data), Err(KError::BrokenPipe));
+    }
+}
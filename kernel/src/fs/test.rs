@@ -6,6 +6,7 @@
 use alloc::vec::Vec;
 use core::cell::RefCell;
 use core::cmp::{Eq, PartialEq};
+use core::mem::size_of;
 use core::sync::atomic::Ordering;
 
 use crate::alloc::borrow::ToOwned;
@@ -14,7 +15,9 @@ use kpi::io::*;
 use log::trace;
 use proptest::prelude::*;
 
+use super::pipe::PIPE_CAPACITY;
 use super::*;
+use crate::memory::BASE_PAGE_SIZE;
 use crate::*;
 
 /// What operations that the model needs to keep track of.
@@ -24,8 +27,8 @@ use crate::*;
 enum ModelOperation {
     /// Stores a write to an mnode, at given offset, pattern, length.
     Write(Mnode, usize, char, usize),
-    /// Stores info about created files.
-    Created(String, Modes, Mnode),
+    /// Stores info about created files and directories.
+    Created(String, Modes, Mnode, FileType),
 }
 
 /// The FS model that we strive to implement.
@@ -39,9 +42,12 @@ struct ModelFS {
 impl Default for ModelFS {
     fn default() -> Self {
         let oplog = RefCell::new(Vec::with_capacity(64));
-        oplog
-            .borrow_mut()
-            .push(ModelOperation::Created("/".to_string(), 0, 1));
+        oplog.borrow_mut().push(ModelOperation::Created(
+            "/".to_string(),
+            0,
+            1,
+            FileType::Directory,
+        ));
         ModelFS {
             oplog,
             mnode_counter: RefCell::new(1),
@@ -50,11 +56,29 @@ impl Default for ModelFS {
 }
 
 impl ModelFS {
+    /// Turn a path into the form `/a/b/c` that is stored in the oplog.
+    fn normalize(pathname: &str) -> String {
+        let components: Vec<&str> = pathname.split('/').filter(|c| !c.is_empty()).collect();
+        format!("/{}", components.join("/"))
+    }
+
+    /// Split a normalized path into its parent and the name of the last component.
+    fn split(path: &String) -> Option<(String, String)> {
+        if path == "/" {
+            return None;
+        }
+        let idx = path.rfind('/').unwrap();
+        Some((
+            ModelFS::normalize(&path[..idx]),
+            path[idx + 1..].to_string(),
+        ))
+    }
+
     /// Find mnode of a path.
     fn path_to_mnode(&self, path: &String) -> Option<Mnode> {
         for x in self.oplog.borrow().iter().rev() {
             match x {
-                ModelOperation::Created(name, _mode, mnode) => {
+                ModelOperation::Created(name, _mode, mnode, _ftype) => {
                     if &name == &path {
                         return Some(*mnode);
                     }
@@ -70,7 +94,7 @@ impl ModelFS {
     fn path_to_idx(&self, path: &String) -> Option<usize> {
         for (idx, x) in self.oplog.borrow().iter().enumerate().rev() {
             match x {
-                ModelOperation::Created(name, _mode, _mnode) => {
+                ModelOperation::Created(name, _mode, _mnode, _ftype) => {
                     if &name == &path {
                         return Some(idx);
                     }
@@ -91,7 +115,7 @@ impl ModelFS {
     fn mnode_exists(&self, look_for: Mnode) -> bool {
         for x in self.oplog.borrow().iter().rev() {
             match x {
-                ModelOperation::Created(_name, _mode, mnode) => {
+                ModelOperation::Created(_name, _mode, mnode, _ftype) => {
                     if look_for == *mnode {
                         return true;
                     }
@@ -103,6 +127,78 @@ impl ModelFS {
         false
     }
 
+    /// Check if a mnode is a directory.
+    fn is_directory(&self, look_for: Mnode) -> bool {
+        for x in self.oplog.borrow().iter().rev() {
+            match x {
+                ModelOperation::Created(_name, _mode, mnode, ftype) => {
+                    if look_for == *mnode {
+                        return *ftype == FileType::Directory;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    /// Check if a directory has any entries.
+    fn has_children(&self, path: &String) -> bool {
+        for x in self.oplog.borrow().iter() {
+            match x {
+                ModelOperation::Created(name, _mode, _mnode, _ftype) => {
+                    if let Some((parent, _name)) = ModelFS::split(name) {
+                        if &parent == path {
+                            return true;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        false
+    }
+
+    /// Walk a normalized path from the root, every component except the last
+    /// one has to be a directory.
+    fn resolve(&self, path: &String) -> Result<Mnode, KError> {
+        let mut current = String::new();
+        let mut mnode = 1;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            if !self.is_directory(mnode) {
+                return Err(KError::DirectoryError);
+            }
+            current = format!("{}/{}", current, name);
+            mnode = self.path_to_mnode(&current).ok_or(KError::InvalidFile)?;
+        }
+        Ok(mnode)
+    }
+
+    /// Creates a file or a directory in the oplog.
+    fn create_node(&self, pathname: &str, mode: Modes, ftype: FileType) -> Result<u64, KError> {
+        let path = ModelFS::normalize(pathname);
+        let (parent, _name) = ModelFS::split(&path).ok_or(KError::AlreadyPresent)?;
+        let parent_mnode = self.resolve(&parent)?;
+        if !self.is_directory(parent_mnode) {
+            return Err(KError::DirectoryError);
+        }
+
+        if self.file_exists(&path) {
+            Err(KError::AlreadyPresent)
+        } else {
+            *self.mnode_counter.borrow_mut() += 1;
+            self.oplog.borrow_mut().push(ModelOperation::Created(
+                path,
+                mode,
+                *self.mnode_counter.borrow(),
+                ftype,
+            ));
+            Ok(*self.mnode_counter.borrow())
+        }
+    }
+
     /// Checks if there is overlap between two ranges
     fn overlaps<T: PartialOrd>(a: &core::ops::Range<T>, b: &core::ops::Range<T>) -> bool {
         a.start < b.end && b.start < a.end
@@ -133,18 +229,7 @@ impl ModelFS {
 impl FileSystem for ModelFS {
     // Create just puts the file in the oplop and increases mnode counter.
     fn create(&self, pathname: &str, mode: Modes) -> Result<u64, KError> {
-        let path = String::from(pathname);
-        if self.file_exists(&path) {
-            Err(KError::AlreadyPresent)
-        } else {
-            *self.mnode_counter.borrow_mut() += 1;
-            self.oplog.borrow_mut().push(ModelOperation::Created(
-                path,
-                mode,
-                *self.mnode_counter.borrow(),
-            ));
-            Ok(*self.mnode_counter.borrow())
-        }
+        self.create_node(pathname, mode, FileType::File)
     }
 
     /// Write just logs the write to the oplog.
@@ -156,8 +241,11 @@ impl FileSystem for ModelFS {
                 trace!("seen {:?}", x);
                 match x {
                     // Check if the file is writable or not
-                    ModelOperation::Created(_path, mode, mnode) => {
-                        if mnode_num == *mnode && !FileModes::from(*mode).is_writable() {
+                    ModelOperation::Created(_path, mode, mnode, ftype) => {
+                        if mnode_num == *mnode
+                            && (*ftype == FileType::Directory
+                                || !FileModes::from(*mode).is_writable())
+                        {
                             return Err(KError::PermissionError);
                         }
                     }
@@ -236,8 +324,11 @@ impl FileSystem for ModelFS {
                         // else: The write is not relevant
                     }
 
-                    ModelOperation::Created(_path, mode, mnode) => {
-                        if mnode_num == *mnode && !FileModes::from(*mode).is_readable() {
+                    ModelOperation::Created(_path, mode, mnode, ftype) => {
+                        if mnode_num == *mnode
+                            && (*ftype == FileType::Directory
+                                || !FileModes::from(*mode).is_readable())
+                        {
                             return Err(KError::PermissionError);
                         }
                     }
@@ -273,12 +364,24 @@ impl FileSystem for ModelFS {
 
     /// Lookup just returns the mnode.
     fn lookup(&self, pathname: &str) -> Option<Arc<Mnode>> {
-        self.path_to_mnode(&String::from(pathname)).map(Arc::from)
+        self.resolve(&ModelFS::normalize(pathname))
+            .ok()
+            .map(Arc::from)
     }
 
     /// Delete finds and removes a path from the oplog again.
     fn delete(&self, pathname: &str) -> Result<(), KError> {
-        if let Some(idx) = self.path_to_idx(&String::from(pathname)) {
+        let path = ModelFS::normalize(pathname);
+        let (parent, _name) = ModelFS::split(&path).ok_or(KError::PermissionError)?;
+        let parent_mnode = self.resolve(&parent)?;
+        if !self.is_directory(parent_mnode) {
+            return Err(KError::DirectoryError);
+        }
+
+        if let Some(idx) = self.path_to_idx(&path) {
+            if self.has_children(&path) {
+                return Err(KError::DirectoryError);
+            }
             self.oplog.borrow_mut().remove(idx);
             // We leave corresponding ModelOperation::Write entries
             // in the log for now...
@@ -290,11 +393,26 @@ impl FileSystem for ModelFS {
 
     /// Returns a `dummy` file-info.
     fn file_info(&self, _mnode: Mnode) -> FileInfo {
-        FileInfo { ftype: 0, fsize: 0 }
+        Default::default()
     }
 
-    /// Return a `dummy` response as this function is only used for open with O_TRUNC flag.
-    fn truncate(&self, _pathname: &str) -> Result<(), KError> {
+    /// Return a `dummy` response for truncate operation.
+    fn truncate(&self, _mnode_num: Mnode, _len: usize) -> Result<(), KError> {
+        Ok(())
+    }
+
+    /// Return a `dummy` response as the model has no memory to map.
+    fn map_regions(
+        &self,
+        _mnode_num: Mnode,
+        _offset: usize,
+        _len: usize,
+    ) -> Result<Vec<(PAddr, usize)>, KError> {
+        Ok(Vec::new())
+    }
+
+    /// Return a `dummy` response as the model has no memory to map.
+    fn unmap_regions(&self, _mnode_num: Mnode, _offset: usize, _len: usize) -> Result<(), KError> {
         Ok(())
     }
 
@@ -303,9 +421,31 @@ impl FileSystem for ModelFS {
         Ok(())
     }
 
-    fn mkdir(&self, _pathname: &str, _mode: Modes) -> Result<(), KError> {
+    /// Mkdir puts the directory in the oplog, same as create.
+    fn mkdir(&self, pathname: &str, mode: Modes) -> Result<(), KError> {
+        self.create_node(pathname, mode, FileType::Directory)
+            .map(|_mnode| ())
+    }
+
+    /// Return a `dummy` response for readdir operation.
+    fn readdir(&self, _pathname: &str, _buffer: &mut UserSlice) -> Result<usize, KError> {
+        Ok(0)
+    }
+
+    /// Return a `dummy` response for link operation.
+    fn link(&self, _oldname: &str, _newname: &str) -> Result<(), KError> {
         Ok(())
     }
+
+    /// Return a `dummy` response for symlink operation.
+    fn symlink(&self, _target: &str, _linkname: &str) -> Result<Mnode, KError> {
+        Ok(0)
+    }
+
+    /// Return a `dummy` response for readlink operation.
+    fn readlink(&self, _pathname: &str, _buffer: &mut UserSlice) -> Result<usize, KError> {
+        Ok(0)
+    }
 }
 
 /// Two writes/reads at different offsets should return
@@ -372,6 +512,7 @@ enum TestAction {
     Read(Mnode, usize, usize),
     Write(Mnode, usize, char, usize),
     Create(Vec<String>, Modes),
+    MkDir(Vec<String>, Modes),
     Delete(Vec<String>),
     Lookup(Vec<String>),
 }
@@ -389,6 +530,7 @@ fn action() -> impl Strategy<Value = TestAction> {
         )
             .prop_map(|(a, b, c, d)| TestAction::Write(a, b, c, d)),
         (path(), mode_gen(0xfff)).prop_map(|(a, b)| TestAction::Create(a, b)),
+        (path(), mode_gen(0xfff)).prop_map(|(a, b)| TestAction::MkDir(a, b)),
         path().prop_map(TestAction::Delete),
         path().prop_map(TestAction::Lookup),
     ]
@@ -448,10 +590,10 @@ fn path_names() -> impl Strategy<Value = String> {
     ]
 }
 
-/// Creates a path of depth a given depth (4), represented as a
+/// Creates a path with a depth of up to 4 components, represented as a
 /// vector of Strings.
 fn path() -> impl Strategy<Value = Vec<String>> {
-    proptest::collection::vec(path_names(), 4)
+    proptest::collection::vec(path_names(), 1..=4)
 }
 
 proptest! {
@@ -491,6 +633,13 @@ proptest! {
                     let rtotest = totest.create(path_str.as_str(), mode);
                     assert_eq!(rmodel, rtotest);
                 }
+                MkDir(path, mode) => {
+                    let path_str = path.join("/");
+
+                    let rmodel = model.mkdir(path_str.as_str(), mode);
+                    let rtotest = totest.mkdir(path_str.as_str(), mode);
+                    assert_eq!(rmodel, rtotest);
+                }
                 Delete(path) => {
                     let path_str = path.join("/");
 
@@ -530,7 +679,7 @@ fn test_memfs_init() {
     let root = String::from("/");
     assert_eq!(memfs.root, (root.to_owned(), 1));
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 2);
-    assert_eq!(memfs.files.read().get(&root), Some(&Arc::new(1)));
+    assert_eq!(memfs.lookup(&root), Some(Arc::new(1)));
     assert_eq!(
         *memfs.mnodes.read().get(&1).unwrap().read(),
         MemNode::new(1, "/", FileModes::S_IRWXU.into(), FileType::Directory).unwrap()
@@ -545,10 +694,7 @@ fn test_file_create() {
     let mnode = memfs.create(filename, FileModes::S_IRUSR.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
 }
 
 /// Create a file with non-read permission and try to read it.
@@ -561,10 +707,7 @@ fn test_file_read_permission_error() {
     let mnode = memfs.create(filename, FileModes::S_IWUSR.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     // On error read returns 0.
     assert_eq!(
         memfs
@@ -583,10 +726,7 @@ fn test_file_write_permission_error() {
     let mnode = memfs.create(filename, FileModes::S_IRUSR.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     // On error read returns 0.
     assert_eq!(
         memfs.write(2, &mut UserSlice::new(buffer.as_ptr() as u64, 10), 0),
@@ -603,10 +743,7 @@ fn test_file_write() {
     let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     assert_eq!(
         memfs
             .write(2, &mut UserSlice::new(buffer.as_ptr() as u64, 10), 0)
@@ -627,10 +764,7 @@ fn test_file_read() {
     let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     assert_eq!(
         memfs
             .write(2, &mut UserSlice::new(wbuffer.as_ptr() as u64, len), 0)
@@ -655,10 +789,7 @@ fn test_file_lookup() {
     let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     let mnode = memfs.lookup(filename);
     assert_eq!(mnode, Some(Arc::new(2)));
 }
@@ -671,10 +802,7 @@ fn test_file_fake_lookup() {
     let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     let mnode = memfs.lookup("filename");
     assert_eq!(mnode, None);
 }
@@ -687,10 +815,7 @@ fn test_file_duplicate_create() {
     let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
-    assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
-    );
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
     assert_eq!(
         memfs.create(filename, FileModes::S_IRWXU.into()),
         Err(KError::AlreadyPresent)
@@ -705,11 +830,29 @@ fn test_file_info() {
     let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
     assert_eq!(mnode, 2);
     assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
+    assert_eq!(memfs.lookup("file.txt"), Some(Arc::new(2)));
+    assert_eq!(
+        memfs.file_info(2),
+        FileInfo {
+            ftype: 2,
+            fsize: 0,
+            fmode: FileModes::S_IRWXU.into(),
+            mnode: 2,
+            nlink: 1,
+            ctime: 0,
+            mtime: 0,
+            atime: 0,
+        }
+    );
+
+    assert_eq!(memfs.update_time(2, TimeUpdate::Created, 10), Ok(()));
+    assert_eq!(memfs.update_time(2, TimeUpdate::Modified, 20), Ok(()));
+    let info = memfs.file_info(2);
+    assert_eq!((info.ctime, info.mtime, info.atime), (10, 20, 10));
     assert_eq!(
-        memfs.files.read().get(&String::from("file.txt")),
-        Some(&Arc::new(2))
+        memfs.update_time(3, TimeUpdate::Modified, 20),
+        Err(KError::InvalidFile)
     );
-    assert_eq!(memfs.file_info(2), FileInfo { ftype: 2, fsize: 0 });
 }
 
 /// Test file deletion.
@@ -734,6 +877,114 @@ fn test_file_delete() {
     );
 }
 
+/// Test truncating a file to a given length.
+#[test]
+fn test_file_truncate() {
+    let memfs: MlnrFS = Default::default();
+    let filename = "file.txt";
+    let mnode = memfs.create(filename, FileModes::S_IRWXU.into()).unwrap();
+    let buffer: &mut [u8; 10] = &mut [0xb; 10];
+    assert_eq!(memfs.write(mnode, buffer, 0), Ok(10));
+
+    assert_eq!(memfs.truncate(mnode, 4), Ok(()));
+    assert_eq!(memfs.file_info(mnode).fsize, 4);
+    assert_eq!(memfs.truncate(mnode, 8), Ok(()));
+    assert_eq!(memfs.file_info(mnode).fsize, 8);
+
+    let rbuffer: &mut [u8; 10] = &mut [0xff; 10];
+    assert_eq!(
+        memfs.read(mnode, &mut UserSlice::new(rbuffer.as_ptr() as u64, 10), 0),
+        Ok(8)
+    );
+    assert_eq!(rbuffer[..8], [0xb, 0xb, 0xb, 0xb, 0, 0, 0, 0]);
+
+    assert_eq!(memfs.truncate(1, 0), Err(KError::PermissionError));
+    assert_eq!(memfs.truncate(mnode + 1, 0), Err(KError::InvalidFile));
+}
+
+#[test]
+fn test_normalize_path() {
+    assert_eq!(normalize_path("/", "file.txt").unwrap(), "/file.txt");
+    assert_eq!(normalize_path("/dir", "file.txt").unwrap(), "/dir/file.txt");
+    assert_eq!(normalize_path("/dir", "/file.txt").unwrap(), "/file.txt");
+    assert_eq!(normalize_path("/dir", "./a//b/").unwrap(), "/dir/a/b");
+    assert_eq!(
+        normalize_path("/dir/sub", "../file.txt").unwrap(),
+        "/dir/file.txt"
+    );
+    assert_eq!(normalize_path("/dir", "../../..").unwrap(), "/");
+    assert_eq!(normalize_path("/", "//a/./b/../c").unwrap(), "/a/c");
+    assert_eq!(normalize_path("/dir", ".").unwrap(), "/dir");
+}
+
+#[test]
+fn test_unpack() {
+    let memfs: MlnrFS = Default::default();
+    let dir = memfs
+        .unpack(
+            "/etc",
+            FileModes::S_IRWXU.into(),
+            FileType::Directory,
+            &[],
+            2,
+        )
+        .unwrap();
+    assert_eq!(dir, 2);
+    assert_eq!(
+        memfs.unpack(
+            "/etc",
+            FileModes::S_IRWXU.into(),
+            FileType::Directory,
+            &[],
+            3
+        ),
+        Ok(dir)
+    );
+
+    // Read-only files still get their content.
+    let mnode = memfs
+        .unpack(
+            "/etc/config",
+            FileModes::S_IRUSR.into(),
+            FileType::File,
+            b"abc",
+            4,
+        )
+        .unwrap();
+    assert_eq!(memfs.file_info(mnode).fsize, 3);
+    assert_eq!(memfs.write(mnode, b"x", 0), Err(KError::PermissionError));
+    assert_eq!(
+        memfs.unpack(
+            "/etc/config",
+            FileModes::S_IRUSR.into(),
+            FileType::File,
+            b"abc",
+            5
+        ),
+        Err(KError::AlreadyPresent)
+    );
+    assert_eq!(
+        memfs.unpack(
+            "/etc/config",
+            FileModes::S_IRWXU.into(),
+            FileType::Directory,
+            &[],
+            6
+        ),
+        Err(KError::AlreadyPresent)
+    );
+    assert_eq!(
+        memfs.unpack(
+            "/bin/init",
+            FileModes::S_IRWXU.into(),
+            FileType::File,
+            &[],
+            7
+        ),
+        Err(KError::InvalidFile)
+    );
+}
+
 #[test]
 fn test_file_rename() {
     let memfs: MlnrFS = Default::default();
@@ -813,3 +1064,471 @@ fn test_file_rename_to_existent_file() {
     // New file points to old mnode.
     assert_eq!(*memfs.lookup(newname).unwrap(), oldmnode);
 }
+
+#[test]
+/// Create files and directories in nested directories and look them up.
+fn test_nested_create_lookup() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/dir", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.mkdir("/dir/sub", FileModes::S_IRWXU.into()), Ok(()));
+    let mnode = memfs
+        .create("/dir/sub/file.txt", FileModes::S_IRWXU.into())
+        .unwrap();
+    assert_eq!(mnode, 4);
+
+    assert_eq!(memfs.lookup("/dir/sub/file.txt"), Some(Arc::new(4)));
+    assert_eq!(memfs.lookup("dir//sub/file.txt"), Some(Arc::new(4)));
+    assert_eq!(memfs.lookup("/dir/sub/"), Some(Arc::new(3)));
+    assert_eq!(memfs.lookup("/file.txt"), None);
+    assert_eq!(memfs.lookup("/dir/file.txt"), None);
+    assert_eq!(memfs.file_info(2).ftype, FileType::Directory.into());
+}
+
+#[test]
+/// Creating a file in a parent directory that doesn't exist, or isn't a
+/// directory, fails.
+fn test_create_invalid_parent() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(
+        memfs.create("/dir/file.txt", FileModes::S_IRWXU.into()),
+        Err(KError::InvalidFile)
+    );
+    assert_eq!(memfs.create("/file.txt", FileModes::S_IRWXU.into()), Ok(2));
+    assert_eq!(
+        memfs.create("/file.txt/file.txt", FileModes::S_IRWXU.into()),
+        Err(KError::DirectoryError)
+    );
+    assert_eq!(
+        memfs.mkdir("/file.txt", FileModes::S_IRWXU.into()),
+        Err(KError::AlreadyPresent)
+    );
+    assert_eq!(
+        memfs.create("/", FileModes::S_IRWXU.into()),
+        Err(KError::AlreadyPresent)
+    );
+    assert_eq!(memfs.nextmemnode.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+/// Only empty directories can be deleted, the root can never be deleted.
+fn test_delete_directory() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/dir", FileModes::S_IRWXU.into()), Ok(()));
+    assert!(memfs
+        .create("/dir/file.txt", FileModes::S_IRWXU.into())
+        .is_ok());
+
+    assert_eq!(memfs.delete("/dir"), Err(KError::DirectoryError));
+    assert_eq!(memfs.delete("/dir/file.txt"), Ok(()));
+    assert_eq!(memfs.delete("/dir"), Ok(()));
+    assert_eq!(memfs.lookup("/dir"), None);
+    assert_eq!(memfs.delete("/"), Err(KError::PermissionError));
+}
+
+#[test]
+/// Rename a file into a different directory.
+fn test_file_rename_across_directories() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/a", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.mkdir("/b", FileModes::S_IRWXU.into()), Ok(()));
+    let mnode = memfs
+        .create("/a/file.txt", FileModes::S_IRWXU.into())
+        .unwrap();
+
+    assert_eq!(memfs.rename("/a/file.txt", "/b/new.txt"), Ok(()));
+    assert_eq!(memfs.lookup("/a/file.txt"), None);
+    assert_eq!(memfs.lookup("/b/new.txt"), Some(Arc::new(mnode)));
+    assert_eq!(
+        memfs.rename("/b/new.txt", "/c/new.txt"),
+        Err(KError::InvalidFile)
+    );
+    assert_eq!(memfs.lookup("/b/new.txt"), Some(Arc::new(mnode)));
+}
+
+#[test]
+/// Rename replaces an existing target and moves directories with their content.
+fn test_rename_replace() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/a", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.mkdir("/b", FileModes::S_IRWXU.into()), Ok(()));
+    let tmp = memfs
+        .create("/a/file.tmp", FileModes::S_IRWXU.into())
+        .unwrap();
+    let old = memfs.create("/b/file", FileModes::S_IRWXU.into()).unwrap();
+
+    // Commit a new version of a file.
+    assert_eq!(memfs.rename("/a/file.tmp", "/b/file"), Ok(()));
+    assert_eq!(memfs.lookup("/a/file.tmp"), None);
+    assert_eq!(memfs.lookup("/b/file"), Some(Arc::new(tmp)));
+    assert!(memfs.mnodes.read().get(&old).is_none());
+
+    // Move a directory with its content.
+    assert_eq!(memfs.rename("/b", "/a/c"), Ok(()));
+    assert_eq!(memfs.lookup("/b/file"), None);
+    assert_eq!(memfs.lookup("/a/c/file"), Some(Arc::new(tmp)));
+
+    // An empty directory can be replaced by a directory.
+    assert_eq!(memfs.mkdir("/d", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.rename("/a/c", "/d"), Ok(()));
+    assert_eq!(memfs.lookup("/d/file"), Some(Arc::new(tmp)));
+    assert_eq!(memfs.lookup("/a/c"), None);
+}
+
+#[test]
+/// Invalid renames fail without changing the file-system.
+fn test_rename_invalid() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/a", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.mkdir("/a/b", FileModes::S_IRWXU.into()), Ok(()));
+    let file = memfs
+        .create("/a/b/file", FileModes::S_IRWXU.into())
+        .unwrap();
+    assert_eq!(memfs.mkdir("/c", FileModes::S_IRWXU.into()), Ok(()));
+
+    // A directory can't be moved into itself.
+    assert_eq!(memfs.rename("/a", "/a/b/a"), Err(KError::PermissionError));
+    assert_eq!(memfs.rename("/a", "/a/b"), Err(KError::PermissionError));
+    assert!(memfs.symlink("/a/b", "/l").is_ok());
+    assert_eq!(memfs.rename("/a", "/l/a"), Err(KError::PermissionError));
+    // Files and directories can't replace each other.
+    assert_eq!(memfs.rename("/a/b/file", "/c"), Err(KError::DirectoryError));
+    assert_eq!(memfs.rename("/c", "/a/b/file"), Err(KError::DirectoryError));
+    // Non-empty directories can't be replaced.
+    assert_eq!(memfs.rename("/c", "/a"), Err(KError::DirectoryError));
+    // The target directory has to exist.
+    assert_eq!(memfs.rename("/c", "/e/c"), Err(KError::InvalidFile));
+    assert_eq!(
+        memfs.rename("/c", "/a/b/file/c"),
+        Err(KError::DirectoryError)
+    );
+    assert_eq!(memfs.rename("/", "/e"), Err(KError::PermissionError));
+
+    assert_eq!(memfs.lookup("/a/b/file"), Some(Arc::new(file)));
+    assert!(memfs.lookup("/c").is_some());
+    assert_eq!(memfs.lookup("/e"), None);
+
+    // A directory which only shares a prefix of the name isn't inside.
+    assert_eq!(memfs.mkdir("/ab", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.rename("/a", "/ab/a"), Ok(()));
+    assert_eq!(memfs.lookup("/ab/a/b/file"), Some(Arc::new(file)));
+}
+
+#[test]
+/// List the entries of a directory.
+fn test_readdir() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/dir", FileModes::S_IRWXU.into()), Ok(()));
+    assert!(memfs
+        .create("/dir/b.txt", FileModes::S_IRWXU.into())
+        .is_ok());
+    assert_eq!(memfs.mkdir("/dir/a", FileModes::S_IRWXU.into()), Ok(()));
+
+    let mut buffer = [0u8; 128];
+    let len = memfs
+        .readdir("/dir", &mut UserSlice::from_slice(&mut buffer))
+        .unwrap();
+    let entries: Vec<DirEntry> = serde_cbor::from_slice(&buffer[..len]).unwrap();
+    assert_eq!(
+        entries,
+        vec![
+            DirEntry {
+                name: "a",
+                mnode: 4,
+                ftype: FileType::Directory.into()
+            },
+            DirEntry {
+                name: "b.txt",
+                mnode: 3,
+                ftype: FileType::File.into()
+            }
+        ]
+    );
+
+    // A buffer that is too small is left untouched, but the required length is returned.
+    let mut small = [0u8; 4];
+    assert_eq!(
+        memfs.readdir("/dir", &mut UserSlice::from_slice(&mut small)),
+        Ok(len)
+    );
+    assert_eq!(small, [0u8; 4]);
+    assert_eq!(
+        memfs.readdir("/dir/b.txt", &mut UserSlice::from_slice(&mut buffer)),
+        Err(KError::DirectoryError)
+    );
+}
+
+#[test]
+fn test_hard_link() {
+    let memfs: MlnrFS = Default::default();
+    let mnode = memfs.create("/a.txt", FileModes::S_IRWXU.into()).unwrap();
+    assert_eq!(memfs.mkdir("/dir", FileModes::S_IRWXU.into()), Ok(()));
+    assert_eq!(memfs.link("/a.txt", "/dir/b.txt"), Ok(()));
+    assert_eq!(memfs.lookup("/dir/b.txt"), Some(Arc::new(mnode)));
+    assert_eq!(memfs.file_info(mnode).nlink, 2);
+
+    // Both names refer to the same content.
+    assert_eq!(memfs.write(mnode, &[0xb; 10], 0), Ok(10));
+    assert_eq!(
+        memfs.file_info(*memfs.lookup("/dir/b.txt").unwrap()).fsize,
+        10
+    );
+
+    assert_eq!(
+        memfs.link("/a.txt", "/dir/b.txt"),
+        Err(KError::AlreadyPresent)
+    );
+    assert_eq!(memfs.link("/dir", "/dir2"), Err(KError::PermissionError));
+    assert_eq!(memfs.link("/c.txt", "/d.txt"), Err(KError::InvalidFile));
+
+    // Renaming a file onto another link of itself does nothing.
+    assert_eq!(memfs.rename("/a.txt", "/dir/b.txt"), Ok(()));
+    assert_eq!(memfs.file_info(mnode).nlink, 2);
+
+    // The file is only removed with its last link.
+    assert_eq!(memfs.delete("/a.txt"), Ok(()));
+    assert_eq!(memfs.lookup("/a.txt"), None);
+    assert_eq!(memfs.file_info(mnode).nlink, 1);
+    assert_eq!(memfs.delete("/dir/b.txt"), Ok(()));
+    assert_eq!(memfs.lookup("/dir/b.txt"), None);
+    assert!(memfs.mnodes.read().get(&mnode).is_none());
+}
+
+#[test]
+fn test_delete_open_file() {
+    let memfs: MlnrFS = Default::default();
+    let mnode = memfs.create("/a.txt", FileModes::S_IRWXU.into()).unwrap();
+    assert_eq!(memfs.acquire(mnode, FileFlags::O_RDWR), Ok(()));
+    assert_eq!(memfs.acquire(mnode, FileFlags::O_RDONLY), Ok(()));
+
+    // The name is gone, but the open file can still be used.
+    assert_eq!(memfs.delete("/a.txt"), Ok(()));
+    assert_eq!(memfs.lookup("/a.txt"), None);
+    assert_eq!(memfs.write(mnode, &[0xb; 10], 0), Ok(10));
+    assert_eq!(memfs.file_info(mnode).fsize, 10);
+    assert_eq!(memfs.file_info(mnode).nlink, 0);
+
+    // A new file with the same name is a different file.
+    let new_mnode = memfs.create("/a.txt", FileModes::S_IRWXU.into()).unwrap();
+    assert_ne!(new_mnode, mnode);
+    assert_eq!(memfs.file_info(new_mnode).fsize, 0);
+
+    // The file is removed when it is released for the last time.
+    assert_eq!(memfs.release(mnode, FileFlags::O_RDWR), Ok(()));
+    assert!(memfs.mnodes.read().get(&mnode).is_some());
+    assert_eq!(memfs.release(mnode, FileFlags::O_RDONLY), Ok(()));
+    assert!(memfs.mnodes.read().get(&mnode).is_none());
+    assert_eq!(
+        memfs.release(mnode, FileFlags::O_RDONLY),
+        Err(KError::InvalidFile)
+    );
+
+    // Closing a file that still has a name keeps it.
+    assert_eq!(memfs.acquire(new_mnode, FileFlags::O_RDWR), Ok(()));
+    assert_eq!(memfs.release(new_mnode, FileFlags::O_RDWR), Ok(()));
+    assert_eq!(memfs.lookup("/a.txt"), Some(Arc::new(new_mnode)));
+}
+
+#[test]
+fn test_file_lock() {
+    let memfs: MlnrFS = Default::default();
+    let mnode = memfs.create("/db", FileModes::S_IRWXU.into()).unwrap();
+
+    // A length of 0 locks the whole file, however large it gets.
+    assert_eq!(memfs.lock(mnode, 1, LockKind::Exclusive, 0, 0), Ok(()));
+    assert_eq!(
+        memfs.lock(mnode, 2, LockKind::Shared, 1 << 40, 1),
+        Err(KError::WouldBlock)
+    );
+    assert_eq!(memfs.unlock(mnode, 1, 4096, 0), Ok(()));
+    assert_eq!(memfs.lock(mnode, 2, LockKind::Shared, 1 << 40, 1), Ok(()));
+    assert_eq!(
+        memfs.lock(mnode, 2, LockKind::Shared, 0, 4097),
+        Err(KError::WouldBlock)
+    );
+    assert_eq!(memfs.unlock_all(mnode, 1), Ok(()));
+    assert_eq!(memfs.lock(mnode, 2, LockKind::Exclusive, 0, 4097), Ok(()));
+
+    assert_eq!(
+        memfs.lock(mnode, 1, LockKind::Shared, u64::MAX, 2),
+        Err(KError::InvalidOffset)
+    );
+    assert_eq!(
+        memfs.lock(mnode + 1, 1, LockKind::Shared, 0, 0),
+        Err(KError::InvalidFile)
+    );
+}
+
+#[test]
+fn test_pipe() {
+    let memfs: MlnrFS = Default::default();
+    let pipe = memfs.get_next_mno() as u64;
+    assert_eq!(memfs.pipe(pipe), Ok(()));
+    assert!(memfs.is_pipe(pipe));
+    assert_eq!(memfs.usage(), (2, PIPE_CAPACITY));
+    assert_eq!(memfs.file_info(pipe).ftype, FileType::Pipe.into());
+    assert_eq!(memfs.file_info(pipe).nlink, 0);
+    assert_eq!(memfs.acquire(pipe, FileFlags::O_RDONLY), Ok(()));
+    assert_eq!(memfs.acquire(pipe, FileFlags::O_WRONLY), Ok(()));
+
+    // Pipes have no offset and can't be accessed like files.
+    assert_eq!(memfs.write(pipe, &[1, 2, 3], 100), Ok(3));
+    assert_eq!(memfs.file_info(pipe).fsize, 3);
+    assert_eq!(memfs.truncate(pipe, 0), Err(KError::PermissionError));
+    assert_eq!(memfs.pipe_read(pipe, 2), Ok(vec![1, 2]));
+    assert_eq!(memfs.pipe_read(pipe, 2), Ok(vec![3]));
+    assert_eq!(memfs.pipe_read(pipe, 2), Err(KError::WouldBlock));
+
+    // The pipe goes away with its last open end.
+    assert_eq!(memfs.release(pipe, FileFlags::O_WRONLY), Ok(()));
+    assert_eq!(memfs.pipe_read(pipe, 2), Ok(vec![]));
+    assert_eq!(memfs.release(pipe, FileFlags::O_RDONLY), Ok(()));
+    assert!(!memfs.is_pipe(pipe));
+    assert!(memfs.mnodes.read().get(&pipe).is_none());
+
+    let file = memfs.create("/file", FileModes::S_IRWXU.into()).unwrap();
+    assert!(!memfs.is_pipe(file));
+    assert_eq!(memfs.pipe_read(file, 2), Err(KError::InvalidFile));
+    assert_eq!(memfs.write(file, &[1, 2, 3], 0), Ok(3));
+    assert_eq!(memfs.usage(), (2, BASE_PAGE_SIZE));
+}
+
+#[test]
+fn test_watch() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/dir", FileModes::S_IRWXU.into()), Ok(()));
+    let dir = *memfs.lookup("/dir").unwrap();
+    let file = memfs
+        .create("/dir/file", FileModes::S_IRWXU.into())
+        .unwrap();
+
+    let queue = memfs.get_next_mno() as u64;
+    assert_eq!(memfs.watch_init(queue), Ok(()));
+    assert!(memfs.is_watch(queue));
+    assert_eq!(memfs.file_info(queue).ftype, FileType::Watch.into());
+    assert_eq!(memfs.acquire(queue, FileFlags::O_RDONLY), Ok(()));
+    let wd = memfs
+        .watch_add(queue, "/dir", WatchEvents::IN_CREATE)
+        .unwrap();
+    assert_eq!(
+        memfs.watch_add(file, "/dir", WatchEvents::IN_CREATE),
+        Err(KError::InvalidFile)
+    );
+    assert_eq!(
+        memfs.watch_add(queue, "/none", WatchEvents::IN_CREATE),
+        Err(KError::InvalidFile)
+    );
+    assert_eq!(memfs.lookup_parent("/dir/file"), Some(dir));
+
+    // Only the events the watch asked for end up in the queue.
+    assert!(memfs.is_watched(dir, WatchEvents::IN_CREATE));
+    assert!(!memfs.is_watched(dir, WatchEvents::IN_DELETE));
+    assert!(!memfs.is_watched(file, WatchEvents::IN_CREATE));
+    memfs.notify(dir, WatchEvents::IN_DELETE, file);
+    assert_eq!(memfs.read_events(queue, 4), Err(KError::WouldBlock));
+    assert_eq!(memfs.is_ready(queue, FileFlags::O_RDONLY), Ok(false));
+    memfs.notify(dir, WatchEvents::IN_CREATE, file);
+    memfs.notify(file, WatchEvents::IN_CREATE, file);
+    assert_eq!(memfs.is_ready(queue, FileFlags::O_RDONLY), Ok(true));
+    assert_eq!(memfs.file_info(queue).fsize, size_of::<WatchEvent>() as u64);
+    assert_eq!(
+        memfs.read_events(queue, 4),
+        Ok(vec![WatchEvent {
+            wd,
+            mask: WatchEvents::IN_CREATE.bits(),
+            mnode: file,
+        }])
+    );
+
+    // Closing the queue removes it with its watches.
+    assert_eq!(memfs.watch_remove(queue, wd + 1), Err(KError::InvalidFile));
+    assert_eq!(memfs.release(queue, FileFlags::O_RDONLY), Ok(()));
+    assert!(!memfs.is_watch(queue));
+    assert_eq!(
+        memfs
+            .watches
+            .read()
+            .matching(dir, WatchEvents::all())
+            .count(),
+        0
+    );
+}
+
+#[test]
+fn test_symlink() {
+    let memfs: MlnrFS = Default::default();
+    assert_eq!(memfs.mkdir("/dir", FileModes::S_IRWXU.into()), Ok(()));
+    let mnode = memfs
+        .create("/dir/file.txt", FileModes::S_IRWXU.into())
+        .unwrap();
+
+    // Relative targets are resolved from the directory of the link.
+    let link = memfs.symlink("dir/file.txt", "/link").unwrap();
+    let dirlink = memfs.symlink("/dir", "/dir/self").unwrap();
+    let uplink = memfs.symlink("../dir/./file.txt", "/dir/up").unwrap();
+    assert_eq!(memfs.lookup("/link"), Some(Arc::new(mnode)));
+    assert_eq!(memfs.lookup("/dir/up"), Some(Arc::new(mnode)));
+    assert_eq!(
+        memfs.lookup("/dir/self/self/file.txt"),
+        Some(Arc::new(mnode))
+    );
+    assert_eq!(memfs.lookup_nofollow("/link"), Some(link));
+    assert_eq!(memfs.lookup_nofollow("/dir/self/up"), Some(uplink));
+    assert_eq!(memfs.lookup_nofollow("/dir/self"), Some(dirlink));
+    assert_eq!(memfs.file_info(link).ftype, FileType::Symlink.into());
+    assert_eq!(memfs.file_info(link).fsize, 12);
+    assert!(memfs.crosses_symlink("/link", true));
+    assert!(!memfs.crosses_symlink("/link", false));
+    assert!(memfs.crosses_symlink("/dir/self/file.txt", false));
+    assert!(!memfs.crosses_symlink("/dir/file.txt", true));
+    assert!(!memfs.crosses_symlink("/missing/file.txt", true));
+
+    let mut buffer = [0u8; 32];
+    assert_eq!(
+        memfs.readlink("/link", &mut UserSlice::from_slice(&mut buffer)),
+        Ok(12)
+    );
+    assert_eq!(&buffer[..12], b"dir/file.txt");
+    assert_eq!(
+        memfs.readlink("/dir/file.txt", &mut UserSlice::from_slice(&mut buffer)),
+        Err(KError::InvalidFile)
+    );
+
+    // Files can be created through a link to a directory.
+    assert!(memfs
+        .create("/dir/self/new.txt", FileModes::S_IRWXU.into())
+        .is_ok());
+    assert!(memfs.lookup("/dir/new.txt").is_some());
+
+    // Deleting the link leaves the target alone, a dangling link resolves to nothing.
+    assert_eq!(memfs.delete("/link"), Ok(()));
+    assert_eq!(memfs.lookup("/dir/file.txt"), Some(Arc::new(mnode)));
+    assert_eq!(memfs.delete("/dir/file.txt"), Ok(()));
+    assert_eq!(memfs.lookup("/dir/up"), None);
+    assert_eq!(memfs.lookup_nofollow("/dir/up"), Some(uplink));
+    assert_eq!(memfs.symlink("", "/empty"), Err(KError::InvalidFile));
+}
+
+#[test]
+fn test_symlink_loop() {
+    let memfs: MlnrFS = Default::default();
+    assert!(memfs.symlink("/b", "/a").is_ok());
+    assert!(memfs.symlink("a", "/b").is_ok());
+    assert_eq!(memfs.lookup("/a"), None);
+    assert_eq!(
+        memfs.create("/a/file.txt", FileModes::S_IRWXU.into()),
+        Err(KError::SymlinkLoop)
+    );
+
+    // A chain that is just short enough still resolves.
+    let mnode = memfs.create("/l0", FileModes::S_IRWXU.into()).unwrap();
+    for i in 1..=MAX_SYMLINK_DEPTH {
+        let target = alloc::format!("l{}", i - 1);
+        let name = alloc::format!("/l{}", i);
+        assert!(memfs.symlink(&target, &name).is_ok());
+    }
+    let last = alloc::format!("/l{}", MAX_SYMLINK_DEPTH);
+    assert_eq!(memfs.lookup(&last), Some(Arc::new(mnode)));
+    let name = alloc::format!("/l{}", MAX_SYMLINK_DEPTH + 1);
+    assert!(memfs.symlink(&last[1..], &name).is_ok());
+    assert_eq!(memfs.lookup(&name), None);
+}
@@ -0,0 +1,222 @@
+// Copyright © 2021 VMware, Inc. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! File change notifications, similar to `inotify`.
+//!
+//! A process creates an event queue (an anonymous mnode behind a file
+//! descriptor) and adds watches for mnodes to it. Changes to a watched mnode
+//! append events to all queues that watch it.
+//!
+//! Events are queued in the order a replica applies the operations. An
+//! operation that reports an event to a watch is put in all logs, so every
+//! replica queues (and drops) the same events in the same order.
+
+use alloc::vec::Vec;
+
+use fallible_collections::{FallibleVec, FallibleVecGlobal};
+use kpi::io::{WatchEvent, WatchEvents};
+
+use super::Mnode;
+use crate::error::KError;
+
+/// Number of events a queue holds before new events are dropped.
+pub const MAX_QUEUED_EVENTS: usize = 256;
+
+/// Watch descriptor reported with the event that tells that events were dropped.
+pub const OVERFLOW_WD: u64 = u64::MAX;
+
+/// Interest of an event queue in changes to an mnode.
+#[derive(Debug, Eq, PartialEq)]
+struct Watch {
+    wd: u64,
+    queue: Mnode,
+    target: Mnode,
+    mask: WatchEvents,
+}
+
+/// All watches of the file-system.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct WatchTable {
+    watches: Vec<Watch>,
+    next_wd: u64,
+}
+
+impl WatchTable {
+    /// Let `queue` watch `target` for the events in `mask`. Returns the watch
+    /// descriptor; watching the same mnode again replaces the mask of the
+    /// existing watch.
+    pub fn add(&mut self, queue: Mnode, target: Mnode, mask: WatchEvents) -> Result<u64, KError> {
+        if mask.is_empty() {
+            return Err(KError::InvalidFlags);
+        }
+        if let Some(watch) = self
+            .watches
+            .iter_mut()
+            .find(|watch| watch.queue == queue && watch.target == target)
+        {
+            watch.mask = mask;
+            return Ok(watch.wd);
+        }
+
+        let wd = self.next_wd;
+        self.watches.try_push(Watch {
+            wd,
+            queue,
+            target,
+            mask,
+        })?;
+        self.next_wd += 1;
+        Ok(wd)
+    }
+
+    /// Remove the watch `wd` of `queue`.
+    pub fn remove(&mut self, queue: Mnode, wd: u64) -> Result<(), KError> {
+        let idx = self
+            .watches
+            .iter()
+            .position(|watch| watch.queue == queue && watch.wd == wd)
+            .ok_or(KError::InvalidFile)?;
+        self.watches.swap_remove(idx);
+        Ok(())
+    }
+
+    /// Remove all watches of `queue`, e.g., once it is closed.
+    pub fn remove_queue(&mut self, queue: Mnode) {
+        self.watches.retain(|watch| watch.queue != queue);
+    }
+
+    /// The queues (and their watch descriptors) interested in `event` on `target`.
+    pub fn matching(
+        &self,
+        target: Mnode,
+        event: WatchEvents,
+    ) -> impl Iterator<Item = (Mnode, u64)> + '_ {
+        self.watches
+            .iter()
+            .filter(move |watch| watch.target == target && watch.mask.intersects(event))
+            .map(|watch| (watch.queue, watch.wd))
+    }
+}
+
+/// The events that wait to be read from an event queue.
+#[derive(Debug, Eq, PartialEq)]
+pub struct EventQueue {
+    events: Vec<WatchEvent>,
+    /// Set if events were dropped because the queue was full.
+    overflow: bool,
+}
+
+impl EventQueue {
+    pub fn new() -> Result<EventQueue, KError> {
+        Ok(EventQueue {
+            events: Vec::try_with_capacity(MAX_QUEUED_EVENTS)?,
+            overflow: false,
+        })
+    }
+
+    /// Number of events that are waiting to be read.
+    pub fn len(&self) -> usize {
+        self.events.len() + self.overflow as usize
+    }
+
+    /// Returns true if there is nothing to read.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Append an event; it is dropped if the queue is full. This never
+    /// allocates, so it can't fail after the operation that caused the event.
+    pub fn push(&mut self, event: WatchEvent) {
+        if self.events.len() < MAX_QUEUED_EVENTS {
+            self.events.push(event);
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    /// Take up to `count` events out of the queue, the oldest first. A lost
+    /// event is reported after all queued ones.
+    ///
+    /// Fails with `WouldBlock` if there are no events.
+    pub fn read(&mut self, count: usize) -> Result<Vec<WatchEvent>, KError> {
+        if self.is_empty() {
+            return Err(KError::WouldBlock);
+        }
+
+        let count = core::cmp::min(count, self.len());
+        let mut events = Vec::try_with_capacity(count)?;
+        let taken = core::cmp::min(count, self.events.len());
+        events.extend(self.events.drain(..taken));
+        if events.len() < count {
+            events.push(WatchEvent {
+                wd: OVERFLOW_WD,
+                mask: WatchEvents::IN_Q_OVERFLOW.bits(),
+                mnode: 0,
+            });
+            self.overflow = false;
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    /// Only watches for the right mnode and event match.
+    fn test_watch_table() {
+        let mut watches = WatchTable::default();
+        let wd = watches.add(10, 1, WatchEvents::IN_CREATE).unwrap();
+        let other = watches.add(11, 1, WatchEvents::all()).unwrap();
+        assert_ne!(wd, other);
+        assert_eq!(
+            watches.add(10, 1, WatchEvents::IN_CREATE | WatchEvents::IN_DELETE),
+            Ok(wd)
+        );
+        assert_eq!(
+            watches.add(10, 2, WatchEvents::empty()),
+            Err(KError::InvalidFlags)
+        );
+
+        let queues: Vec<_> = watches.matching(1, WatchEvents::IN_DELETE).collect();
+        assert_eq!(queues, [(10, wd), (11, other)]);
+        let queues: Vec<_> = watches.matching(1, WatchEvents::IN_MODIFY).collect();
+        assert_eq!(queues, [(11, other)]);
+        assert_eq!(watches.matching(2, WatchEvents::all()).count(), 0);
+
+        assert_eq!(watches.remove(11, wd), Err(KError::InvalidFile));
+        assert_eq!(watches.remove(10, wd), Ok(()));
+        watches.remove_queue(11);
+        assert_eq!(watches.matching(1, WatchEvents::all()).count(), 0);
+    }
+
+    #[test]
+    /// Events are read in order; a full queue drops events and reports it.
+    fn test_event_queue() {
+        let mut queue = EventQueue::new().unwrap();
+        assert_eq!(queue.read(1), Err(KError::WouldBlock));
+
+        for mnode in 0..MAX_QUEUED_EVENTS as u64 + 2 {
+            queue.push(WatchEvent {
+                wd: 1,
+                mask: WatchEvents::IN_MODIFY.bits(),
+                mnode,
+            });
+        }
+        assert_eq!(queue.len(), MAX_QUEUED_EVENTS + 1);
+
+        let events = queue.read(2).unwrap();
+        assert_eq!(events[0].mnode, 0);
+        assert_eq!(events[1].mnode, 1);
+
+        let events = queue.read(MAX_QUEUED_EVENTS).unwrap();
+        assert_eq!(events.len(), MAX_QUEUED_EVENTS - 1);
+        assert_eq!(
+            events[MAX_QUEUED_EVENTS - 3].mnode,
+            MAX_QUEUED_EVENTS as u64 - 1
+        );
+        assert_eq!(events[MAX_QUEUED_EVENTS - 2].wd, OVERFLOW_WD);
+        assert!(queue.is_empty());
+    }
+}
@@ -24,7 +24,10 @@ use crate::arch::process::PROCESS_TABLE;
 use crate::memory::emem::EmergencyAllocator;
 use crate::memory::mcache::TCache;
 use crate::memory::mcache::TCacheSp;
-use crate::memory::{AllocatorStatistics, GlobalMemory, GrowBackend, PAddr, PhysicalPageProvider};
+use crate::memory::{
+    AllocatorStatistics, GlobalMemory, GrowBackend, PAddr, PhysicalPageProvider, BASE_PAGE_SIZE,
+    LARGE_PAGE_SIZE,
+};
 use crate::nr::KernelNode;
 use crate::nrproc::NrProcess;
 use crate::process::{Pid, Process, MAX_PROCESSES};
@@ -56,6 +59,22 @@ enum CmdToken {
     #[token("appcmd")]
     AppArgs,
 
+    /// Name of the cpio archive that is unpacked into the file-system.
+    #[token("initrd")]
+    Initrd,
+
+    /// Number of logs of the replicated file-system.
+    #[token("fslogs")]
+    FsLogs,
+
+    /// Size of each file-system log.
+    #[token("fslogsize")]
+    FsLogSize,
+
+    /// Size of each process log.
+    #[token("proclogsize")]
+    ProcessLogSize,
+
     #[regex("[a-zA-Z0-9\\._-]*")]
     Ident,
 
@@ -80,8 +99,29 @@ pub struct BootloaderArguments {
     pub init_binary: &'static str,
     pub init_args: &'static str,
     pub app_args: &'static str,
+    pub initrd: &'static str,
+    /// Number of file-system logs, by default one per hardware thread of a
+    /// NUMA node.
+    pub fs_logs: Option<usize>,
+    /// Size (in bytes) of each file-system log.
+    pub fs_log_size: usize,
+    /// Size (in bytes) of each process log.
+    pub process_log_size: usize,
 }
 
+/// Default size (in bytes) of the file-system and process logs.
+pub const DEFAULT_LOG_SIZE: usize = LARGE_PAGE_SIZE;
+
+/// Smallest size (in bytes) of the file-system and process logs.
+pub const MIN_LOG_SIZE: usize = 8 * BASE_PAGE_SIZE;
+
+/// Largest size (in bytes) of a file-system log.
+pub const MAX_FS_LOG_SIZE: usize = 512 * LARGE_PAGE_SIZE;
+
+/// Largest size (in bytes) of a process log; there is one log for each of
+/// the `MAX_PROCESSES` processes, allocated at boot.
+pub const MAX_PROCESS_LOG_SIZE: usize = 16 * LARGE_PAGE_SIZE;
+
 impl Default for BootloaderArguments {
     fn default() -> BootloaderArguments {
         BootloaderArguments {
@@ -89,6 +129,10 @@ impl Default for BootloaderArguments {
             init_binary: "init",
             init_args: "",
             app_args: "",
+            initrd: "",
+            fs_logs: None,
+            fs_log_size: DEFAULT_LOG_SIZE,
+            process_log_size: DEFAULT_LOG_SIZE,
         }
     }
 }
@@ -99,18 +143,26 @@ impl BootloaderArguments {
         init_binary: &'static str,
         init_args: &'static str,
         app_args: &'static str,
+        initrd: &'static str,
     ) -> Self {
         BootloaderArguments {
             log_filter,
             init_binary,
             init_args,
             app_args,
+            initrd,
+            fs_logs: None,
+            fs_log_size: DEFAULT_LOG_SIZE,
+            process_log_size: DEFAULT_LOG_SIZE,
         }
     }
 
     /// Parse command line argument and initialize the logging infrastructure.
     ///
     /// Example: If args is './kernel log=trace' -> sets level to Level::Trace
+    ///
+    /// Log sizes are in bytes and can have a `K`, `M` or `G` suffix, e.g.,
+    /// './kernel fslogs=4 fslogsize=32M'.
     pub fn from_str(args: &'static str) -> BootloaderArguments {
         // The args argument will be a physical address slice that
         // goes away once we switch to a process address space
@@ -131,7 +183,14 @@ impl BootloaderArguments {
                 CmdToken::KernelBinary => {
                     //assert_eq!(slice, "./kernel");
                 }
-                CmdToken::Log | CmdToken::InitBinary | CmdToken::InitArgs | CmdToken::AppArgs => {
+                CmdToken::Log
+                | CmdToken::InitBinary
+                | CmdToken::InitArgs
+                | CmdToken::AppArgs
+                | CmdToken::Initrd
+                | CmdToken::FsLogs
+                | CmdToken::FsLogSize
+                | CmdToken::ProcessLogSize => {
                     prev = token;
                 }
                 CmdToken::Ident => match prev {
@@ -151,6 +210,25 @@ impl BootloaderArguments {
                         parsed_args.app_args = slice;
                         prev = CmdToken::Error;
                     }
+                    CmdToken::Initrd => {
+                        parsed_args.initrd = slice;
+                        prev = CmdToken::Error;
+                    }
+                    CmdToken::FsLogs => {
+                        parsed_args.fs_logs = parse_number(args, slice).or(parsed_args.fs_logs);
+                        prev = CmdToken::Error;
+                    }
+                    CmdToken::FsLogSize => {
+                        parsed_args.fs_log_size = parse_log_size(args, slice, MAX_FS_LOG_SIZE)
+                            .unwrap_or(DEFAULT_LOG_SIZE);
+                        prev = CmdToken::Error;
+                    }
+                    CmdToken::ProcessLogSize => {
+                        parsed_args.process_log_size =
+                            parse_log_size(args, slice, MAX_PROCESS_LOG_SIZE)
+                                .unwrap_or(DEFAULT_LOG_SIZE);
+                        prev = CmdToken::Error;
+                    }
                     _ => {
                         error!("Invalid cmd arguments: {} (skipped {})", args, slice);
                         continue;
@@ -161,6 +239,10 @@ impl BootloaderArguments {
                         && prev != CmdToken::InitBinary
                         && prev != CmdToken::InitArgs
                         && prev != CmdToken::AppArgs
+                        && prev != CmdToken::Initrd
+                        && prev != CmdToken::FsLogs
+                        && prev != CmdToken::FsLogSize
+                        && prev != CmdToken::ProcessLogSize
                     {
                         error!("Malformed args (unexpected equal sign) in {}", args);
                         continue;
@@ -185,6 +267,10 @@ impl BootloaderArguments {
                             parsed_args.app_args = &slice[1..slice.len() - 1];
                             prev = CmdToken::Error;
                         }
+                        CmdToken::Initrd => {
+                            parsed_args.initrd = &slice[1..slice.len() - 1];
+                            prev = CmdToken::Error;
+                        }
                         _ => {
                             error!("Invalid cmd arguments: {} (skipped {})", args, slice);
                             continue;
@@ -202,6 +288,60 @@ impl BootloaderArguments {
     }
 }
 
+/// Parse a number without a suffix.
+fn parse_number(args: &str, value: &str) -> Option<usize> {
+    let number = value.parse::<usize>().ok();
+    if number.is_none() {
+        error!(
+            "Invalid number in cmd arguments: {} (skipped {})",
+            args, value
+        );
+    }
+    number
+}
+
+/// Parse a log size, it has to be at least `MIN_LOG_SIZE` and at most `max`.
+fn parse_log_size(args: &str, value: &str, max: usize) -> Option<usize> {
+    let size = parse_size(args, value)?;
+    if size < MIN_LOG_SIZE {
+        error!(
+            "Log size below {} bytes in cmd arguments: {} (skipped {})",
+            MIN_LOG_SIZE, args, value
+        );
+        return None;
+    }
+    if size > max {
+        error!(
+            "Log size above {} bytes in cmd arguments: {} (skipped {})",
+            max, args, value
+        );
+        return None;
+    }
+    Some(size)
+}
+
+/// Parse a number with an optional `K`, `M` or `G` suffix (powers of 1024).
+fn parse_size(args: &str, value: &str) -> Option<usize> {
+    let (digits, shift) = match value.as_bytes().last() {
+        Some(b'K') => (&value[..value.len() - 1], 10),
+        Some(b'M') => (&value[..value.len() - 1], 20),
+        Some(b'G') => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+
+    let size = digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift));
+    if size.is_none() {
+        error!(
+            "Invalid number in cmd arguments: {} (skipped {})",
+            args, value
+        );
+    }
+    size
+}
+
 /// State which allows to do memory management for a particular
 /// NUMA node on a given core.
 pub struct PhysicalMemoryArena {
@@ -520,6 +660,43 @@ mod test {
         assert_eq!(ba.init_args, "0");
     }
 
+    #[test]
+    fn parse_args_initrd() {
+        let ba = BootloaderArguments::from_str("./kernel init=init initrd=rootfs.cpio log=info");
+        assert_eq!(ba.log_filter, "info");
+        assert_eq!(ba.init_binary, "init");
+        assert_eq!(ba.initrd, "rootfs.cpio");
+
+        let ba = BootloaderArguments::from_str("./kernel");
+        assert_eq!(ba.initrd, "");
+    }
+
+    #[test]
+    fn parse_args_logs() {
+        let ba = BootloaderArguments::from_str("./kernel fslogs=4 fslogsize=32M proclogsize=65536");
+        assert_eq!(ba.fs_logs, Some(4));
+        assert_eq!(ba.fs_log_size, 32 * 1024 * 1024);
+        assert_eq!(ba.process_log_size, 64 * 1024);
+
+        let ba = BootloaderArguments::from_str("./kernel fslogs=many fslogsize=1G");
+        assert_eq!(ba.fs_logs, None);
+        assert_eq!(ba.fs_log_size, 1024 * 1024 * 1024);
+        assert_eq!(ba.process_log_size, super::DEFAULT_LOG_SIZE);
+
+        // Huge logs are ignored.
+        let ba = BootloaderArguments::from_str("./kernel fslogsize=2G proclogsize=64M");
+        assert_eq!(ba.fs_log_size, super::DEFAULT_LOG_SIZE);
+        assert_eq!(ba.process_log_size, super::DEFAULT_LOG_SIZE);
+        let ba = BootloaderArguments::from_str("./kernel proclogsize=32M");
+        assert_eq!(ba.process_log_size, 32 * 1024 * 1024);
+
+        // The number of logs has no unit and tiny logs are ignored.
+        let ba = BootloaderArguments::from_str("./kernel fslogs=4K fslogsize=1K proclogsize=4096");
+        assert_eq!(ba.fs_logs, None);
+        assert_eq!(ba.fs_log_size, super::DEFAULT_LOG_SIZE);
+        assert_eq!(ba.process_log_size, super::DEFAULT_LOG_SIZE);
+    }
+
     #[test]
     fn parse_args_leveldb() {
         let args = "./kernel log=warn init=dbbench.bin initargs=3 appcmd='--threads=1 --benchmarks=fillseq,readrandom --reads=100000 --num=50000 --value_size=65535'";
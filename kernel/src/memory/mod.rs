@@ -305,6 +305,42 @@ impl KernelAllocator {
         Ok(())
     }
 
+    /// Give base- and large-pages back once they are no longer in use.
+    ///
+    /// Frames go to our core-local tcache if they are local and it has space
+    /// left, otherwise to the ncache of the node they belong to.
+    pub fn release_frames(frames: &[Frame]) -> Result<(), KError> {
+        let kcb = kcb::try_get_kcb().ok_or(KError::KcbUnavailable)?;
+
+        for frame in frames.iter().copied() {
+            debug_assert!(frame.size() == BASE_PAGE_SIZE || frame.size() == LARGE_PAGE_SIZE);
+            if frame.affinity == kcb.physical_memory.affinity {
+                let mut mem_manager = kcb.try_mem_manager()?;
+                let released = if frame.size() == BASE_PAGE_SIZE {
+                    mem_manager.release_base_page(frame)
+                } else {
+                    mem_manager.release_large_page(frame)
+                };
+                if released.is_ok() {
+                    continue;
+                }
+            }
+
+            let gmanager = kcb
+                .physical_memory
+                .gmanager
+                .ok_or(KError::GlobalMemoryNotSet)?;
+            let mut ncache = gmanager.node_caches[frame.affinity as usize].lock();
+            if frame.size() == BASE_PAGE_SIZE {
+                ncache.release_base_page(frame)?;
+            } else {
+                ncache.release_large_page(frame)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Refill TCache only if the layout will exhaust the cache's current
     /// stored memory
     ///
@@ -732,6 +768,14 @@ impl GlobalMemory {
 
         Ok(gm)
     }
+
+    /// Free memory (in bytes) left in the node-caches of all NUMA nodes.
+    pub fn free(&self) -> usize {
+        self.node_caches
+            .iter()
+            .map(|ncache| ncache.lock().free())
+            .sum()
+    }
 }
 
 impl fmt::Debug for GlobalMemory {
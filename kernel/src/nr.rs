@@ -4,6 +4,9 @@
 use crate::prelude::*;
 use core::fmt::Debug;
 
+use alloc::vec::Vec;
+
+use fallible_collections::FallibleVec;
 use hashbrown::HashMap;
 use log::{error, trace};
 use node_replication::Dispatch;
@@ -16,14 +19,25 @@ use crate::process::{Pid, MAX_PROCESSES};
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ReadOps {
     CurrentProcess(atopology::GlobalThreadId),
+    /// How many processes are running (processes that exited but weren't
+    /// waited for yet don't count)
+    ProcessCount,
+    /// Did the child exit (parent, child)?
+    ChildExited(Pid, Pid),
 }
 
 #[derive(PartialEq, Clone, Debug)]
 pub enum Op {
-    /// Allocate a new process (Pid)
-    AllocatePid,
+    /// Allocate a new process (Pid), with the process that created it
+    AllocatePid(Option<Pid>),
+    /// Record the exit code of a process, the pid is kept until the parent
+    /// waits for it
+    ExitPid(Pid, u64),
     /// Destroy a process
     FreePid(Pid),
+    /// Collect the exit code of a child (parent, child), frees the pid of
+    /// the child
+    WaitPid(Pid, Pid),
     /// Assign a core to a process
     SchedAllocateCore(
         Pid,
@@ -31,14 +45,20 @@ pub enum Op {
         Option<atopology::GlobalThreadId>,
         VAddr,
     ),
+    /// Take all cores away from a process
+    SchedReleaseCores(Pid),
 }
 
 #[derive(Debug, Clone)]
 pub enum NodeResult {
     PidAllocated(Pid),
     PidReturned,
+    PidExited,
+    ExitCode(u64),
     CoreInfo(CoreInfo),
     CoreAllocated(atopology::GlobalThreadId),
+    CoresReleased(Vec<atopology::GlobalThreadId>),
+    ProcessCount(usize),
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -47,8 +67,17 @@ pub struct CoreInfo {
     pub entry_point: VAddr,
 }
 
+/// State the kernel keeps for every allocated pid.
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessStatus {
+    /// The process that created this one (if it's still around).
+    pub parent: Option<Pid>,
+    /// Set once the process exited.
+    pub exit_code: Option<u64>,
+}
+
 pub struct KernelNode {
-    process_map: HashMap<Pid, ()>,
+    process_map: HashMap<Pid, ProcessStatus>,
     scheduler_map: HashMap<atopology::GlobalThreadId, CoreInfo>,
 }
 
@@ -92,6 +121,98 @@ impl KernelNode {
                 }
             })
     }
+
+    /// Removes all cores of the process from the scheduler, returns the cores
+    /// that were assigned to it.
+    pub fn release_cores(pid: Pid) -> Result<Vec<atopology::GlobalThreadId>, KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute_mut(Op::SchedReleaseCores(pid), *token);
+
+                match response {
+                    Ok(NodeResult::CoresReleased(cores)) => Ok(cores),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn free_pid(pid: Pid) -> Result<(), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute_mut(Op::FreePid(pid), *token);
+
+                match response {
+                    Ok(NodeResult::PidReturned) => Ok(()),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Records that process `pid` exited with `exit_code`.
+    ///
+    /// The pid stays allocated until the parent waits for it, processes
+    /// without a parent are freed right away.
+    pub fn exit_pid(pid: Pid, exit_code: u64) -> Result<(), KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute_mut(Op::ExitPid(pid, exit_code), *token);
+
+                match response {
+                    Ok(NodeResult::PidExited) => Ok(()),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    /// Waits until `child` (a process created by `parent`) exited and returns
+    /// its exit code. With `nonblock`, fails with `WouldBlock` if the child is
+    /// still running, otherwise waiting restarts the system call.
+    pub fn wait_pid(parent: Pid, child: Pid, nonblock: bool) -> Result<u64, KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                // The exit code is only collected (in the log) once the child exited
+                let response = replica.execute(ReadOps::ChildExited(parent, child), *token);
+                match response {
+                    Ok(NodeResult::PidExited) => {}
+                    Err(KError::WouldBlock) if !nonblock => return Err(KError::Restart),
+                    Err(e) => return Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+
+                let response = replica.execute_mut(Op::WaitPid(parent, child), *token);
+                match response {
+                    Ok(NodeResult::ExitCode(exit_code)) => Ok(exit_code),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
+
+    pub fn process_count() -> Result<usize, KError> {
+        let kcb = super::kcb::get_kcb();
+        kcb.replica
+            .as_ref()
+            .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
+                let response = replica.execute(ReadOps::ProcessCount, *token);
+
+                match response {
+                    Ok(NodeResult::ProcessCount(count)) => Ok(count),
+                    Err(e) => Err(e),
+                    Ok(_) => unreachable!("Got unexpected response"),
+                }
+            })
+    }
 }
 
 impl Dispatch for KernelNode {
@@ -108,25 +229,80 @@ impl Dispatch for KernelNode {
                     .ok_or(KError::NoExecutorForCore)?;
                 Ok(NodeResult::CoreInfo(*core_info))
             }
+            ReadOps::ProcessCount => {
+                let running = self
+                    .process_map
+                    .values()
+                    .filter(|status| status.exit_code.is_none())
+                    .count();
+                Ok(NodeResult::ProcessCount(running))
+            }
+            ReadOps::ChildExited(parent, child) => match self.process_map.get(&child) {
+                Some(status) if status.parent == Some(parent) => match status.exit_code {
+                    Some(_exit_code) => Ok(NodeResult::PidExited),
+                    None => Err(KError::WouldBlock),
+                },
+                _ => Err(KError::NoProcessFoundForPid),
+            },
         }
     }
 
     fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
         match op {
-            Op::AllocatePid => {
+            Op::AllocatePid(parent) => {
                 // TODO(performance): O(n) scan probably not what we really
                 // want, fine for now, MAX_PROCESSES is tiny
                 for i in 0..MAX_PROCESSES {
                     if !self.process_map.contains_key(&i) {
                         self.process_map.try_reserve(1)?;
-                        let r = self.process_map.insert(i, ());
+                        let status = ProcessStatus {
+                            parent,
+                            exit_code: None,
+                        };
+                        let r = self.process_map.insert(i, status);
                         assert!(r.is_none(), "!contains_key");
                         return Ok(NodeResult::PidAllocated(i));
                     }
                 }
                 Err(KError::OutOfPids)
             }
-            // TODO: better impl, what about scheduler_map?
+            Op::ExitPid(pid, exit_code) => {
+                let status = match self.process_map.get(&pid) {
+                    Some(status) if status.exit_code.is_none() => *status,
+                    _ => return Err(KError::NoProcessFoundForPid),
+                };
+
+                // Nobody waits for the children of the process anymore
+                self.process_map
+                    .retain(|_cpid, child| child.parent != Some(pid) || child.exit_code.is_none());
+                for child in self.process_map.values_mut() {
+                    if child.parent == Some(pid) {
+                        child.parent = None;
+                    }
+                }
+
+                // A parent always outlives the parent link of its children
+                // (see above), so someone can still wait for the process
+                if status.parent.is_some() {
+                    let status = self.process_map.get_mut(&pid).expect("Checked above");
+                    status.exit_code = Some(exit_code);
+                } else {
+                    self.process_map.remove(&pid);
+                }
+                Ok(NodeResult::PidExited)
+            }
+            Op::WaitPid(parent, child) => match self.process_map.get(&child).copied() {
+                Some(status) if status.parent == Some(parent) => match status.exit_code {
+                    Some(exit_code) => {
+                        self.process_map.remove(&child);
+                        Ok(NodeResult::ExitCode(exit_code))
+                    }
+                    None => Err(KError::WouldBlock),
+                },
+                _ => Err(KError::NoProcessFoundForPid),
+            },
+            // The cores of the process are released with `SchedReleaseCores`
+            // before, so it doesn't run anywhere anymore.
             Op::FreePid(pid) => match self.process_map.remove(&pid) {
                 Some(_) => Ok(NodeResult::PidReturned),
                 None => {
@@ -153,6 +329,22 @@ impl Dispatch for KernelNode {
                 }
             }
             Op::SchedAllocateCore(_pid, _affinity, _gtid, _entry_point) => unimplemented!(),
+            Op::SchedReleaseCores(pid) => {
+                if !self.process_map.contains_key(&pid) {
+                    return Err(KError::NoProcessFoundForPid);
+                }
+
+                let mut cores = Vec::new();
+                for (gtid, cinfo) in self.scheduler_map.iter() {
+                    if cinfo.pid == pid {
+                        cores.try_push(*gtid)?;
+                    }
+                }
+                for gtid in cores.iter() {
+                    self.scheduler_map.remove(gtid);
+                }
+                Ok(NodeResult::CoresReleased(cores))
+            }
         }
     }
 }
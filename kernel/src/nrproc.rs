@@ -16,7 +16,7 @@ use crate::error::KError;
 use crate::memory::detmem::DA;
 use crate::memory::vspace::{AddressSpace, MapAction, TlbFlushHandle};
 use crate::memory::{Frame, PAddr, VAddr};
-use crate::process::{Eid, Executor, Pid, Process, MAX_PROCESSES};
+use crate::process::{Eid, Executor, Pid, Process, ProcessArgs, MAX_PROCESSES};
 
 use crate::kcb::{ArchSpecificKcb, Kcb};
 
@@ -25,25 +25,33 @@ use crate::kcb::{ArchSpecificKcb, Kcb};
 pub enum ReadOps {
     ProcessInfo,
     MemResolve(VAddr),
+    /// Is the process subscribed to an exception vector?
+    EventSubscribed(u64),
 }
 
 /// Mutable operations on the NrProcess.
 #[derive(PartialEq, Clone, Debug)]
 pub enum Op {
     ProcRaiseIrq,
-    Load(Pid, &'static Module, Vec<Frame>),
+    Load(Pid, &'static Module, Vec<Frame>, ProcessArgs),
 
     /// Assign a core to a process.
     AssignExecutor(atopology::NodeId, atopology::GlobalThreadId),
 
+    /// Release all resources of the process (returns the frames to free).
     Destroy,
 
+    /// Forward an exception vector to the process as an upcall.
+    SubscribeEvent(u64),
+
     /// Assign a physical frame to a process (returns a FrameId).
     AllocateFrameToProcess(Frame),
 
     DispatcherAllocation(Frame),
 
     MemMapFrame(VAddr, Frame, MapAction),
+    /// Map a frame of a file (the file-system owns the frame).
+    MemMapFileFrame(VAddr, Frame, MapAction),
     MemMapDevice(Frame, MapAction),
     MemMapFrameId(VAddr, FrameId, MapAction),
     MemAdjust,
@@ -54,10 +62,11 @@ pub enum Op {
 #[derive(Debug, Clone)]
 pub enum NodeResult<E: Executor> {
     Loaded,
-    Destroyed,
+    Destroyed(Vec<Frame>),
     ProcessInfo(ProcessInfo),
     Executor(Box<E>),
     VectorAllocated(u64),
+    Subscribed(bool),
     ExecutorsCreated(usize),
     Mapped,
     MappedFrameId(PAddr, usize),
@@ -81,6 +90,8 @@ pub fn advance_all() {
 pub struct NrProcess<P: Process, M: Allocator + Clone = alloc::alloc::Global> {
     /// A list of all cores where the current process is running.
     active_cores: Vec<(atopology::GlobalThreadId, Eid), M>,
+    /// Memory that got mapped into the process with `MemMapFrame`.
+    mapped_memory: Vec<Frame, M>,
     /// The process struct itself.
     process: Box<P>,
 }
@@ -89,6 +100,7 @@ impl<P: Process> NrProcess<P> {
     pub fn new(process: Box<P>, _da: DA) -> NrProcess<P> {
         NrProcess {
             active_cores: Vec::new(),
+            mapped_memory: Vec::new(),
             process,
         }
     }
@@ -99,6 +111,7 @@ impl<P: Process> NrProcess<P> {
         pid: Pid,
         module: &'static Module,
         writeable_sections: Vec<Frame>,
+        args: ProcessArgs,
     ) -> Result<(), KError> {
         debug_assert!(pid < MAX_PROCESSES, "Invalid PID");
 
@@ -106,7 +119,7 @@ impl<P: Process> NrProcess<P> {
         let node = kcb.arch.node();
 
         let response = PROCESS_TABLE[node][pid].execute_mut(
-            Op::Load(pid, module, writeable_sections),
+            Op::Load(pid, module, writeable_sections, args),
             kcb.process_token[pid],
         );
         match response {
@@ -116,7 +129,30 @@ impl<P: Process> NrProcess<P> {
         }
     }
 
+    /// Releases the state of the process on all replicas, returns the frames
+    /// that were allocated for the process.
+    ///
+    /// The process must not run on any core anymore.
+    pub fn destroy(pid: Pid) -> Result<Vec<Frame>, KError> {
+        debug_assert!(pid < MAX_PROCESSES, "Invalid PID");
+
+        let kcb = super::kcb::get_kcb();
+        let node = kcb.arch.node();
+
+        let response = PROCESS_TABLE[node][pid].execute_mut(Op::Destroy, kcb.process_token[pid]);
+        match response {
+            Ok(NodeResult::Destroyed(frames)) => Ok(frames),
+            Err(e) => Err(e),
+            _ => unreachable!("Got unexpected response"),
+        }
+    }
+
     pub fn resolve(pid: Pid, base: VAddr) -> Result<(u64, u64), KError> {
+        NrProcess::<P>::resolve_rights(pid, base).map(|(paddr, _rights)| (paddr.as_u64(), 0x0))
+    }
+
+    /// Find the frame `base` is mapped to, and the rights of the mapping.
+    pub fn resolve_rights(pid: Pid, base: VAddr) -> Result<(PAddr, MapAction), KError> {
         debug_assert!(pid < MAX_PROCESSES, "Invalid PID");
         debug_assert!(base.as_u64() < kpi::KERNEL_BASE, "Invalid base");
 
@@ -126,7 +162,7 @@ impl<P: Process> NrProcess<P> {
         let response =
             PROCESS_TABLE[node][pid].execute(ReadOps::MemResolve(base), kcb.process_token[pid]);
         match response {
-            Ok(NodeResult::Resolved(paddr, _rights)) => Ok((paddr.as_u64(), 0x0)),
+            Ok(NodeResult::Resolved(paddr, rights)) => Ok((paddr, rights)),
             Err(e) => Err(e),
             _ => unreachable!("Got unexpected response"),
         }
@@ -201,6 +237,30 @@ impl<P: Process> NrProcess<P> {
         base: VAddr,
         frames: Vec<Frame>,
         action: MapAction,
+    ) -> Result<(u64, u64), KError> {
+        NrProcess::<P>::map_frames_with(pid, base, frames, |vaddr, frame| {
+            Op::MemMapFrame(vaddr, frame, action)
+        })
+    }
+
+    /// Like `map_frames` but for frames that belong to a file, they stay with
+    /// the file-system when the process is destroyed.
+    pub fn map_file_frames(
+        pid: Pid,
+        base: VAddr,
+        frames: Vec<Frame>,
+        action: MapAction,
+    ) -> Result<(u64, u64), KError> {
+        NrProcess::<P>::map_frames_with(pid, base, frames, |vaddr, frame| {
+            Op::MemMapFileFrame(vaddr, frame, action)
+        })
+    }
+
+    fn map_frames_with(
+        pid: Pid,
+        base: VAddr,
+        frames: Vec<Frame>,
+        map_op: impl Fn(VAddr, Frame) -> Op,
     ) -> Result<(u64, u64), KError> {
         debug_assert!(pid < MAX_PROCESSES, "Invalid PID");
 
@@ -209,19 +269,12 @@ impl<P: Process> NrProcess<P> {
 
         let mut virtual_offset = 0;
         for frame in frames {
-            let response = PROCESS_TABLE[node][pid].execute_mut(
-                Op::MemMapFrame(base + virtual_offset, frame, action),
-                kcb.process_token[pid],
-            );
+            let op = map_op(base + virtual_offset, frame);
+            let response = PROCESS_TABLE[node][pid].execute_mut(op.clone(), kcb.process_token[pid]);
             match response {
                 Ok(NodeResult::Mapped) => {}
-                e => unreachable!(
-                    "Got unexpected response MemMapFrame {:?} {:?} {:?} {:?}",
-                    e,
-                    base + virtual_offset,
-                    frame,
-                    action
-                ),
+                Err(e) => return Err(e),
+                e => unreachable!("Got unexpected response {:?} {:?}", e, op),
             }
 
             virtual_offset += frame.size();
@@ -245,6 +298,36 @@ impl<P: Process> NrProcess<P> {
         }
     }
 
+    pub fn subscribe_event(pid: Pid, vector: u64) -> Result<(), KError> {
+        debug_assert!(pid < MAX_PROCESSES, "Invalid PID");
+
+        let kcb = super::kcb::get_kcb();
+        let node = kcb.arch.node();
+
+        let response = PROCESS_TABLE[node][pid]
+            .execute_mut(Op::SubscribeEvent(vector), kcb.process_token[pid]);
+        match response {
+            Ok(NodeResult::Subscribed(true)) => Ok(()),
+            Err(e) => Err(e),
+            _ => unreachable!("Got unexpected response"),
+        }
+    }
+
+    pub fn is_subscribed(pid: Pid, vector: u64) -> Result<bool, KError> {
+        debug_assert!(pid < MAX_PROCESSES, "Invalid PID");
+
+        let kcb = super::kcb::get_kcb();
+        let node = kcb.arch.node();
+
+        let response = PROCESS_TABLE[node][pid]
+            .execute(ReadOps::EventSubscribed(vector), kcb.process_token[pid]);
+        match response {
+            Ok(NodeResult::Subscribed(subscribed)) => Ok(subscribed),
+            Err(e) => Err(e),
+            _ => unreachable!("Got unexpected response"),
+        }
+    }
+
     pub fn allocate_executor<A>(kcb: &Kcb<A>, pid: Pid) -> Result<Box<P::E>, KError>
     where
         A: ArchSpecificKcb<Process = P>,
@@ -313,17 +396,24 @@ where
                 let (paddr, rights) = self.process.vspace().resolve(base)?;
                 Ok(NodeResult::Resolved(paddr, rights))
             }
+            ReadOps::EventSubscribed(vector) => {
+                Ok(NodeResult::Subscribed(self.process.is_subscribed(vector)))
+            }
         }
     }
 
     fn dispatch_mut(&mut self, op: Self::WriteOperation) -> Self::Response {
         match op {
-            Op::Destroy => unimplemented!("Destrroy"),
             Op::ProcRaiseIrq => unimplemented!("ProcRaiseIrq"),
             Op::MemAdjust => unimplemented!("MemAdjust"),
 
-            Op::Load(pid, module, writeable_sections) => {
-                self.process.load(pid, module, writeable_sections)?;
+            Op::SubscribeEvent(vector) => {
+                self.process.subscribe_event(vector)?;
+                Ok(NodeResult::Subscribed(true))
+            }
+
+            Op::Load(pid, module, writeable_sections, args) => {
+                self.process.load(pid, module, writeable_sections, args)?;
                 Ok(NodeResult::Loaded)
             }
 
@@ -332,7 +422,23 @@ where
                 Ok(NodeResult::ExecutorsCreated(how_many))
             }
 
+            Op::Destroy => {
+                let mut frames = self.process.destroy()?;
+                FallibleVec::try_reserve(&mut frames, self.mapped_memory.len())?;
+                frames.extend(self.mapped_memory.drain(..));
+                self.active_cores.clear();
+                Ok(NodeResult::Destroyed(frames))
+            }
+
             Op::MemMapFrame(base, frame, action) => {
+                crate::memory::KernelAllocator::try_refill_tcache(7, 0)?;
+                FallibleVec::try_reserve(&mut self.mapped_memory, 1)?;
+                self.process.vspace_mut().map_frame(base, frame, action)?;
+                self.mapped_memory.push(frame);
+                Ok(NodeResult::Mapped)
+            }
+
+            Op::MemMapFileFrame(base, frame, action) => {
                 crate::memory::KernelAllocator::try_refill_tcache(7, 0)?;
                 self.process.vspace_mut().map_frame(base, frame, action)?;
                 Ok(NodeResult::Mapped)
@@ -14,6 +14,7 @@ use cstr_core::CStr;
 use fallible_collections::vec::FallibleVecGlobal;
 use fallible_collections::vec::TryCollect;
 use fallible_collections::TryReserveError;
+use kpi::io::IoVec;
 use kpi::process::{FrameId, ELF_OFFSET};
 use log::{debug, info, trace};
 
@@ -56,6 +57,27 @@ impl KernSlice {
         unsafe { Arc::get_mut_unchecked(&mut buffer).copy_from_slice(&user_slice[0..len]) };
         KernSlice { buffer }
     }
+
+    /// Copy the user buffers in `iovecs` one after the other into a single
+    /// kernel buffer of `len` bytes (the sum of the buffer lengths).
+    pub fn from_iovecs(iovecs: &[IoVec], len: usize) -> KernSlice {
+        let buffer = Arc::<[u8]>::new_uninit_slice(len);
+        let mut buffer = unsafe { buffer.assume_init() };
+
+        let mut copied = 0;
+        for iov in iovecs {
+            let mut user_ptr = VAddr::from(iov.base);
+            let slice_ptr = UserPtr::new(&mut user_ptr);
+            let user_slice: &[u8] =
+                unsafe { core::slice::from_raw_parts(slice_ptr.as_ptr(), iov.len as usize) };
+            unsafe {
+                Arc::get_mut_unchecked(&mut buffer)[copied..copied + user_slice.len()]
+                    .copy_from_slice(user_slice)
+            };
+            copied += user_slice.len();
+        }
+        KernSlice { buffer }
+    }
 }
 
 pub fn userptr_to_str(useraddr: u64) -> Result<String, KError> {
@@ -77,6 +99,16 @@ pub fn userptr_to_str(useraddr: u64) -> Result<String, KError> {
 /// Process ID.
 pub type Pid = usize;
 
+/// Command line arguments a process is started with (they end up in the
+/// [`kpi::process::ProcessInfo`] of the process).
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct ProcessArgs {
+    /// Command line arguments.
+    pub cmdline: String,
+    /// App specific command line arguments.
+    pub app_cmdline: String,
+}
+
 /// Executor ID.
 pub type Eid = usize;
 
@@ -90,6 +122,7 @@ pub trait Process {
         pid: Pid,
         module: &Module,
         writable_sections: Vec<Frame>,
+        args: ProcessArgs,
     ) -> Result<(), KError>
     where
         Self: core::marker::Sized;
@@ -115,9 +148,21 @@ pub trait Process {
 
     fn pinfo(&self) -> &kpi::process::ProcessInfo;
 
+    /// Forward the exception `vector` to the process as an upcall.
+    fn subscribe_event(&mut self, vector: u64) -> Result<(), KError>;
+    /// Does the process want the exception `vector` as an upcall?
+    fn is_subscribed(&self, vector: u64) -> bool;
+
     fn add_frame(&mut self, frame: Frame) -> Result<FrameId, KError>;
     fn get_frame(&mut self, frame_id: FrameId) -> Result<Frame, KError>;
     fn deallocate_frame(&mut self, fid: FrameId) -> Result<Frame, KError>;
+
+    /// Releases the address-space and executors of the process, afterwards
+    /// it can be loaded again.
+    ///
+    /// Memory that only this replica uses is freed, the frames shared by all
+    /// replicas are returned so they can be freed once.
+    fn destroy(&mut self) -> Result<Vec<Frame>, KError>;
 }
 
 /// ResumeHandle is the HW specific logic that switches the CPU
@@ -335,7 +380,13 @@ impl elfloader::ElfLoader for DataSecAllocator {
 ///
 /// Parse & relocate ELF
 /// Create an initial VSpace
-pub fn make_process<P: Process>(binary: &'static str) -> Result<Pid, KError> {
+///
+/// `parent` is the process that creates the new one (it can wait for it).
+pub fn make_process<P: Process>(
+    binary: &'static str,
+    args: ProcessArgs,
+    parent: Option<Pid>,
+) -> Result<Pid, KError> {
     KernelAllocator::try_refill_tcache(7, 1)?;
     let kcb = kcb::get_kcb();
 
@@ -350,7 +401,7 @@ pub fn make_process<P: Process>(binary: &'static str) -> Result<Pid, KError> {
     let mod_file = mod_file.ok_or(KError::BinaryNotFound { binary })?;
     info!(
         "binary={} cmdline={} module={:?}",
-        binary, kcb.cmdline.init_args, mod_file
+        binary, args.cmdline, mod_file
     );
 
     let elf_module = unsafe {
@@ -381,12 +432,20 @@ pub fn make_process<P: Process>(binary: &'static str) -> Result<Pid, KError> {
     kcb.replica
         .as_ref()
         .map_or(Err(KError::ReplicaNotSet), |(replica, token)| {
-            let response = replica.execute_mut(nr::Op::AllocatePid, *token)?;
+            let response = replica.execute_mut(nr::Op::AllocatePid(parent), *token)?;
             if let nr::NodeResult::PidAllocated(pid) = response {
-                cnrfs::MlnrKernelNode::add_process(pid)
-                    .expect("TODO(error-handling): revert state");
-                crate::nrproc::NrProcess::<P>::load(pid, mod_file, data_frames)
-                    .expect("TODO(error-handling): revert state properly");
+                // A failed step undoes the ones before it, the process never ran
+                if let Err(e) = cnrfs::MlnrKernelNode::add_process(pid) {
+                    nr::KernelNode::free_pid(pid)?;
+                    return Err(e);
+                }
+                if let Err(e) = nrproc::NrProcess::<P>::load(pid, mod_file, data_frames, args) {
+                    let frames = nrproc::NrProcess::<P>::destroy(pid)?;
+                    KernelAllocator::release_frames(&frames)?;
+                    cnrfs::MlnrKernelNode::remove_process(pid)?;
+                    nr::KernelNode::free_pid(pid)?;
+                    return Err(e);
+                }
                 Ok(pid)
             } else {
                 Err(KError::ProcessLoadingFailed)
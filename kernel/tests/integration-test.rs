@@ -1190,6 +1190,27 @@ fn s04_userspace_multicore() {
     wait_for_sigterm(&cmdline, qemu_run(), output);
 }
 
+/// Tests that a process can spawn another process and wait for its exit code.
+#[cfg(not(feature = "baremetal"))]
+#[test]
+fn s04_userspace_spawn() {
+    let cmdline = RunnerArgs::new("test-userspace-smp")
+        .user_feature("test-spawn")
+        .cores(2)
+        .timeout(20_000);
+    let mut output = String::new();
+
+    let mut qemu_run = || -> Result<WaitStatus> {
+        let mut p = spawn_nrk(&cmdline)?;
+
+        output += p.exp_string("spawn_test OK")?.as_str();
+        output += p.exp_eof()?.as_str();
+        p.process.exit()
+    };
+
+    check_for_successful_exit(&cmdline, qemu_run(), output);
+}
+
 /// Tests that user-space networking is functional.
 ///
 /// This tests various user-space components such as:
@@ -1951,6 +1972,32 @@ fn s06_test_fs() {
     check_for_successful_exit(&cmdline, qemu_run(), output);
 }
 
+/// Tests that a file opened through the file-system replica of one NUMA node
+/// can be written through the replica of another node.
+#[cfg(not(feature = "baremetal"))]
+#[test]
+fn s06_test_fs_replicas() {
+    let cmdline = RunnerArgs::new("test-userspace-smp")
+        .module("init")
+        .user_feature("test-fs-replicas")
+        .cores(2)
+        .nodes(2)
+        .memory(2048)
+        .release()
+        .timeout(20_000);
+    let mut output = String::new();
+
+    let mut qemu_run = || -> Result<WaitStatus> {
+        let mut p = spawn_nrk(&cmdline)?;
+
+        p.exp_string("fs_replica_test OK")?;
+        output = p.exp_eof()?;
+        p.process.exit()
+    };
+
+    check_for_successful_exit(&cmdline, qemu_run(), output);
+}
+
 fn memcached_benchmark(
     driver: &'static str,
     cores: usize,
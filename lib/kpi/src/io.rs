@@ -2,22 +2,73 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use bitflags::*;
+use serde::{Deserialize, Serialize};
 
-/// Struct used in `file_getinfo` systemcall.
+/// Struct used in `file_getinfo` and `fstat` systemcalls.
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
 pub struct FileInfo {
     pub ftype: u64,
     pub fsize: u64,
+    /// Modes of the file (see `FileModes`).
+    pub fmode: u64,
+    /// Mnode number of the file.
+    pub mnode: u64,
+    /// Number of links to the file.
+    pub nlink: u64,
+    /// Creation time in nanoseconds since the unix epoch.
+    pub ctime: u64,
+    /// Last modification time in nanoseconds since the unix epoch.
+    pub mtime: u64,
+    /// Last access time in nanoseconds since the unix epoch (not updated by
+    /// reads, so it is the creation time).
+    pub atime: u64,
 }
 
-/// Each file-node can be of two types: directory or a file.
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+/// Struct used in the `statfs` system call.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct FsStats {
+    /// Number of mnodes (files, directories, links and pipes).
+    pub mnodes: u64,
+    /// Memory (in bytes) allocated to hold file and pipe data.
+    pub used: u64,
+    /// Free memory (in bytes) left for the file-system to allocate.
+    pub free: u64,
+    /// Number of logs the file-system operations are spread over.
+    pub logs: u64,
+    /// Number of file-system replicas.
+    pub replicas: u64,
+}
+
+/// Maximum number of buffers in a `readv`/`writev` system call.
+pub const IOV_MAX: usize = 1024;
+
+/// A buffer for vectored I/O; laid out like `struct iovec` in C.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct IoVec {
+    /// Start address of the buffer.
+    pub base: u64,
+    /// Length of the buffer in bytes.
+    pub len: u64,
+}
+
+/// Each file-node can be of five types: directory, file, symbolic link, pipe
+/// or the event queue of file watches.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
 #[repr(u64)]
 pub enum FileType {
     /// The mnode is of directory type
     Directory = 1,
     /// The mnode is of regular type
     File = 2,
+    /// The mnode is a symbolic link to another path
+    Symlink = 3,
+    /// The mnode is an anonymous pipe
+    Pipe = 4,
+    /// The mnode is a queue of file change events
+    Watch = 5,
 }
 
 impl From<FileType> for u64 {
@@ -25,10 +76,49 @@ impl From<FileType> for u64 {
         match ft {
             FileType::Directory => 1,
             FileType::File => 2,
+            FileType::Symlink => 3,
+            FileType::Pipe => 4,
+            FileType::Watch => 5,
+        }
+    }
+}
+
+/// Reference point for the offset given to the `seek` systemcall.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[repr(u64)]
+pub enum SeekWhence {
+    /// The offset is set to the given offset (SEEK_SET).
+    Set = 0,
+    /// The offset is set to the current offset plus the given offset (SEEK_CUR).
+    Current = 1,
+    /// The offset is set to the size of the file plus the given offset (SEEK_END).
+    End = 2,
+    Unknown,
+}
+
+impl From<u64> for SeekWhence {
+    /// Construct a SeekWhence enum based on a 64-bit value.
+    fn from(whence: u64) -> SeekWhence {
+        match whence {
+            0 => SeekWhence::Set,
+            1 => SeekWhence::Current,
+            2 => SeekWhence::End,
+            _ => SeekWhence::Unknown,
         }
     }
 }
 
+/// An entry of a directory, returned by the `readdir` systemcall.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DirEntry<'a> {
+    /// Name of the entry (without the path of the directory).
+    pub name: &'a str,
+    /// Mnode number of the entry.
+    pub mnode: u64,
+    /// Type of the entry (see `FileType`).
+    pub ftype: u64,
+}
+
 bitflags! {
     /// File flags to open the file
     pub struct FileFlags:u64 {
@@ -36,8 +126,10 @@ bitflags! {
         const O_RDONLY = 0x0001; /* open for reading only */
         const O_WRONLY = 0x0002; /* open for writing only */
         const O_RDWR = 0x0003; /* open for reading and writing */
+        const O_NONBLOCK = 0x0004; /* no delay */
         const O_CREAT = 0x0200; /* create if nonexistant */
         const O_TRUNC = 0x0400; /* truncate to zero length */
+        const O_EXCL = 0x0800; /* error if already exists */
         const O_APPEND = 0x02000; /* append at the EOF */
     }
 }
@@ -85,6 +177,76 @@ impl FileFlags {
     pub fn is_append(&self) -> bool {
         (*self & FileFlags::O_APPEND) == FileFlags::O_APPEND
     }
+
+    pub fn is_excl(&self) -> bool {
+        (*self & FileFlags::O_EXCL) == FileFlags::O_EXCL
+    }
+
+    pub fn is_nonblock(&self) -> bool {
+        (*self & FileFlags::O_NONBLOCK) == FileFlags::O_NONBLOCK
+    }
+}
+
+bitflags! {
+    /// Changes a file watch can report. A watch on a directory reports the
+    /// changes to its entries, a watch on any mnode the changes to the mnode
+    /// itself.
+    pub struct WatchEvents: u64 {
+        const IN_CREATE = 0x1; /* entry created */
+        const IN_MODIFY = 0x2; /* file written or truncated */
+        const IN_DELETE = 0x4; /* entry deleted */
+        const IN_RENAME = 0x8; /* entry renamed */
+        const IN_Q_OVERFLOW = 0x10; /* events were dropped */
+    }
+}
+
+/// Convert u64 to WatchEvents.
+impl From<u64> for WatchEvents {
+    fn from(events: u64) -> WatchEvents {
+        WatchEvents::from_bits_truncate(events)
+    }
+}
+
+/// Convert WatchEvents to u64.
+impl From<WatchEvents> for u64 {
+    fn from(events: WatchEvents) -> u64 {
+        events.bits()
+    }
+}
+
+/// A file change, as read from the event queue of file watches.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct WatchEvent {
+    /// Watch descriptor of the watch that reported the event.
+    pub wd: u64,
+    /// What happened (see `WatchEvents`).
+    pub mask: u64,
+    /// Mnode number of the created, modified, deleted or renamed mnode.
+    pub mnode: u64,
+}
+
+bitflags! {
+    /// Flags for advisory file locks.
+    pub struct LockFlags: u64 {
+        const LOCK_SH = 0x1; /* shared lock */
+        const LOCK_EX = 0x2; /* exclusive lock */
+        const LOCK_NB = 0x4; /* don't block when locking */
+    }
+}
+
+/// Convert u64 to LockFlags.
+impl From<u64> for LockFlags {
+    fn from(flag: u64) -> LockFlags {
+        LockFlags::from_bits_truncate(flag)
+    }
+}
+
+/// Convert LockFlags to u64.
+impl From<LockFlags> for u64 {
+    fn from(flag: LockFlags) -> u64 {
+        flag.bits()
+    }
 }
 
 bitflags! {
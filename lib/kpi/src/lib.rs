@@ -54,6 +54,10 @@ pub enum SystemCallError {
     PermissionError = 9,
     /// Bad offset
     OffsetError = 10,
+    /// The file already exists.
+    AlreadyExists = 11,
+    /// The operation would block on a non-blocking file descriptor.
+    WouldBlock = 12,
     /// Placeholder for an invalid, unknown error code.
     Unknown,
 }
@@ -72,6 +76,8 @@ impl From<u64> for SystemCallError {
             8 => SystemCallError::BadFlags,
             9 => SystemCallError::PermissionError,
             10 => SystemCallError::OffsetError,
+            11 => SystemCallError::AlreadyExists,
+            12 => SystemCallError::WouldBlock,
             _ => SystemCallError::Unknown,
         }
     }
@@ -98,6 +104,10 @@ pub enum ProcessOperation {
     RequestCore = 7,
     /// Allocate a physical memory page as a mem object to the process.
     AllocatePhysical = 8,
+    /// Start a new process from a binary.
+    Spawn = 9,
+    /// Wait for a child process to exit.
+    Wait = 10,
     Unknown,
 }
 
@@ -113,6 +123,8 @@ impl From<u64> for ProcessOperation {
             6 => ProcessOperation::GetProcessInfo,
             7 => ProcessOperation::RequestCore,
             8 => ProcessOperation::AllocatePhysical,
+            9 => ProcessOperation::Spawn,
+            10 => ProcessOperation::Wait,
             _ => ProcessOperation::Unknown,
         }
     }
@@ -130,6 +142,8 @@ impl From<&str> for ProcessOperation {
             "GetProcessInfo" => ProcessOperation::GetProcessInfo,
             "RequestCore" => ProcessOperation::RequestCore,
             "AllocatePhysical" => ProcessOperation::AllocatePhysical,
+            "Spawn" => ProcessOperation::Spawn,
+            "Wait" => ProcessOperation::Wait,
             _ => ProcessOperation::Unknown,
         }
     }
@@ -149,6 +163,8 @@ pub enum VSpaceOperation {
     MapFrame = 4,
     /// Resolve a virtual to a physical address
     Identify = 5,
+    /// Map a range of an opened file
+    MapFile = 6,
     Unknown,
 }
 
@@ -161,6 +177,7 @@ impl From<u64> for VSpaceOperation {
             3 => VSpaceOperation::MapDevice,
             4 => VSpaceOperation::MapFrame,
             5 => VSpaceOperation::Identify,
+            6 => VSpaceOperation::MapFile,
             _ => VSpaceOperation::Unknown,
         }
     }
@@ -175,6 +192,7 @@ impl From<&str> for VSpaceOperation {
             "MapDevice" => VSpaceOperation::MapDevice,
             "MapFrame" => VSpaceOperation::MapFrame,
             "Identify" => VSpaceOperation::Identify,
+            "MapFile" => VSpaceOperation::MapFile,
             _ => VSpaceOperation::Unknown,
         }
     }
@@ -208,6 +226,46 @@ pub enum FileOperation {
     FileRename = 11,
     /// Create a directory.
     MkDir = 12,
+    /// List the entries of a directory.
+    ReadDir = 13,
+    /// Reposition the offset of a file descriptor.
+    Seek = 14,
+    /// Get the information related to an opened file.
+    FStat = 15,
+    /// Truncate (or extend) an opened file to a given length.
+    Truncate = 16,
+    /// Duplicate a file descriptor into the lowest free one.
+    Dup = 17,
+    /// Duplicate a file descriptor into a given one.
+    Dup2 = 18,
+    /// Change the working directory.
+    Chdir = 19,
+    /// Get the working directory.
+    Getcwd = 20,
+    /// Create a hard link to a file.
+    Link = 21,
+    /// Create a symbolic link.
+    Symlink = 22,
+    /// Read the target of a symbolic link.
+    ReadLink = 23,
+    /// Create a pipe, returns a read and a write file descriptor.
+    Pipe = 24,
+    /// Take an advisory lock on (a byte range of) a file.
+    Lock = 25,
+    /// Release an advisory lock.
+    Unlock = 26,
+    /// Read into an array of buffers.
+    ReadV = 27,
+    /// Write an array of buffers.
+    WriteV = 28,
+    /// Get the usage of the file-system.
+    StatFs = 29,
+    /// Create an event queue for file watches, returns a file descriptor.
+    WatchInit = 30,
+    /// Watch a file or directory for changes.
+    WatchAdd = 31,
+    /// Remove a watch from an event queue.
+    WatchRemove = 32,
     Unknown,
 }
 
@@ -227,6 +285,26 @@ impl From<u64> for FileOperation {
             10 => FileOperation::WriteDirect,
             11 => FileOperation::FileRename,
             12 => FileOperation::MkDir,
+            13 => FileOperation::ReadDir,
+            14 => FileOperation::Seek,
+            15 => FileOperation::FStat,
+            16 => FileOperation::Truncate,
+            17 => FileOperation::Dup,
+            18 => FileOperation::Dup2,
+            19 => FileOperation::Chdir,
+            20 => FileOperation::Getcwd,
+            21 => FileOperation::Link,
+            22 => FileOperation::Symlink,
+            23 => FileOperation::ReadLink,
+            24 => FileOperation::Pipe,
+            25 => FileOperation::Lock,
+            26 => FileOperation::Unlock,
+            27 => FileOperation::ReadV,
+            28 => FileOperation::WriteV,
+            29 => FileOperation::StatFs,
+            30 => FileOperation::WatchInit,
+            31 => FileOperation::WatchAdd,
+            32 => FileOperation::WatchRemove,
             _ => FileOperation::Unknown,
         }
     }
@@ -248,6 +326,26 @@ impl From<&str> for FileOperation {
             "WriteDirect" => FileOperation::WriteDirect,
             "Rename" => FileOperation::FileRename,
             "MkDir" => FileOperation::MkDir,
+            "ReadDir" => FileOperation::ReadDir,
+            "Seek" => FileOperation::Seek,
+            "FStat" => FileOperation::FStat,
+            "Truncate" => FileOperation::Truncate,
+            "Dup" => FileOperation::Dup,
+            "Dup2" => FileOperation::Dup2,
+            "Chdir" => FileOperation::Chdir,
+            "Getcwd" => FileOperation::Getcwd,
+            "Link" => FileOperation::Link,
+            "Symlink" => FileOperation::Symlink,
+            "ReadLink" => FileOperation::ReadLink,
+            "Pipe" => FileOperation::Pipe,
+            "Lock" => FileOperation::Lock,
+            "Unlock" => FileOperation::Unlock,
+            "ReadV" => FileOperation::ReadV,
+            "WriteV" => FileOperation::WriteV,
+            "StatFs" => FileOperation::StatFs,
+            "WatchInit" => FileOperation::WatchInit,
+            "WatchAdd" => FileOperation::WatchAdd,
+            "WatchRemove" => FileOperation::WatchRemove,
             _ => FileOperation::Unknown,
         }
     }
@@ -259,7 +357,7 @@ impl From<&str> for FileOperation {
 pub enum SystemOperation {
     /// Query information about available hardware threads in the system
     GetHardwareThreads = 1,
-    /// Print system/per-core info.
+    /// Print system/per-core info and get the system configuration.
     Stats = 2,
     /// Get the core id for the current thread.
     GetCoreID = 3,
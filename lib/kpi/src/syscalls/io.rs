@@ -3,6 +3,9 @@
 
 //! Abstraction for system calls to access the global file-system and control interrupts.
 
+use alloc::string::String;
+use alloc::vec::Vec;
+
 use crate::io::*;
 use crate::*;
 
@@ -102,6 +105,28 @@ impl Fs {
         }
     }
 
+    /// Reposition the offset of the file descriptor `fd` (like `lseek`).
+    ///
+    /// Returns the new offset, measured from the start of the file.
+    pub fn seek(fd: u64, offset: i64, whence: SeekWhence) -> Result<u64, SystemCallError> {
+        let (r, new_offset) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Seek as u64,
+                fd,
+                offset as u64,
+                whence as u64,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(new_offset)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
     pub fn read_at(fd: u64, buffer: u64, len: u64, offset: i64) -> Result<u64, SystemCallError> {
         Fs::fileio_at(FileOperation::ReadAt, fd, buffer, len, offset)
     }
@@ -110,6 +135,52 @@ impl Fs {
         Fs::fileio_at(FileOperation::WriteAt, fd, buffer, len, offset)
     }
 
+    pub fn readv(fd: u64, iov: &[IoVec]) -> Result<u64, SystemCallError> {
+        Fs::fileio_vectored(FileOperation::ReadV, fd, iov, -1)
+    }
+
+    pub fn writev(fd: u64, iov: &[IoVec]) -> Result<u64, SystemCallError> {
+        Fs::fileio_vectored(FileOperation::WriteV, fd, iov, -1)
+    }
+
+    pub fn readv_at(fd: u64, iov: &[IoVec], offset: i64) -> Result<u64, SystemCallError> {
+        Fs::fileio_vectored(FileOperation::ReadV, fd, iov, offset)
+    }
+
+    pub fn writev_at(fd: u64, iov: &[IoVec], offset: i64) -> Result<u64, SystemCallError> {
+        Fs::fileio_vectored(FileOperation::WriteV, fd, iov, offset)
+    }
+
+    /// Read into or write from the buffers in `iov` with a single system call,
+    /// starting at `offset` or at the file offset if `offset` is -1.
+    ///
+    /// A vectored write is applied to the file as a whole, so other processes
+    /// never see only part of it.
+    fn fileio_vectored(
+        op: FileOperation,
+        fd: u64,
+        iov: &[IoVec],
+        offset: i64,
+    ) -> Result<u64, SystemCallError> {
+        let (r, len) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                op as u64,
+                fd,
+                iov.as_ptr() as u64,
+                iov.len() as u64,
+                offset as u64,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(len)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
     /// Read or write an opened file starting at the offset.
     fn fileio_at(
         op: FileOperation,
@@ -170,6 +241,95 @@ impl Fs {
         }
     }
 
+    /// Retrieve information about an opened file.
+    pub fn fstat(fd: u64) -> Result<FileInfo, SystemCallError> {
+        let fileinfo: FileInfo = Default::default();
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::FStat,
+                fd,
+                &fileinfo as *const FileInfo as u64,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(fileinfo)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Get the number of mnodes and the memory usage of the file-system.
+    pub fn statfs() -> Result<FsStats, SystemCallError> {
+        let mut stats = FsStats::default();
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::StatFs as u64,
+                &mut stats as *mut FsStats as u64,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(stats)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Truncate (or extend with zeros) the opened file `fd` to `len` bytes.
+    pub fn truncate(fd: u64, len: u64) -> Result<u64, SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Truncate as u64,
+                fd,
+                len,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(0)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Duplicate the file descriptor `fd`, the new fd shares the offset with it.
+    pub fn dup(fd: u64) -> Result<u64, SystemCallError> {
+        let (r, newfd) =
+            unsafe { syscall!(SystemCall::FileIO as u64, FileOperation::Dup as u64, fd, 2) };
+
+        if r == 0 {
+            Ok(newfd)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Duplicate `oldfd` into `newfd`, `newfd` is closed first if it is open.
+    pub fn dup2(oldfd: u64, newfd: u64) -> Result<u64, SystemCallError> {
+        let (r, fd) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Dup2 as u64,
+                oldfd,
+                newfd,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(fd)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
     /// Delete a file given by `name`.
     pub fn delete(name: u64) -> Result<bool, SystemCallError> {
         let (r, is_deleted) = unsafe {
@@ -252,4 +412,290 @@ impl Fs {
             Err(SystemCallError::from(r))
         }
     }
+
+    /// List the entries of the directory `pathname`, sorted by name.
+    ///
+    /// The entries are deserialized from `buf`, which is grown if it is too small
+    /// to hold all of them.
+    pub fn readdir(pathname: u64, buf: &mut Vec<u8>) -> Result<Vec<DirEntry>, SystemCallError> {
+        if buf.is_empty() {
+            buf.resize(256, 0);
+        }
+
+        loop {
+            let (r, len) = unsafe {
+                syscall!(
+                    SystemCall::FileIO as u64,
+                    FileOperation::ReadDir as u64,
+                    pathname,
+                    buf.as_mut_ptr() as u64,
+                    buf.len() as u64,
+                    2
+                )
+            };
+
+            if r != 0 {
+                return Err(SystemCallError::from(r));
+            }
+
+            let len = len as usize;
+            if len <= buf.len() {
+                buf.truncate(len);
+                // A short or garbled buffer is an error, not a reason to panic.
+                return serde_cbor::from_slice(buf.as_slice())
+                    .map_err(|_e| SystemCallError::InternalError);
+            }
+            buf.resize(len, 0);
+        }
+    }
+
+    /// Change the working directory of the process to `pathname`.
+    pub fn chdir(pathname: u64) -> Result<(), SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Chdir as u64,
+                pathname,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Get the working directory of the process.
+    pub fn getcwd() -> Result<String, SystemCallError> {
+        let mut buf: Vec<u8> = alloc::vec![0; 64];
+
+        loop {
+            let (r, len) = unsafe {
+                syscall!(
+                    SystemCall::FileIO as u64,
+                    FileOperation::Getcwd as u64,
+                    buf.as_mut_ptr() as u64,
+                    buf.len() as u64,
+                    2
+                )
+            };
+
+            if r != 0 {
+                return Err(SystemCallError::from(r));
+            }
+
+            // The path is only copied if it fits with a terminating NUL.
+            let len = len as usize;
+            if len < buf.len() {
+                buf.truncate(len);
+                return String::from_utf8(buf).map_err(|_e| SystemCallError::InternalError);
+            }
+            buf.resize(len + 1, 0);
+        }
+    }
+
+    /// Create `newname` as a hard link to the file `oldname`.
+    pub fn link(oldname: u64, newname: u64) -> Result<(), SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Link as u64,
+                oldname,
+                newname,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Create a symbolic link `linkname` that points to `target`.
+    pub fn symlink(target: u64, linkname: u64) -> Result<(), SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Symlink as u64,
+                target,
+                linkname,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Get the target of the symbolic link `pathname`.
+    pub fn readlink(pathname: u64) -> Result<String, SystemCallError> {
+        let mut buf: Vec<u8> = alloc::vec![0; 64];
+
+        loop {
+            let (r, len) = unsafe {
+                syscall!(
+                    SystemCall::FileIO as u64,
+                    FileOperation::ReadLink as u64,
+                    pathname,
+                    buf.as_mut_ptr() as u64,
+                    buf.len() as u64,
+                    2
+                )
+            };
+
+            if r != 0 {
+                return Err(SystemCallError::from(r));
+            }
+
+            let len = len as usize;
+            if len <= buf.len() {
+                buf.truncate(len);
+                return String::from_utf8(buf).map_err(|_e| SystemCallError::InternalError);
+            }
+            buf.resize(len, 0);
+        }
+    }
+
+    /// Create a pipe. Returns the file descriptors of the read and the write
+    /// end; with `O_NONBLOCK` in `flags` reads fail with `WouldBlock` instead of
+    /// waiting for data.
+    pub fn pipe(flags: u64) -> Result<(u64, u64), SystemCallError> {
+        let (r, readfd, writefd) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Pipe as u64,
+                flags,
+                3
+            )
+        };
+
+        if r == 0 {
+            Ok((readfd, writefd))
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Take an advisory lock on `len` bytes of the file behind `fd`, starting
+    /// at `offset`; a `len` of 0 locks up to the end of the file, also when it
+    /// grows. `flags` has either `LOCK_SH` or `LOCK_EX` set; with `LOCK_NB` the
+    /// call fails with `WouldBlock` instead of waiting for a conflicting lock.
+    ///
+    /// Locks are held by the process and released when it closes a file
+    /// descriptor of the file.
+    pub fn lock(fd: u64, flags: LockFlags, offset: u64, len: u64) -> Result<(), SystemCallError> {
+        let (r, _) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Lock as u64,
+                fd,
+                u64::from(flags),
+                offset,
+                len,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Release the advisory locks the process holds on `len` bytes of the
+    /// file behind `fd`, starting at `offset`.
+    pub fn unlock(fd: u64, offset: u64, len: u64) -> Result<(), SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::Unlock as u64,
+                fd,
+                offset,
+                len,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Create an event queue for file watches. Changes are read from the
+    /// returned file descriptor with `read_events`; with `O_NONBLOCK` in
+    /// `flags` reading an empty queue fails with `WouldBlock`.
+    pub fn watch_init(flags: u64) -> Result<u64, SystemCallError> {
+        let (r, fd) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::WatchInit as u64,
+                flags,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(fd)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Watch `pathname` for the changes in `events`; returns the watch
+    /// descriptor reported with the events.
+    pub fn watch_add(fd: u64, pathname: u64, events: WatchEvents) -> Result<u64, SystemCallError> {
+        let (r, wd) = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::WatchAdd as u64,
+                fd,
+                pathname,
+                u64::from(events),
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(wd)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Remove the watch `wd` from the event queue `fd`.
+    pub fn watch_remove(fd: u64, wd: u64) -> Result<(), SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::FileIO as u64,
+                FileOperation::WatchRemove as u64,
+                fd,
+                wd,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Read events from the event queue `fd` into `events`, returns the
+    /// number of events read.
+    pub fn read_events(fd: u64, events: &mut [WatchEvent]) -> Result<usize, SystemCallError> {
+        let len = events.len() * core::mem::size_of::<WatchEvent>();
+        let read = Fs::read(fd, events.as_mut_ptr() as u64, len as u64)?;
+        Ok(read as usize / core::mem::size_of::<WatchEvent>())
+    }
 }
@@ -5,6 +5,7 @@
 
 use core::convert::TryInto;
 
+use crate::io::FileModes;
 use crate::process::FrameId;
 use crate::*;
 
@@ -64,6 +65,37 @@ impl VSpace {
         }
     }
 
+    /// Maps `len` bytes of the opened file `fd`, starting at `offset`, at
+    /// `base`. The mapping shares the memory with the file, so it sees all
+    /// later writes to it. `rights` can be read or read-execute.
+    ///
+    /// # Safety
+    /// Manipulates address space of process.
+    pub unsafe fn map_file(
+        base: u64,
+        fd: u64,
+        offset: u64,
+        len: u64,
+        rights: FileModes,
+    ) -> Result<VAddr, SystemCallError> {
+        let fd_rights = (u64::from(rights) << 32) | fd;
+        let (err, _base) = syscall!(
+            SystemCall::VSpace as u64,
+            VSpaceOperation::MapFile as u64,
+            base,
+            fd_rights,
+            offset,
+            len,
+            2
+        );
+
+        if err == 0 {
+            Ok(VAddr::from(base))
+        } else {
+            Err(SystemCallError::from(err))
+        }
+    }
+
     pub fn identify(base: u64) -> Result<(VAddr, PAddr), SystemCallError> {
         unsafe { VSpace::vspace(VSpaceOperation::Identify, base, 0) }
     }
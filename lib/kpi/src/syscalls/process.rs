@@ -34,6 +34,25 @@ impl Process {
         }
     }
 
+    /// Forward the exception `vector` (see [`crate::upcall`]) to the process
+    /// as an upcall instead of terminating the process.
+    pub fn subscribe_event(vector: u64) -> Result<(), SystemCallError> {
+        let r = unsafe {
+            syscall!(
+                SystemCall::Process as u64,
+                ProcessOperation::SubscribeEvent as u64,
+                vector,
+                1
+            )
+        };
+
+        if r == 0 {
+            Ok(())
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
     /// Print `buffer` on the console.
     pub fn print(buffer: &str) -> Result<(), SystemCallError> {
         let r = unsafe {
@@ -101,6 +120,79 @@ impl Process {
         }
     }
 
+    /// Start the boot module `binary` as a new process on `core_id`.
+    ///
+    /// The new process finds `cmdline` and `app_cmdline` in its
+    /// [`ProcessInfo`]. Returns the pid of the new process.
+    pub fn spawn(
+        binary: &str,
+        cmdline: &str,
+        app_cmdline: &str,
+        core_id: usize,
+    ) -> Result<u64, SystemCallError> {
+        // The kernel expects NUL-terminated strings, empty arguments are
+        // passed as a null pointer
+        let binary = alloc::format!("{}\0", binary);
+        let cmdline = alloc::format!("{}\0", cmdline);
+        let app_cmdline = alloc::format!("{}\0", app_cmdline);
+        let arg_ptr = |arg: &str| {
+            if arg.len() > 1 {
+                arg.as_ptr() as u64
+            } else {
+                0
+            }
+        };
+
+        let (r, pid) = unsafe {
+            syscall!(
+                SystemCall::Process as u64,
+                ProcessOperation::Spawn as u64,
+                binary.as_ptr() as u64,
+                arg_ptr(&cmdline),
+                arg_ptr(&app_cmdline),
+                core_id as u64,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(pid)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
+    /// Wait until the child process `pid` exited and return its exit code.
+    ///
+    /// A child can only be waited for once, afterwards its pid can be reused.
+    pub fn wait(pid: u64) -> Result<u64, SystemCallError> {
+        Process::wait_with(pid, false)
+    }
+
+    /// Return the exit code of the child process `pid`; fails with
+    /// `WouldBlock` if the child is still running.
+    pub fn try_wait(pid: u64) -> Result<u64, SystemCallError> {
+        Process::wait_with(pid, true)
+    }
+
+    fn wait_with(pid: u64, nonblock: bool) -> Result<u64, SystemCallError> {
+        let (r, exit_code) = unsafe {
+            syscall!(
+                SystemCall::Process as u64,
+                ProcessOperation::Wait as u64,
+                pid,
+                nonblock as u64,
+                2
+            )
+        };
+
+        if r == 0 {
+            Ok(exit_code)
+        } else {
+            Err(SystemCallError::from(r))
+        }
+    }
+
     /// Exit the process (pass an error `code` to exit).
     pub fn exit(code: u64) -> ! {
         unsafe {
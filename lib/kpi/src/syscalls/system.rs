@@ -8,7 +8,7 @@ use alloc::vec::Vec;
 
 use crate::{syscall, *};
 
-use crate::system::{CoreId, CpuThread};
+use crate::system::{CoreId, CpuThread, SystemStats};
 
 pub struct System;
 
@@ -37,12 +37,21 @@ impl System {
         }
     }
 
-    /// Prints some stats for the core.
-    pub fn stats() -> Result<(), SystemCallError> {
-        let r = unsafe { syscall!(SystemCall::System as u64, SystemOperation::Stats as u64, 1) };
+    /// Prints some stats for the core and gets the log configuration of the
+    /// system.
+    pub fn stats() -> Result<SystemStats, SystemCallError> {
+        let mut stats = SystemStats::default();
+        let r = unsafe {
+            syscall!(
+                SystemCall::System as u64,
+                SystemOperation::Stats as u64,
+                &mut stats as *mut SystemStats as u64,
+                1
+            )
+        };
 
         if r == 0 {
-            Ok(())
+            Ok(stats)
         } else {
             Err(SystemCallError::from(r))
         }
@@ -20,6 +20,20 @@ pub type PackageId = usize;
 /// Affinity region, a NUMA node (consists of a bunch of threads/core/packages and memory regions).
 pub type NodeId = usize;
 
+/// Struct used in the `stats` system call.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct SystemStats {
+    /// Cycles the core spent in the TLB shootdown IRQ handler.
+    pub irq_handler_time: u64,
+    /// Number of logs of the replicated file-system.
+    pub fs_logs: u64,
+    /// Size (in bytes) of each file-system log.
+    pub fs_log_size: u64,
+    /// Size (in bytes) of each process log.
+    pub process_log_size: u64,
+}
+
 #[derive(Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub struct CpuThread {
     /// ID the thread, global within a system.
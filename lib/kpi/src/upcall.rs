@@ -4,3 +4,18 @@
 //! Upcall command passed as the 2nd argument to the upcall.
 
 pub const NEW_CORE: u64 = 0x99;
+
+// Exceptions delivered to a process, with the vector as command and the error
+// code of the exception as argument. A process has to subscribe to all of them
+// except `BREAKPOINT`:
+
+/// Divide error.
+pub const DIVIDE_ERROR: u64 = 0;
+/// Debug trap.
+pub const DEBUG: u64 = 1;
+/// Breakpoint (`int3`), always delivered to the process (can't be subscribed to).
+pub const BREAKPOINT: u64 = 3;
+/// General protection fault.
+pub const GENERAL_PROTECTION_FAULT: u64 = 13;
+/// Page-fault, the faulting address is in `VirtualCpu::fault_address`.
+pub const PAGE_FAULT: u64 = 14;
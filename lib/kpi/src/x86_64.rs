@@ -31,6 +31,8 @@ pub struct VirtualCpu {
     pub is_disabled: bool,
     /// An upcall needs to be executed.
     pub has_pending_upcall: bool,
+    /// Address that caused the page-fault when upcalled for one.
+    pub fault_address: VAddr,
 }
 
 impl VirtualCpu {
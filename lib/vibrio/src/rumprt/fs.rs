@@ -7,7 +7,7 @@ use super::{c_int, c_size_t, c_void, rump_biodone_fn};
 use cstr_core::CStr;
 
 use kpi::io::*;
-use kpi::FileOperation;
+use kpi::{FileOperation, SystemCallError};
 
 use bitflags::*;
 use log::*;
@@ -50,7 +50,7 @@ pub unsafe extern "C" fn rumpuser_open(name: *const i8, mode: c_int, fdp: *mut c
         flags = flags | FileFlags::O_CREAT;
     }
     if ((mode_mode & RumpFileFlags::RUMPUSER_OPEN_EXCL) == RumpFileFlags::RUMPUSER_OPEN_EXCL) {
-        error!("NRK does not support O_EXCL\n");
+        flags = flags | FileFlags::O_EXCL;
     }
 
     // Rump documentation says the 'hypervisor' sets the permissions of all opened files.
@@ -64,6 +64,7 @@ pub unsafe extern "C" fn rumpuser_open(name: *const i8, mode: c_int, fdp: *mut c
             *fdp = fd as c_int;
             0
         }
+        Err(SystemCallError::AlreadyExists) => super::errno::EEXIST as c_int,
         Err(_) => super::errno::EINVAL as c_int,
     }
 }
@@ -108,6 +109,12 @@ pub unsafe extern "C" fn rumpuser_bio(
     unimplemented!("rumpuser_bio");
 }
 
+/// View an array of `rumpuser_iovec` as the `IoVec` array the kernel expects;
+/// both have the layout of a C `struct iovec`.
+unsafe fn iovecs<'a>(ruiov: *const rumpuser_iovec, iovlen: c_size_t) -> &'a [IoVec] {
+    core::slice::from_raw_parts(ruiov as *const IoVec, iovlen as usize)
+}
+
 /// int rumpuser_iovread(int fd, struct rumpuser_iovec *ruiov, size_t iovlen, int64_t off, size_t *retv)
 #[no_mangle]
 pub unsafe extern "C" fn rumpuser_iovread(
@@ -117,12 +124,7 @@ pub unsafe extern "C" fn rumpuser_iovread(
     off: i64,
     retv: *mut c_size_t,
 ) -> c_int {
-    match Fs::read_at(
-        fd as u64,
-        (*ruiov).iov_base as u64,
-        (*ruiov).iov_len as u64,
-        off,
-    ) {
+    match Fs::readv_at(fd as u64, iovecs(ruiov, iovlen), off) {
         Ok(len) => {
             *retv = len.try_into().unwrap();
             0
@@ -140,12 +142,7 @@ pub unsafe extern "C" fn rumpuser_iovwrite(
     off: i64,
     retv: *mut c_size_t,
 ) -> c_int {
-    match Fs::write_at(
-        fd as u64,
-        (*ruiov).iov_base as u64,
-        (*ruiov).iov_len as u64,
-        off,
-    ) {
+    match Fs::writev_at(fd as u64, iovecs(ruiov, iovlen), off) {
         Ok(len) => {
             *retv = len.try_into().unwrap();
             0
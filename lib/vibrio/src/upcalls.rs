@@ -19,6 +19,22 @@ use log::trace;
 
 pub static CORES_ONLINE: AtomicUsize = AtomicUsize::new(1);
 
+/// Handles an exception the process subscribed to (see
+/// [`kpi::syscalls::Process::subscribe_event`]), it gets the vector and the
+/// error code of the exception.
+///
+/// The process resumes from `enabled_state` of the [kpi::arch::VirtualCpu]
+/// once the handler returns (so for faults, the handler either fixes the
+/// cause or changes the state).
+pub type ExceptionHandler = fn(control: &mut kpi::arch::VirtualCpu, vector: u64, error: u64);
+
+static EXCEPTION_HANDLER: spin::Once<ExceptionHandler> = spin::Once::new();
+
+/// Install the handler for exceptions (this can only be done once).
+pub fn set_exception_handler(handler: ExceptionHandler) {
+    EXCEPTION_HANDLER.call_once(|| handler);
+}
+
 lazy_static! {
     pub static ref PROCESS_SCHEDULER: lineup::scheduler::SmpScheduler<'static> = {
         #[cfg(feature = "rumprt")]
@@ -78,7 +94,18 @@ pub fn upcall_while_enabled(control: &mut kpi::arch::VirtualCpu, cmd: u64, arg:
         }
     }
 
-    if cmd == 0x2a || cmd == 0x24 {
+    use kpi::upcall::{BREAKPOINT, DEBUG, DIVIDE_ERROR, GENERAL_PROTECTION_FAULT, PAGE_FAULT};
+    let is_fault = cmd == DIVIDE_ERROR || cmd == GENERAL_PROTECTION_FAULT || cmd == PAGE_FAULT;
+    let is_trap = cmd == DEBUG || cmd == BREAKPOINT;
+
+    if is_fault || is_trap {
+        match EXCEPTION_HANDLER.r#try() {
+            Some(handler) => handler(control, cmd, arg),
+            // Traps resume after the instruction that caused them
+            None if is_trap => log::info!("got trap cmd={} arg={}", cmd, arg),
+            None => panic!("No handler for exception {} (error {:#x})", cmd, arg),
+        }
+    } else if cmd == 0x2a || cmd == 0x24 {
         // TODO(correctness): this will use `gs` to access the SchedulerControlBlock
         // that assumes that we have already called scheduler.run() and we preserve
         // the SchedulerControlBlock register even if we return from run()
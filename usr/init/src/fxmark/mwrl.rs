@@ -17,6 +17,13 @@ impl Bench for MWRL {
     fn init(&self, cores: Vec<usize>, _open_files: usize) {
         unsafe {
             for core in cores {
+                // The directory might already exist from an earlier run.
+                let dir_name = format!("/{}\0", core);
+                let _r = vibrio::syscalls::Fs::mkdir_simple(
+                    dir_name.as_ptr() as u64,
+                    u64::from(FileModes::S_IRWXU),
+                );
+
                 let file_name = format!("/{}/file-0.txt\0", core);
                 let fd = vibrio::syscalls::Fs::open(
                     file_name.as_ptr() as u64,
@@ -522,6 +522,13 @@ fn fs_test() {
             .expect("FileOpen syscall failed");
         assert_eq!(fileinfo.fsize, 256);
         assert_eq!(fileinfo.ftype, FileType::File.into());
+        assert_eq!(fileinfo.fmode, u64::from(FileModes::S_IRWXU));
+        assert_eq!(fileinfo.nlink, 1);
+        assert!(fileinfo.mtime >= fileinfo.ctime);
+
+        // The same information is available through the file descriptor.
+        let fdinfo = vibrio::syscalls::Fs::fstat(fd).expect("FStat syscall failed");
+        assert_eq!(fdinfo, fileinfo);
 
         // Reset the slice content. And read the file content from the file and
         // check if it's same as the date which was written to the file.
@@ -534,6 +541,47 @@ fn fs_test() {
         assert_eq!(slice[255], 0xb);
         assert_eq!(slice[256], 0);
 
+        // Move the offset around and read the tail of the file again.
+        let ret = vibrio::syscalls::Fs::seek(fd, 0, SeekWhence::End).expect("Seek syscall failed");
+        assert_eq!(ret, 256);
+        let ret =
+            vibrio::syscalls::Fs::seek(fd, -56, SeekWhence::Current).expect("Seek syscall failed");
+        assert_eq!(ret, 200);
+        let ret = vibrio::syscalls::Fs::read(fd, slice.as_ptr() as u64, 256)
+            .expect("FileRead syscall failed");
+        assert_eq!(ret, 56);
+        let _ret = vibrio::syscalls::Fs::seek(fd, -1, SeekWhence::Set)
+            .expect_err("Seek to a negative offset should fail");
+        let ret = vibrio::syscalls::Fs::seek(fd, 0, SeekWhence::Set).expect("Seek syscall failed");
+        assert_eq!(ret, 0);
+
+        // A duplicated fd shares the offset with the original one.
+        let dupfd = vibrio::syscalls::Fs::dup(fd).expect("Dup syscall failed");
+        assert_ne!(dupfd, fd);
+        let ret =
+            vibrio::syscalls::Fs::seek(dupfd, 100, SeekWhence::Set).expect("Seek syscall failed");
+        assert_eq!(ret, 100);
+        let ret =
+            vibrio::syscalls::Fs::seek(fd, 0, SeekWhence::Current).expect("Seek syscall failed");
+        assert_eq!(ret, 100);
+        let ret = vibrio::syscalls::Fs::dup2(fd, dupfd).expect("Dup2 syscall failed");
+        assert_eq!(ret, dupfd);
+        let ret = vibrio::syscalls::Fs::close(dupfd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+        let ret = vibrio::syscalls::Fs::seek(fd, 0, SeekWhence::Set).expect("Seek syscall failed");
+        assert_eq!(ret, 0);
+
+        // Shrink the file and extend it again, the new part reads as zeros.
+        let ret = vibrio::syscalls::Fs::truncate(fd, 128).expect("Truncate syscall failed");
+        assert_eq!(ret, 0);
+        let ret = vibrio::syscalls::Fs::truncate(fd, 256).expect("Truncate syscall failed");
+        assert_eq!(ret, 0);
+        let ret = vibrio::syscalls::Fs::read_at(fd, slice.as_ptr() as u64, 256, 0)
+            .expect("FileReadAt syscall failed");
+        assert_eq!(ret, 256);
+        assert_eq!(slice[127], 0xb);
+        assert_eq!(slice[128], 0);
+
         // This call is to tests nrk memory deallocator for large allocations.
         let ret = vibrio::syscalls::Fs::write_at(fd, slice.as_ptr() as u64, 256, 4096 * 255)
             .expect("FileWriteAt syscall failed");
@@ -542,6 +590,50 @@ fn fs_test() {
         let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
         assert_eq!(ret, 0);
 
+        // Exclusive create of an existing file fails.
+        let _ret = vibrio::syscalls::Fs::open(
+            "file.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDWR | FileFlags::O_CREAT | FileFlags::O_EXCL),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect_err("O_EXCL open of an existing file should fail");
+
+        // Writes to an append-mode fd land at the end of the file.
+        let fd = vibrio::syscalls::Fs::open(
+            "file.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_WRONLY | FileFlags::O_APPEND),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        let ret = vibrio::syscalls::Fs::write(fd, slice.as_ptr() as u64, 16)
+            .expect("FileWrite syscall failed");
+        assert_eq!(ret, 16);
+        let fileinfo = vibrio::syscalls::Fs::getinfo("file.txt\0".as_ptr() as u64)
+            .expect("FileGetInfo syscall failed");
+        assert_eq!(fileinfo.fsize, 4096 * 255 + 256 + 16);
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+
+        // Map the file, the mapping sees later writes to the file.
+        let fd = vibrio::syscalls::Fs::open(
+            "file.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDWR),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        let map_base: u64 = 0x5_0000_0000;
+        vibrio::syscalls::VSpace::map_file(map_base, fd, 0, 0x1000 * 2, FileModes::S_IRUSR)
+            .expect("MapFile syscall failed");
+        let mapping: &[u8] = core::slice::from_raw_parts(map_base as *const u8, 0x1000 * 2);
+        assert_eq!(mapping[0], 0xb);
+        slice[0] = 0xc;
+        let ret = vibrio::syscalls::Fs::write_at(fd, slice.as_ptr() as u64, 1, 0)
+            .expect("FileWriteAt syscall failed");
+        assert_eq!(ret, 1);
+        assert_eq!(mapping[0], 0xc);
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+
         // Rename the file
         let ret = vibrio::syscalls::Fs::rename(
             "file.txt\0".as_ptr() as u64,
@@ -555,6 +647,230 @@ fn fs_test() {
             .expect("FileDelete syscall failed");
         assert_eq!(ret, true);
 
+        // Create a file in a new directory and list the directory.
+        let ret = vibrio::syscalls::Fs::mkdir_simple(
+            "/dir\0".as_ptr() as u64,
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("MkDir syscall failed");
+        assert_eq!(ret, 0);
+        let fd = vibrio::syscalls::Fs::open(
+            "/dir/file.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDWR | FileFlags::O_CREAT),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+
+        let mut buf = alloc::vec::Vec::new();
+        let entries = vibrio::syscalls::Fs::readdir("/dir\0".as_ptr() as u64, &mut buf)
+            .expect("ReadDir syscall failed");
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "file.txt");
+        assert_eq!(entries[0].ftype, FileType::File.into());
+
+        let ret = vibrio::syscalls::Fs::delete("/dir\0".as_ptr() as u64)
+            .expect_err("Non-empty directory can't be deleted");
+
+        // Relative paths are resolved from the working directory.
+        vibrio::syscalls::Fs::chdir("/dir\0".as_ptr() as u64).expect("Chdir syscall failed");
+        let cwd = vibrio::syscalls::Fs::getcwd().expect("Getcwd syscall failed");
+        assert_eq!(cwd, "/dir");
+        let fileinfo = vibrio::syscalls::Fs::getinfo("./file.txt\0".as_ptr() as u64)
+            .expect("FileGetInfo syscall failed");
+        assert_eq!(fileinfo.ftype, FileType::File.into());
+        vibrio::syscalls::Fs::chdir("..//dir/../\0".as_ptr() as u64).expect("Chdir syscall failed");
+        let cwd = vibrio::syscalls::Fs::getcwd().expect("Getcwd syscall failed");
+        assert_eq!(cwd, "/");
+
+        // The root directory can be opened and inspected like any directory.
+        let fd = vibrio::syscalls::Fs::open(
+            "/\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDONLY),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        let fdinfo = vibrio::syscalls::Fs::fstat(fd).expect("FStat syscall failed");
+        assert_eq!(fdinfo.ftype, FileType::Directory.into());
+        let fileinfo = vibrio::syscalls::Fs::getinfo("/\0".as_ptr() as u64)
+            .expect("FileGetInfo syscall failed");
+        assert_eq!(fileinfo, fdinfo);
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+
+        let _ret = vibrio::syscalls::Fs::chdir("/dir/file.txt\0".as_ptr() as u64)
+            .expect_err("Chdir to a file should fail");
+
+        // Hard links share the file, symbolic links are followed on lookup.
+        vibrio::syscalls::Fs::link(
+            "/dir/file.txt\0".as_ptr() as u64,
+            "/hardlink.txt\0".as_ptr() as u64,
+        )
+        .expect("Link syscall failed");
+        let fileinfo = vibrio::syscalls::Fs::getinfo("/hardlink.txt\0".as_ptr() as u64)
+            .expect("FileGetInfo syscall failed");
+        assert_eq!(fileinfo.nlink, 2);
+        vibrio::syscalls::Fs::symlink(
+            "dir/file.txt\0".as_ptr() as u64,
+            "/symlink.txt\0".as_ptr() as u64,
+        )
+        .expect("Symlink syscall failed");
+        let linkinfo = vibrio::syscalls::Fs::getinfo("/symlink.txt\0".as_ptr() as u64)
+            .expect("FileGetInfo syscall failed");
+        assert_eq!(linkinfo.mnode, fileinfo.mnode);
+        let target = vibrio::syscalls::Fs::readlink("/symlink.txt\0".as_ptr() as u64)
+            .expect("ReadLink syscall failed");
+        assert_eq!(target, "dir/file.txt");
+        let _ret = vibrio::syscalls::Fs::delete("/symlink.txt\0".as_ptr() as u64)
+            .expect("FileDelete syscall failed");
+        let _ret = vibrio::syscalls::Fs::delete("/hardlink.txt\0".as_ptr() as u64)
+            .expect("FileDelete syscall failed");
+
+        // A deleted file stays usable until it is closed.
+        let fd = vibrio::syscalls::Fs::open(
+            "/unlinked.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDWR | FileFlags::O_CREAT),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        let ret = vibrio::syscalls::Fs::delete("/unlinked.txt\0".as_ptr() as u64)
+            .expect("FileDelete syscall failed");
+        assert_eq!(ret, true);
+        let _ret = vibrio::syscalls::Fs::getinfo("/unlinked.txt\0".as_ptr() as u64)
+            .expect_err("Deleted file shouldn't be found");
+        let ret = vibrio::syscalls::Fs::write_at(fd, slice.as_ptr() as u64, 16, 0)
+            .expect("FileWriteAt syscall failed");
+        assert_eq!(ret, 16);
+        let mut rbuf: [u8; 16] = [0; 16];
+        let ret = vibrio::syscalls::Fs::read_at(fd, rbuf.as_mut_ptr() as u64, 16, 0)
+            .expect("FileReadAt syscall failed");
+        assert_eq!(ret, 16);
+        assert_eq!(&rbuf[..], &slice[..16]);
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+
+        // Data written to a pipe comes out at the other end.
+        let (readfd, writefd) = vibrio::syscalls::Fs::pipe(u64::from(FileFlags::O_NONBLOCK))
+            .expect("Pipe syscall failed");
+        let _err = vibrio::syscalls::Fs::read(readfd, rbuf.as_mut_ptr() as u64, 16)
+            .expect_err("Reading an empty, non-blocking pipe should fail");
+        let ret = vibrio::syscalls::Fs::write(writefd, slice.as_ptr() as u64, 16)
+            .expect("FileWrite syscall failed");
+        assert_eq!(ret, 16);
+        let _err = vibrio::syscalls::Fs::read(writefd, rbuf.as_mut_ptr() as u64, 16)
+            .expect_err("Reading the write end should fail");
+        vibrio::syscalls::Fs::close(writefd).expect("FileClose syscall failed");
+        let ret = vibrio::syscalls::Fs::read(readfd, rbuf.as_mut_ptr() as u64, 16)
+            .expect("FileRead syscall failed");
+        assert_eq!(ret, 16);
+        // All write ends are closed, so this is the end of the pipe.
+        let ret = vibrio::syscalls::Fs::read(readfd, rbuf.as_mut_ptr() as u64, 16)
+            .expect("FileRead syscall failed");
+        assert_eq!(ret, 0);
+        vibrio::syscalls::Fs::close(readfd).expect("FileClose syscall failed");
+
+        // The file-system reports its size and how it is replicated.
+        let stats = vibrio::syscalls::Fs::statfs().expect("StatFs syscall failed");
+        assert!(stats.mnodes > 1);
+        assert!(stats.used > 0);
+        assert!(stats.logs > 0 && stats.replicas > 0);
+        let system = vibrio::syscalls::System::stats().expect("Stats syscall failed");
+        assert_eq!(system.fs_logs, stats.logs);
+        assert!(system.fs_log_size > 0 && system.process_log_size > 0);
+
+        // A writev is one write of all buffers, readv splits the data again.
+        let fd = vibrio::syscalls::Fs::open(
+            "/vectored.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDWR | FileFlags::O_CREAT),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        let iov = [
+            IoVec {
+                base: slice.as_ptr() as u64,
+                len: 8,
+            },
+            IoVec {
+                base: slice.as_ptr() as u64 + 16,
+                len: 8,
+            },
+        ];
+        let ret = vibrio::syscalls::Fs::writev(fd, &iov).expect("WriteV syscall failed");
+        assert_eq!(ret, 16);
+        let mut rbuf1: [u8; 4] = [0; 4];
+        let mut rbuf2: [u8; 16] = [0; 16];
+        let iov = [
+            IoVec {
+                base: rbuf1.as_mut_ptr() as u64,
+                len: 4,
+            },
+            IoVec {
+                base: rbuf2.as_mut_ptr() as u64,
+                len: 16,
+            },
+        ];
+        // The file only has 16 bytes, so the second buffer is filled partially.
+        let ret = vibrio::syscalls::Fs::readv_at(fd, &iov, 0).expect("ReadV syscall failed");
+        assert_eq!(ret, 16);
+        assert_eq!(&rbuf1[..], &slice[..4]);
+        assert_eq!(&rbuf2[..4], &slice[4..8]);
+        assert_eq!(&rbuf2[4..12], &slice[16..24]);
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+        let _ret = vibrio::syscalls::Fs::delete("/vectored.txt\0".as_ptr() as u64)
+            .expect("FileDelete syscall failed");
+
+        // Advisory locks; a process never conflicts with its own locks.
+        let fd = vibrio::syscalls::Fs::open(
+            "/locked.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDONLY | FileFlags::O_CREAT),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        vibrio::syscalls::Fs::lock(fd, LockFlags::LOCK_SH | LockFlags::LOCK_NB, 0, 0)
+            .expect("Lock syscall failed");
+        let _err = vibrio::syscalls::Fs::lock(fd, LockFlags::LOCK_EX, 0, 16)
+            .expect_err("Exclusive lock on a read-only fd should fail");
+        let _err = vibrio::syscalls::Fs::lock(fd, LockFlags::LOCK_SH | LockFlags::LOCK_EX, 0, 16)
+            .expect_err("Lock should be either shared or exclusive");
+        vibrio::syscalls::Fs::unlock(fd, 0, 16).expect("Unlock syscall failed");
+        let ret = vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        assert_eq!(ret, 0);
+        let _ret = vibrio::syscalls::Fs::delete("/locked.txt\0".as_ptr() as u64)
+            .expect("FileDelete syscall failed");
+
+        // A watch on a directory reports the files created in it.
+        let wfd = vibrio::syscalls::Fs::watch_init(u64::from(FileFlags::O_NONBLOCK))
+            .expect("WatchInit syscall failed");
+        let wd = vibrio::syscalls::Fs::watch_add(
+            wfd,
+            "/dir\0".as_ptr() as u64,
+            WatchEvents::IN_CREATE | WatchEvents::IN_DELETE,
+        )
+        .expect("WatchAdd syscall failed");
+        let fd = vibrio::syscalls::Fs::open(
+            "/dir/watched.txt\0".as_ptr() as u64,
+            u64::from(FileFlags::O_RDWR | FileFlags::O_CREAT),
+            u64::from(FileModes::S_IRWXU),
+        )
+        .expect("FileOpen syscall failed");
+        vibrio::syscalls::Fs::close(fd).expect("FileClose syscall failed");
+        let mut events = [WatchEvent::default(); 4];
+        let ret =
+            vibrio::syscalls::Fs::read_events(wfd, &mut events).expect("Reading events failed");
+        assert_eq!(ret, 1);
+        assert_eq!(events[0].wd, wd);
+        assert_eq!(events[0].mask, WatchEvents::IN_CREATE.bits());
+        let _err = vibrio::syscalls::Fs::read_events(wfd, &mut events)
+            .expect_err("Reading an empty, non-blocking event queue should fail");
+        vibrio::syscalls::Fs::watch_remove(wfd, wd).expect("WatchRemove syscall failed");
+        let _ret = vibrio::syscalls::Fs::delete("/dir/watched.txt\0".as_ptr() as u64)
+            .expect("FileDelete syscall failed");
+        let _err = vibrio::syscalls::Fs::read_events(wfd, &mut events)
+            .expect_err("A removed watch shouldn't report events");
+        vibrio::syscalls::Fs::close(wfd).expect("FileClose syscall failed");
+
         // Test fs with invalid userspace pointers
         test_fs_invalid_addresses();
     }
@@ -562,6 +878,71 @@ fn fs_test() {
     info!("fs_test OK");
 }
 
+/// Opens a file on one replica of the file-system and writes to it from a
+/// core on another NUMA node, so through another replica.
+#[cfg(feature = "test-fs-replicas")]
+fn fs_replica_test() {
+    use lineup::tls2::Environment;
+    use vibrio::io::*;
+    use vibrio::syscalls::Fs;
+
+    unsafe extern "C" fn remote_write(arg: *mut u8) -> *mut u8 {
+        let fd = arg as u64;
+        let data = [0xa_u8; 64];
+        let ret = Fs::write_at(fd, data.as_ptr() as u64, 64, 0).expect("FileWrite syscall failed");
+        assert_eq!(ret, 64);
+        ptr::null_mut()
+    }
+
+    let threads = vibrio::syscalls::System::threads().expect("Can't get system topology");
+    let remote = threads
+        .iter()
+        .find(|thread| thread.node_id != threads[0].node_id)
+        .expect("Need a second NUMA node")
+        .id;
+    vibrio::syscalls::Process::request_core(
+        remote,
+        VAddr::from(vibrio::upcalls::upcall_while_enabled as *const fn() as u64),
+    )
+    .expect("Can't spawn core on the second NUMA node");
+
+    let fd = Fs::open(
+        "/replicas.txt\0".as_ptr() as u64,
+        u64::from(FileFlags::O_RDWR | FileFlags::O_CREAT),
+        u64::from(FileModes::S_IRWXU),
+    )
+    .expect("FileOpen syscall failed");
+
+    let s = &vibrio::upcalls::PROCESS_SCHEDULER;
+    s.spawn(
+        32 * 4096,
+        move |_| {
+            let thandle = Environment::thread()
+                .spawn_on_core(Some(remote_write), fd as *mut u8, remote)
+                .expect("Can't spawn thread on the second NUMA node");
+            Environment::thread().join(thandle);
+
+            // The replica that opened the file sees the write.
+            let mut data = [0_u8; 64];
+            let ret =
+                Fs::read_at(fd, data.as_mut_ptr() as u64, 64, 0).expect("FileRead syscall failed");
+            assert_eq!(ret, 64);
+            assert_eq!(data, [0xa_u8; 64]);
+        },
+        ptr::null_mut(),
+        0,
+        None,
+    );
+
+    let scb: SchedulerControlBlock = SchedulerControlBlock::new(0);
+    while s.has_active_threads() {
+        s.run(&scb);
+    }
+
+    Fs::close(fd).expect("FileClose syscall failed");
+    info!("fs_replica_test OK");
+}
+
 fn fs_write_test() {
     use vibrio::syscalls::Fs;
 
@@ -593,6 +974,39 @@ fn fs_write_test() {
     info!("fs_write Ok");
 }
 
+/// Command line of the child that `spawn_test` starts.
+#[cfg(feature = "test-spawn")]
+const SPAWN_TEST_CHILD: &str = "spawn-child";
+
+/// Exit code of the child that `spawn_test` starts.
+#[cfg(feature = "test-spawn")]
+const SPAWN_TEST_EXIT_CODE: u64 = 7;
+
+/// Command line of the child that `spawn_test` starts to crash.
+#[cfg(feature = "test-spawn")]
+const SPAWN_TEST_FAULTING_CHILD: &str = "spawn-faulting-child";
+
+/// Starts another instance of init (on core 1) and collects its exit code.
+#[cfg(feature = "test-spawn")]
+fn spawn_test() {
+    use vibrio::syscalls::Process;
+
+    let pid = Process::spawn("init", SPAWN_TEST_CHILD, "", 1).expect("Can't spawn");
+    info!("spawned process {}", pid);
+
+    let exit_code = Process::wait(pid).expect("Can't wait for child");
+    assert_eq!(exit_code, SPAWN_TEST_EXIT_CODE);
+    // The child is gone after the first wait
+    assert!(Process::try_wait(pid).is_err());
+
+    // A page-fault only kills the child
+    let pid = Process::spawn("init", SPAWN_TEST_FAULTING_CHILD, "", 1).expect("Can't spawn");
+    let exit_code = Process::wait(pid).expect("Can't wait for child");
+    assert_ne!(exit_code, 0);
+
+    info!("spawn_test OK");
+}
+
 pub fn install_vcpu_area() {
     let ctl =
         vibrio::syscalls::Process::vcpu_control_area().expect("Can't read vcpu control area.");
@@ -600,9 +1014,30 @@ pub fn install_vcpu_area() {
         VAddr::from(vibrio::upcalls::upcall_while_enabled as *const fn() as u64);
 }
 
+/// Maps the page that caused a page-fault upcall.
+fn map_faulting_page(control: &mut vibrio::arch::VirtualCpu, vector: u64, _error: u64) {
+    if vector == vibrio::upcall::PAGE_FAULT {
+        let page = control.fault_address.as_u64() & !0xfff;
+        unsafe {
+            vibrio::syscalls::VSpace::map(page, 0x1000).expect("Map syscall failed");
+        }
+    }
+}
+
 pub fn upcall_test() {
     sys_println!("causing a debug exception");
     unsafe { x86::int!(3) };
+
+    // Page-faults are resolved by the process itself
+    vibrio::upcalls::set_exception_handler(map_faulting_page);
+    vibrio::syscalls::Process::subscribe_event(vibrio::upcall::PAGE_FAULT)
+        .expect("Can't subscribe to page-faults");
+    let unmapped: *mut u64 = 0x6_0000_0000 as *mut u64;
+    unsafe {
+        ptr::write_volatile(unmapped, 0xdead);
+        assert_eq!(ptr::read_volatile(unmapped), 0xdead);
+    }
+
     info!("upcall_test OK");
 }
 
@@ -618,6 +1053,15 @@ pub extern "C" fn _start() -> ! {
     install_vcpu_area();
 
     let pinfo = vibrio::syscalls::Process::process_info().expect("Can't read process info");
+    #[cfg(feature = "test-spawn")]
+    if pinfo.cmdline == SPAWN_TEST_CHILD {
+        vibrio::syscalls::Process::exit(SPAWN_TEST_EXIT_CODE);
+    }
+    #[cfg(feature = "test-spawn")]
+    if pinfo.cmdline == SPAWN_TEST_FAULTING_CHILD {
+        unsafe { ptr::read_volatile(ptr::null::<u64>()) };
+    }
+
     #[cfg(not(feature = "fxmark"))]
     let ncores: Option<usize> = pinfo.cmdline.parse().ok();
 
@@ -671,6 +1115,12 @@ pub extern "C" fn _start() -> ! {
     #[cfg(feature = "test-fs")]
     fs_test();
 
+    #[cfg(feature = "test-fs-replicas")]
+    fs_replica_test();
+
+    #[cfg(feature = "test-spawn")]
+    spawn_test();
+
     #[cfg(feature = "fs-write")]
     fs_write_test();
 